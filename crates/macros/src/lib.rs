@@ -1,92 +1,88 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 
-use syn::{parse_macro_input, DeriveInput, Data, Fields, Meta::List, NestedMeta::Lit, Lit::{Int, Bool}};
-use quote::quote;
-use proc_macro2::Ident;
+use syn::{parse_macro_input, Attribute, DeriveInput, Data, Error, Expr, Field, Fields, Lit, LitInt, Member, Result, Token, Type};
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use quote::{format_ident, quote, ToTokens};
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2, TokenTree};
 
 // https://doc.rust-lang.org/reference/procedural-macros.html#derive-mode-macros
-// TODO please improve me
+
+/// Implements `utils::Packet`, the packet is described by `#[packet(id = 0x27, state = Play, direction = Clientbound)]`.
+///
+/// Structs send their fields in order. Enums send a VarInt tag followed by the fields of the variant,
+/// the tag of a variant is set with `#[packet(tag = 3)]` and counts up from the previous variant otherwise.
+///
+/// Fields accept `#[packet(prefix = Short)]` to choose the length prefix of an array, and
+/// `#[packet(if = "action.val == 0")]` for `Option` fields that are only sent when the condition
+/// on the earlier fields holds. Conditions refer to the fields of tuple structs as `_0`, `_1` and so on.
+/// Writing fails with `EncodeError::InvalidValue` when such a field is not set exactly when its condition holds.
 #[proc_macro_derive(Packet, attributes(packet))]
 pub fn derive_packet(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let data = match input.data {
-        Data::Struct(d) => {
-           d
-        }
-        _ => panic!("usage of derive_packet in unsupported place")
-    };
-
-    let fields = match data.fields {
-        Fields::Named(f) => {
-            f
-        }
-        _ => panic!("usage of derive_packet in unsupported place")
-    }.named;
-
-    let mapped_fields : Vec<&Ident> = fields.iter().map(|field| field.ident.as_ref().unwrap()).collect();
-
-    let name = input.ident;
-    let mut packet_attr = None;
-
-    for attr in input.attrs.iter() {
-        if attr.path.is_ident("packet") {
-            packet_attr = Some(attr);
-        }
-    }
-
-    if packet_attr.is_none() {
-        panic!("no attr packet");
-    }
+    expand_packet(&input).unwrap_or_else(|error| error.to_compile_error()).into()
+}
 
-    let packet_attr = packet_attr.unwrap();
-
-    let meta = &packet_attr.parse_meta().expect("there is no meta");
+/// Implements `utils::sendable::Sendable` for the types packets are made of, they are encoded like packets.
+#[proc_macro_derive(Sendable, attributes(packet))]
+pub fn derive_sendable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_sendable(&input).unwrap_or_else(|error| error.to_compile_error()).into()
+}
 
-    let id;
-    let state;
-    let inbound;
+fn expand_packet(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let attr = input.attrs.iter().find(|attr| attr.path.is_ident("packet"))
+        .ok_or_else(|| Error::new(Span::call_site(), "missing #[packet(id = .., state = .., direction = ..)] attribute"))?;
 
-    match meta {
-        List(m) => {
-            match &m.nested[0] {
-                Lit(l) => {
-                    match l {
-                        Int(i) => {
-                            id = i;
-                        },
-                        _ => panic!("id isnt an int")
-                    }
-                }
-                _ => panic!("id is not a lit")
+    let mut id = None;
+    let mut state = None;
+    let mut inbound = None;
+    for arg in packet_args(&input.attrs)? {
+        match arg.key.to_string().as_str() {
+            "id" => set(&mut id, &arg, arg.int()?)?,
+            "state" => {
+                let value = arg.ident()?;
+                let constant = match value.to_string().as_str() {
+                    "Handshaking" => quote! { packets::HANDSHAKING_STATE },
+                    "Status" => quote! { packets::STATUS_STATE },
+                    "Login" => quote! { packets::LOGIN_STATE },
+                    "Play" => quote! { packets::PLAY_STATE },
+                    _ => return Err(Error::new(value.span(), "unknown state, expected Handshaking, Status, Login or Play"))
+                };
+                set(&mut state, &arg, constant)?
             }
-            state = &m.nested[1];
-            match &m.nested[2] {
-                Lit(l)=> {
-                    match l {
-                        Bool(i) => {
-                            inbound = i;
-                        },
-                        _ => panic!("inbound isnt a bool")
-                    }
-                }
-                _ => panic!("inbound is not a lit")
+            "direction" => {
+                let value = arg.ident()?;
+                let serverbound = match value.to_string().as_str() {
+                    "Serverbound" => true,
+                    "Clientbound" => false,
+                    _ => return Err(Error::new(value.span(), "unknown direction, expected Serverbound or Clientbound"))
+                };
+                set(&mut inbound, &arg, serverbound)?
             }
-        },
-        _ => panic!("invalid meta")
+            _ => return Err(Error::new(arg.key.span(), "unknown packet argument, expected id, state or direction"))
+        }
     }
+    let id = id.ok_or_else(|| Error::new_spanned(attr, "the packet has no id"))?;
+    let state = state.ok_or_else(|| Error::new_spanned(attr, "the packet has no state"))?;
+    let inbound = inbound.ok_or_else(|| Error::new_spanned(attr, "the packet has no direction"))?;
+
+    let (read, write) = codec(name, &input.data)?;
 
-    let tokens = quote! {
+    Ok(quote! {
         impl utils::Packet for #name {
-            fn read(mut buffer: &mut dyn bytes::Buf) -> Self where Self: Sized {
-                #( let #mapped_fields = utils::sendable::Sendable::read(buffer); )*
-                #name {
-                    #( #mapped_fields ),*
-                }
+            fn read(buffer: &mut dyn bytes::Buf) -> Result<Self, utils::error::DecodeError> where Self: Sized {
+                #read
             }
 
-            fn write(&self, mut buffer: &mut dyn bytes::BufMut) {
-                #( utils::sendable::Sendable::write(buffer, &self.#mapped_fields); )*
+            fn write(&self, buffer: &mut dyn bytes::BufMut) -> Result<(), utils::error::EncodeError> {
+                let data = self;
+                #write
+                Ok(())
             }
 
             fn get_id() -> i32 where Self: Sized {
@@ -105,7 +101,307 @@ pub fn derive_packet(input: TokenStream) -> TokenStream {
                 self
             }
         }
-    };
+    })
+}
+
+fn expand_sendable(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    if let Some(arg) = packet_args(&input.attrs)?.first() {
+        return Err(Error::new(arg.key.span(), "only packets have an id, state and direction, derive Packet instead"));
+    }
+
+    let (read, write) = codec(name, &input.data)?;
+
+    Ok(quote! {
+        impl utils::sendable::Sendable for #name {
+            fn read(buffer: &mut dyn bytes::Buf) -> Result<Self, utils::error::DecodeError> {
+                #read
+            }
+
+            fn write(buffer: &mut dyn bytes::BufMut, data: &Self) -> Result<(), utils::error::EncodeError> {
+                #write
+                Ok(())
+            }
+        }
+    })
+}
+
+/// The body of the read function, and of the write function which has the value in `data`.
+fn codec(name: &Ident, data: &Data) -> Result<(TokenStream2, TokenStream2)> {
+    match data {
+        Data::Struct(data) => {
+            let fields = CodecFields::new(&data.fields)?;
+            let (reads, writes, pattern) = (&fields.reads, &fields.writes, &fields.pattern);
+            let read = quote! {
+                #( #reads )*
+                Ok(#name { #pattern })
+            };
+            let write = quote! {
+                let #name { #pattern } = data;
+                #( #writes )*
+            };
+            Ok((read, write))
+        }
+        Data::Enum(data) => {
+            let mut next_tag = 0i32;
+            let mut tags: Vec<(i32, &Ident)> = Vec::new();
+            let mut read_arms = Vec::new();
+            let mut write_arms = Vec::new();
+            for variant in data.variants.iter() {
+                let mut tag = None;
+                for arg in packet_args(&variant.attrs)? {
+                    match arg.key.to_string().as_str() {
+                        "tag" => {
+                            let value = arg.int()?;
+                            set(&mut tag, &arg, value.base10_parse::<i32>()?)?
+                        }
+                        _ => return Err(Error::new(arg.key.span(), "unknown variant argument, expected tag"))
+                    }
+                }
+                let tag = tag.unwrap_or(next_tag);
+                next_tag = tag.wrapping_add(1);
+                if let Some((_, other)) = tags.iter().find(|(other_tag, _)| *other_tag == tag) {
+                    return Err(Error::new(variant.ident.span(), format!("tag {} is already used by {}", tag, other)));
+                }
+                tags.push((tag, &variant.ident));
+
+                let fields = CodecFields::new(&variant.fields)?;
+                let (reads, writes, pattern) = (&fields.reads, &fields.writes, &fields.pattern);
+                let variant = &variant.ident;
+                let tag = LitInt::new(&tag.to_string(), variant.span());
+                read_arms.push(quote! {
+                    #tag => {
+                        #( #reads )*
+                        Ok(#name::#variant { #pattern })
+                    }
+                });
+                write_arms.push(quote! {
+                    #name::#variant { #pattern } => {
+                        utils::sendable::Sendable::write(buffer, &utils::sendable::Vari32 { val: #tag })?;
+                        #( #writes )*
+                    }
+                });
+            }
+            let read = quote! {
+                let tag = <utils::sendable::Vari32 as utils::sendable::Sendable>::read(buffer)?.val;
+                match tag {
+                    #( #read_arms )*
+                    tag => Err(utils::error::DecodeError::InvalidValue(format!("unknown {} tag {}", stringify!(#name), tag)))
+                }
+            };
+            let write = quote! {
+                match data {
+                    #( #write_arms )*
+                }
+            };
+            Ok((read, write))
+        }
+        Data::Union(data) => Err(Error::new(data.union_token.span(), "unions can't be sent, use a struct or an enum"))
+    }
+}
+
+/// How the fields of a struct or variant are read and written, each field is bound to a variable
+/// named like the field, or `_0`, `_1` and so on for tuple fields.
+struct CodecFields {
+    /// Reads every field into its variable, so conditions can use the earlier fields.
+    reads: Vec<TokenStream2>,
+    /// Writes every field from its variable, which borrows the field.
+    writes: Vec<TokenStream2>,
+    /// Binds the variables in `Name { .. }`, also builds the value from them.
+    pattern: TokenStream2,
+}
+
+impl CodecFields {
+    fn new(fields: &Fields) -> Result<CodecFields> {
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+        let mut pattern = Vec::new();
+        let mut variables: Vec<Ident> = Vec::new();
+        for (index, field) in fields.iter().enumerate() {
+            let options = FieldOptions::of(field)?;
+            let variable = match &field.ident {
+                Some(ident) => {
+                    pattern.push(quote! { #ident });
+                    ident.clone()
+                }
+                None => {
+                    let variable = format_ident!("_{}", index);
+                    let member = Member::from(index);
+                    pattern.push(quote! { #member: #variable });
+                    variable
+                }
+            };
+
+            let ty = &field.ty;
+            let value = match &options.prefix {
+                Some(prefix) => quote! { utils::sendable::Prefixed::read_prefixed(buffer, #prefix)? },
+                None => quote! { utils::sendable::Sendable::read(buffer)? }
+            };
+            reads.push(match &options.condition {
+                Some((condition, _)) => quote! { let #variable: #ty = if #condition { Some(#value) } else { None }; },
+                None => quote! { let #variable: #ty = #value; }
+            });
+
+            let value = if options.condition.is_some() { quote! { value } } else { quote! { #variable } };
+            let write = match &options.prefix {
+                Some(prefix) => quote! { utils::sendable::Prefixed::write_prefixed(buffer, #value, #prefix)?; },
+                None => quote! { utils::sendable::Sendable::write(buffer, #value)?; }
+            };
+            writes.push(match &options.condition {
+                Some((condition, source)) => {
+                    // the fields are borrowed while writing, the condition gets the values it was read with
+                    let used = idents(condition.to_token_stream());
+                    let used: Vec<&Ident> = variables.iter().filter(|variable| used.contains(variable)).collect();
+                    let message = format!("{} has to be set exactly when `{}` holds", variable, source);
+                    quote! {
+                        let condition_holds = {
+                            #( let #used = ::std::clone::Clone::clone(#used); )*
+                            #condition
+                        };
+                        match #variable {
+                            Some(value) if condition_holds => {
+                                #write
+                            }
+                            None if !condition_holds => {}
+                            _ => return Err(utils::error::EncodeError::invalid_value(#message))
+                        }
+                    }
+                }
+                None => write
+            });
+            variables.push(variable);
+        }
+        Ok(CodecFields { reads, writes, pattern: quote! { #( #pattern ),* } })
+    }
+}
+
+/// The arguments of a field's `#[packet(..)]` attributes.
+struct FieldOptions {
+    /// The length prefix of an array, a VarInt if it isn't set.
+    prefix: Option<TokenStream2>,
+    /// The field is an `Option` that is only sent when this holds, with the condition as it was written.
+    condition: Option<(Expr, String)>,
+}
+
+impl FieldOptions {
+    fn of(field: &Field) -> Result<FieldOptions> {
+        let mut prefix = None;
+        let mut condition = None;
+        for arg in packet_args(&field.attrs)? {
+            match arg.key.to_string().as_str() {
+                "prefix" => {
+                    let value = arg.ident()?;
+                    match value.to_string().as_str() {
+                        "VarInt" | "Byte" | "Short" | "Int" | "None" => set(&mut prefix, &arg, quote! { utils::sendable::Prefix::#value })?,
+                        _ => return Err(Error::new(value.span(), "unknown prefix, expected VarInt, Byte, Short, Int or None"))
+                    }
+                }
+                "if" => {
+                    let value = match &arg.value {
+                        Value::Lit(Lit::Str(value)) => value,
+                        value => return Err(Error::new(value.span(), "expected the condition as a string"))
+                    };
+                    if !is_option(&field.ty) {
+                        return Err(Error::new_spanned(&field.ty, "fields with a condition must be an Option"));
+                    }
+                    let expr = value.parse::<Expr>()
+                        .map_err(|error| Error::new(value.span(), format!("invalid condition: {}", error)))?;
+                    set(&mut condition, &arg, (expr, value.value()))?
+                }
+                _ => return Err(Error::new(arg.key.span(), "unknown field argument, expected prefix or if"))
+            }
+        }
+        Ok(FieldOptions { prefix, condition })
+    }
+}
 
-    tokens.into()
-}
\ No newline at end of file
+/// Every identifier in `tokens`, including the ones in groups.
+fn idents(tokens: TokenStream2) -> Vec<Ident> {
+    let mut idents = Vec::new();
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => idents.push(ident),
+            TokenTree::Group(group) => idents.extend(self::idents(group.stream())),
+            _ => {}
+        }
+    }
+    idents
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path.qself.is_none() && path.path.segments.last().is_some_and(|segment| segment.ident == "Option"),
+        _ => false
+    }
+}
+
+/// A `key = value` argument of a `#[packet(..)]` attribute.
+struct Arg {
+    key: Ident,
+    value: Value,
+}
+
+enum Value {
+    Lit(Lit),
+    Ident(Ident),
+}
+
+impl Value {
+    fn span(&self) -> Span {
+        match self {
+            Value::Lit(lit) => lit.span(),
+            Value::Ident(ident) => ident.span(),
+        }
+    }
+}
+
+impl Arg {
+    fn int(&self) -> Result<LitInt> {
+        match &self.value {
+            Value::Lit(Lit::Int(value)) => Ok(value.clone()),
+            value => Err(Error::new(value.span(), format!("expected an integer {}", self.key)))
+        }
+    }
+
+    fn ident(&self) -> Result<&Ident> {
+        match &self.value {
+            Value::Ident(value) => Ok(value),
+            value => Err(Error::new(value.span(), format!("expected a name as the {}", self.key)))
+        }
+    }
+}
+
+impl Parse for Arg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if !input.peek(Ident::peek_any) || !input.peek2(Token![=]) {
+            return Err(input.error("expected key = value arguments, like id = 0x27"));
+        }
+        // `if` is a keyword
+        let key = Ident::parse_any(input)?;
+        input.parse::<Token![=]>()?;
+        let value = if input.peek(Lit) {
+            Value::Lit(input.parse()?)
+        } else {
+            Value::Ident(input.parse()?)
+        };
+        Ok(Arg { key, value })
+    }
+}
+
+/// Stores the value of an argument, every argument can only be given once.
+fn set<T>(slot: &mut Option<T>, arg: &Arg, value: T) -> Result<()> {
+    if slot.is_some() {
+        return Err(Error::new(arg.key.span(), format!("{} is given more than once", arg.key)));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// The arguments of every `#[packet(..)]` attribute.
+fn packet_args(attrs: &[Attribute]) -> Result<Vec<Arg>> {
+    let mut args = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("packet")) {
+        args.extend(attr.parse_args_with(Punctuated::<Arg, Token![,]>::parse_terminated)?);
+    }
+    Ok(args)
+}
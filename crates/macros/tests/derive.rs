@@ -0,0 +1,9 @@
+//! Compiles the cases in `pass/`, which also check that values survive a round trip,
+//! and checks the errors of the ones in `fail/` against their `.stderr` files.
+
+#[test]
+fn derive() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/pass/*.rs");
+    cases.compile_fail("tests/fail/*.rs");
+}
@@ -0,0 +1,9 @@
+#[derive(macros::Packet)]
+#[packet(id = 0x22, state = Play, direction = Serverbound)]
+struct AdvancementTab {
+    action: utils::sendable::Vari32,
+    #[packet(if = "action.val == 0")]
+    tab_id: utils::sendable::Identifier,
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[derive(macros::Packet)]
+#[packet(id = 0x01, state = Status, direction = Serverbound, id = 0x02)]
+struct Ping {
+    payload: i64,
+}
+
+fn main() {}
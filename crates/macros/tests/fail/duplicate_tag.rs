@@ -0,0 +1,9 @@
+#[derive(macros::Sendable)]
+enum Action {
+    Interact,
+    Attack,
+    #[packet(tag = 1)]
+    InteractAt,
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[derive(macros::Packet)]
+#[packet(id = "ping", state = Status, direction = Serverbound)]
+struct Ping {
+    payload: i64,
+}
+
+fn main() {}
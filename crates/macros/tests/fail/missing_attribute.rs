@@ -0,0 +1,6 @@
+#[derive(macros::Packet)]
+struct Ping {
+    payload: i64,
+}
+
+fn main() {}
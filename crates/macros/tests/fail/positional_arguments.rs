@@ -0,0 +1,7 @@
+#[derive(macros::Packet)]
+#[packet(0x01, packets::STATUS_STATE, true)]
+struct Ping {
+    payload: i64,
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[derive(macros::Sendable)]
+#[packet(id = 0x01)]
+struct Icon {
+    flags: i32,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[derive(macros::Packet)]
+#[packet(id = 0x01, state = Status, direction = Serverbound)]
+union Ping {
+    payload: i64,
+    bits: u64,
+}
+
+fn main() {}
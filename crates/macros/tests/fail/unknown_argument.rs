@@ -0,0 +1,7 @@
+#[derive(macros::Packet)]
+#[packet(id = 0x01, state = Status, direction = Serverbound, compressed = true)]
+struct Ping {
+    payload: i64,
+}
+
+fn main() {}
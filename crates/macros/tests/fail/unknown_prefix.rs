@@ -0,0 +1,9 @@
+#[derive(macros::Packet)]
+#[packet(id = 0x13, state = Play, direction = Clientbound)]
+struct WindowItems {
+    window_id: u8,
+    #[packet(prefix = Long)]
+    slots: Vec<utils::slot::Slot>,
+}
+
+fn main() {}
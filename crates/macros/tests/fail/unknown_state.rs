@@ -0,0 +1,7 @@
+#[derive(macros::Packet)]
+#[packet(id = 0x01, state = Config, direction = Serverbound)]
+struct Ping {
+    payload: i64,
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[derive(macros::Sendable)]
+enum Action {
+    #[packet(id = 1)]
+    Attack,
+}
+
+fn main() {}
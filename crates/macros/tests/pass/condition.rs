@@ -0,0 +1,45 @@
+use utils::Packet;
+use utils::error::EncodeError;
+use utils::sendable::{Identifier, Sendable, Vari32};
+
+#[derive(macros::Packet, Debug, PartialEq)]
+#[packet(id = 0x22, state = Play, direction = Serverbound)]
+struct AdvancementTab {
+    action: Vari32,
+    #[packet(if = "action.val == 0")]
+    tab_id: Option<Identifier>,
+}
+
+#[derive(macros::Sendable, Debug, PartialEq)]
+struct Icon {
+    flags: i32,
+    #[packet(if = "flags & 1 != 0", prefix = Byte)]
+    texture: Option<Vec<u8>>,
+}
+
+fn main() {
+    let packets = [
+        AdvancementTab { action: 0.into(), tab_id: Some(Identifier::new("minecraft", "story/root")) },
+        AdvancementTab { action: 1.into(), tab_id: None },
+    ];
+    for packet in packets {
+        let mut buffer = Vec::new();
+        packet.write(&mut buffer).unwrap();
+        assert_eq!(AdvancementTab::read(&mut &buffer[..]), Ok(packet));
+    }
+
+    // a field that doesn't match its condition can't be read back, so it isn't written
+    let error = Err(EncodeError::invalid_value("tab_id has to be set exactly when `action.val == 0` holds"));
+    let missing = AdvancementTab { action: 0.into(), tab_id: None };
+    assert_eq!(missing.write(&mut Vec::new()), error);
+    let unexpected = AdvancementTab { action: 1.into(), tab_id: Some(Identifier::new("minecraft", "story/root")) };
+    assert_eq!(unexpected.write(&mut Vec::new()), error);
+
+    let icon = Icon { flags: 1, texture: Some(vec![9]) };
+    let mut buffer = Vec::new();
+    Icon::write(&mut buffer, &icon).unwrap();
+    assert_eq!(buffer, [0, 0, 0, 1, 1, 9]);
+    assert_eq!(Icon::read(&mut &buffer[..]), Ok(icon));
+    let error = Err(EncodeError::invalid_value("texture has to be set exactly when `flags & 1 != 0` holds"));
+    assert_eq!(Icon::write(&mut Vec::new(), &Icon { flags: 0, texture: Some(vec![9]) }), error);
+}
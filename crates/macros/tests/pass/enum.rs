@@ -0,0 +1,44 @@
+use utils::Packet;
+use utils::sendable::{Sendable, Vari32};
+
+#[derive(macros::Packet, Debug, PartialEq)]
+#[packet(id = 0x4F, state = Play, direction = Clientbound)]
+enum Title {
+    SetTitle { text: String },
+    #[packet(tag = 3)]
+    SetTimes { fade_in: i32, stay: i32, fade_out: i32 },
+    Hide,
+    Reset(),
+}
+
+#[derive(macros::Sendable, Debug, PartialEq)]
+enum Action {
+    #[packet(tag = 2)]
+    Interact(Vari32),
+    Attack,
+}
+
+fn main() {
+    let cases = [
+        (Title::SetTitle { text: "hi".into() }, vec![0, 2, b'h', b'i']),
+        (Title::SetTimes { fade_in: 1, stay: 2, fade_out: 3 }, vec![3, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3]),
+        (Title::Hide, vec![4]),
+        (Title::Reset(), vec![5]),
+    ];
+    for (packet, bytes) in cases {
+        let mut buffer = Vec::new();
+        packet.write(&mut buffer);
+        assert_eq!(buffer, bytes);
+        assert_eq!(Title::read(&mut &buffer[..]), Ok(packet));
+    }
+
+    for action in [Action::Interact(1.into()), Action::Attack] {
+        let mut buffer = Vec::new();
+        Action::write(&mut buffer, &action);
+        assert_eq!(Action::read(&mut &buffer[..]), Ok(action));
+    }
+
+    // tags without a variant and packets that end early are errors
+    assert!(Title::read(&mut &[1u8][..]).is_err());
+    assert!(Title::read(&mut &[3u8, 0, 0][..]).is_err());
+}
@@ -0,0 +1,25 @@
+use utils::Packet;
+use utils::sendable::Vari32;
+
+#[derive(macros::Packet, Debug, PartialEq)]
+#[packet(id = 0x27, state = Play, direction = Clientbound)]
+struct EntityPosition {
+    entity_id: Vari32,
+    delta: [i16; 3],
+    #[packet(prefix = Short)]
+    passengers: Vec<Vari32>,
+    #[packet(prefix = None)]
+    rest: Vec<u8>,
+}
+
+fn main() {
+    assert_eq!(EntityPosition::get_id(), 0x27);
+    assert_eq!(EntityPosition::get_state(), packets::PLAY_STATE);
+    assert!(!EntityPosition::is_inbound());
+
+    let packet = EntityPosition { entity_id: 5.into(), delta: [1, -2, 3], passengers: vec![7.into()], rest: vec![1, 2] };
+    let mut buffer = Vec::new();
+    packet.write(&mut buffer);
+    assert_eq!(buffer, [5, 0, 1, 0xFF, 0xFE, 0, 3, 0, 1, 7, 1, 2]);
+    assert_eq!(EntityPosition::read(&mut &buffer[..]), Ok(packet));
+}
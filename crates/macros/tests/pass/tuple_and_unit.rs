@@ -0,0 +1,33 @@
+use utils::Packet;
+
+#[derive(macros::Packet, Debug, PartialEq)]
+#[packet(id = 0x00, state = Status, direction = Serverbound)]
+struct Request;
+
+#[derive(macros::Packet, Debug, PartialEq)]
+#[packet(id = 0x01, state = Status, direction = Serverbound)]
+struct Ping(i64);
+
+#[derive(macros::Packet, Debug, PartialEq)]
+#[packet(id = 0x02, state = Status, direction = Serverbound)]
+struct Flagged(bool, #[packet(if = "*&_0")] Option<u8>);
+
+fn main() {
+    assert!(Request::is_inbound());
+
+    let mut buffer = Vec::new();
+    Request.write(&mut buffer);
+    assert!(buffer.is_empty());
+    assert_eq!(Request::read(&mut &buffer[..]), Ok(Request));
+
+    let mut buffer = Vec::new();
+    Ping(3).write(&mut buffer);
+    assert_eq!(buffer, [0, 0, 0, 0, 0, 0, 0, 3]);
+    assert_eq!(Ping::read(&mut &buffer[..]), Ok(Ping(3)));
+
+    for packet in [Flagged(true, Some(4)), Flagged(false, None)] {
+        let mut buffer = Vec::new();
+        packet.write(&mut buffer);
+        assert_eq!(Flagged::read(&mut &buffer[..]), Ok(packet));
+    }
+}
@@ -1,13 +1,20 @@
-use bytes::Buf;
+use std::collections::HashMap;
+
+use bytes::{Buf, BufMut};
 use utils::contexts::{NetworkThreadContext, ConnectionContext};
 use utils::Packet;
 use utils::indexed_vec::IndexedVec;
-use utils::buffers::VarIntsMut;
+use utils::buffers::{VarInts, VarIntsMut};
+use utils::error::DecodeError;
+use utils::protocol::{PacketInfo, ProtocolIds};
+use crate::translation::{copy_packet, TranslationStep};
 use crate::TransformationResult;
 use crate::TransformationResult::{Unchanged, Canceled, Modified};
 
 const PACKET_IDS: usize = 0x5B+1;
 const STATES: usize = 4;
+// the handshake is the same in every version, the version is only known once it was read
+const HANDSHAKING_STATE: usize = 0;
 
 /// Represents a packet that is decompressed, decrypted, and has a known id.
 pub struct UnparsedPacket<T: Buf> {
@@ -21,10 +28,20 @@ impl<T: Buf> UnparsedPacket<T> {
     }
 }
 
+/// Maps the packet ids of a protocol version to the ids of the packet structs, indexed by state, direction and id.
+struct ProtocolMapping {
+    struct_ids: [[Vec<Option<i32>>; 2]; STATES],
+    /// The other way around, indexed by the struct id.
+    ids: [[Vec<Option<i32>>; 2]; STATES],
+}
+
 /// Contains protocol mapping.
 pub struct HandlingContext {
-    inbound_packets: [[Option<Box<dyn Fn(&mut dyn Buf) -> (Box<dyn Packet>, i32) + Send + Sync>>; PACKET_IDS]; STATES],
-    outbound_packets: [[Option<Box<dyn Fn(&mut dyn Buf) -> (Box<dyn Packet>, i32) + Send + Sync>>; PACKET_IDS]; STATES],
+    protocols: HashMap<i32, ProtocolMapping>,
+    translations: Vec<TranslationStep>,
+
+    inbound_packets: [[Option<Box<dyn Fn(&mut dyn Buf) -> Result<Box<dyn Packet>, DecodeError> + Send + Sync>>; PACKET_IDS]; STATES],
+    outbound_packets: [[Option<Box<dyn Fn(&mut dyn Buf) -> Result<Box<dyn Packet>, DecodeError> + Send + Sync>>; PACKET_IDS]; STATES],
 
     inbound_transformers: [[Option<Vec<Box<dyn Fn(&mut NetworkThreadContext, &mut ConnectionContext, &mut ConnectionContext, &mut dyn Packet) -> TransformationResult + Send + Sync>>>; PACKET_IDS]; STATES],
     outbound_transformers: [[Option<Vec<Box<dyn Fn(&mut NetworkThreadContext, &mut ConnectionContext, &mut ConnectionContext, &mut dyn Packet) -> TransformationResult + Send + Sync>>>; PACKET_IDS]; STATES],
@@ -32,12 +49,14 @@ pub struct HandlingContext {
 
 impl HandlingContext {
     pub fn new() -> HandlingContext {
-        const NONE1: Option<Box<dyn Fn(&mut dyn Buf) -> (Box<dyn Packet>, i32) + Send + Sync>> = None;
+        const NONE1: Option<Box<dyn Fn(&mut dyn Buf) -> Result<Box<dyn Packet>, DecodeError> + Send + Sync>> = None;
         const NONE2: Option<Vec<Box<dyn Fn(&mut NetworkThreadContext, &mut ConnectionContext, &mut ConnectionContext, &mut dyn Packet) -> TransformationResult + Send + Sync>>> = None;
-        const ARRAY1: [Option<Box<dyn Fn(&mut dyn Buf) -> (Box<dyn Packet>, i32) + Send + Sync>>; PACKET_IDS] = [NONE1; PACKET_IDS];
+        const ARRAY1: [Option<Box<dyn Fn(&mut dyn Buf) -> Result<Box<dyn Packet>, DecodeError> + Send + Sync>>; PACKET_IDS] = [NONE1; PACKET_IDS];
         const ARRAY2: [Option<Vec<Box<dyn Fn(&mut NetworkThreadContext, &mut ConnectionContext, &mut ConnectionContext, &mut dyn Packet) -> TransformationResult + Send + Sync>>>; PACKET_IDS] = [NONE2; PACKET_IDS];
 
         HandlingContext {
+            protocols: HashMap::new(),
+            translations: Vec::new(),
             inbound_packets: [ARRAY1; STATES],
             outbound_packets: [ARRAY1; STATES],
             inbound_transformers: [ARRAY2; STATES],
@@ -45,15 +64,99 @@ impl HandlingContext {
         }
     }
 
+    /// Lets the packets of a protocol version be parsed and transformed, packets are left untouched for versions that weren't registered.
+    /// The same transformers run for every version.
+    pub fn register_protocol(&mut self, protocol: &ProtocolIds) {
+        let mut mapping = ProtocolMapping { struct_ids: Default::default(), ids: Default::default() };
+        for packet in protocol.packets.iter() {
+            let direction = packet.inbound as usize;
+            insert_id(&mut mapping.struct_ids[packet.state as usize][direction], packet.id, packet.struct_id);
+            insert_id(&mut mapping.ids[packet.state as usize][direction], packet.struct_id, packet.id);
+        }
+        self.protocols.insert(protocol.protocol_version, mapping);
+    }
+
+    /// Lets clients join backends that run an older version, the versions of the step have to be registered as well.
+    /// Versions further apart are translated through every step in between.
+    pub fn register_translation(&mut self, step: TranslationStep) {
+        self.translations.push(step);
+    }
+
+    /// Translates a packet, its id followed by its data, from version `from` to version `to` and appends what it becomes to `out`.
+    /// Packets that have no equivalent in `to` are dropped, as is everything if there are no steps between the versions.
+    /// Fails if the packet is malformed, `out` may hold some of the packets it was translated to by then.
+    pub fn translate(&self, state: u8, inbound: bool, from: i32, to: i32, packet: &[u8], out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+        let mut first = IndexedVec::new();
+        first.put_slice(packet);
+        let mut packets = vec![first];
+        let mut version = from;
+        while version != to {
+            // serverbound packets go from the client down to the backend, clientbound ones the other way around
+            let step = self.translations.iter()
+                .find(|step| if inbound { step.newer == version } else { step.older == version });
+            let step = match step {
+                Some(step) => step,
+                None => return Ok(())
+            };
+            let target = if inbound { step.older } else { step.newer };
+            let translators = if inbound { &step.serverbound } else { &step.clientbound };
+
+            let mut translated = Vec::with_capacity(packets.len());
+            for packet in packets.iter() {
+                let mut buf = packet.as_slice();
+                let (id, _id_bytes) = buf.get_var_i32()?;
+                match translators.get(&(state, id)) {
+                    Some(translator) => translator(&mut buf, &mut translated)?,
+                    None => {
+                        if let Some(new_id) = self.same_packet(state, inbound, version, target, id) {
+                            translated.push(copy_packet(new_id, &mut buf));
+                        }
+                    }
+                }
+            }
+            packets = translated;
+            version = target;
+        }
+        out.extend(packets);
+        Ok(())
+    }
+
+    /// The id a packet of version `from` has in version `to`, if both encode it like its packet struct.
+    fn same_packet(&self, state: u8, inbound: bool, from: i32, to: i32, id: i32) -> Option<i32> {
+        let state = state as usize;
+        if state == HANDSHAKING_STATE {
+            return Some(id);
+        }
+        let struct_id = (*self.protocols.get(&from)?.struct_ids.get(state)?[inbound as usize].get(id as usize)?)?;
+        *self.protocols.get(&to)?.ids[state][inbound as usize].get(struct_id as usize)?
+    }
+
+    /// The id of the packet struct for a packet id of the connection's version.
+    fn struct_id(&self, connection_ctx: &ConnectionContext, id: i32, inbound: bool) -> Option<usize> {
+        let state = connection_ctx.state as usize;
+        if state == HANDSHAKING_STATE {
+            return Some(id as usize);
+        }
+        let mapping = self.protocols.get(&connection_ctx.protocol_version)?;
+        let struct_id = *mapping.struct_ids.get(state)?[inbound as usize].get(id as usize)?;
+        struct_id.map(|id| id as usize)
+    }
+
+    /// Parses the packet and runs the transformers registered for it. A packet that can't be parsed
+    /// closes the connection it came from, and with it the connection on the other side of the proxy.
     pub fn handle_packet(&self, thread_ctx: &mut NetworkThreadContext, connection_ctx: &mut ConnectionContext, other_ctx: &mut ConnectionContext, mut packet: UnparsedPacket<&[u8]>, inbound: bool) -> (TransformationResult, Option<IndexedVec<u8>>) {
-        let id = packet.id as usize;
+        let id = match self.struct_id(connection_ctx, packet.id, inbound) {
+            Some(id) => id,
+            // unknown to this version, or encoded differently than the packet struct
+            None => return (Unchanged, None)
+        };
         let packet_supplier;
         let transformers;
 
         let state = connection_ctx.state as usize;
 
         // No such packet
-        if state >= STATES && id >= PACKET_IDS {
+        if state >= STATES || id >= PACKET_IDS {
             println!("No such packet, state: {}, id: {}", connection_ctx.state, id);
             return (Unchanged, None);
         }
@@ -73,11 +176,19 @@ impl HandlingContext {
             } else { return (Unchanged, None); };
         }
 
-        let mut packet: (Box<dyn Packet>, i32) = packet_supplier(&mut packet.buf);
+        let mut parsed_packet: Box<dyn Packet> = match packet_supplier(&mut packet.buf) {
+            Ok(parsed_packet) => parsed_packet,
+            Err(e) => {
+                let side = if inbound { "client" } else { "backend" };
+                println!("malformed packet {:#04x} from {} {:?}: {}", packet.id, side, connection_ctx.username, e);
+                connection_ctx.should_close = true;
+                return (Canceled, None);
+            }
+        };
         let mut result = Unchanged;
 
         for transformer in transformers.iter() {
-            if result.combine(transformer(thread_ctx, connection_ctx, other_ctx, &mut *packet.0)) {
+            if result.combine(transformer(thread_ctx, connection_ctx, other_ctx, &mut *parsed_packet)) {
                 return (Canceled, None);
             }
         }
@@ -90,19 +201,54 @@ impl HandlingContext {
         }
 
         let mut buffer: IndexedVec<u8> = IndexedVec::new();
-        buffer.put_var_i32(packet.1);
-        packet.0.write(&mut buffer);
+        // the packet keeps the id it has in the connection's version
+        buffer.put_var_i32(packet.id);
+        if let Err(e) = parsed_packet.write(&mut buffer) {
+            // a transformer left a value that can't be sent, handled like a malformed packet
+            let side = if inbound { "client" } else { "backend" };
+            println!("could not write packet {:#04x} from {} {:?}: {}", packet.id, side, connection_ctx.username, e);
+            connection_ctx.should_close = true;
+            return (Canceled, None);
+        }
 
         (Modified, Some(buffer))
     }
 
-    pub fn register_packet_supplier<P: Packet, F: 'static + Fn(&mut dyn Buf) -> P + Send + Sync>(&mut self, transformer: F) {
+    /// Runs the transformers registered for `P` on a packet that didn't come from the network,
+    /// like one the proxy is about to send itself.
+    pub fn transform_packet<P: Packet>(&self, thread_ctx: &mut NetworkThreadContext, connection_ctx: &mut ConnectionContext, other_ctx: &mut ConnectionContext, packet: &mut P) -> TransformationResult {
+        let transformers = if P::is_inbound() { &self.inbound_transformers } else { &self.outbound_transformers };
+        let mut result = Unchanged;
+        if let Some(transformers) = &transformers[P::get_state() as usize][P::get_id() as usize] {
+            for transformer in transformers.iter() {
+                if result.combine(transformer(thread_ctx, connection_ctx, other_ctx, packet)) {
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    /// Lets every packet in `packets`, indexed by state and direction like `packets::PACKETS`, be parsed.
+    /// Packets that already have a supplier keep it.
+    pub fn register_packets(&mut self, packets: &[[&[PacketInfo]; 2]]) {
+        for packet in packets.iter().flatten().flat_map(|packets| packets.iter()) {
+            let suppliers = if packet.inbound { &mut self.inbound_packets } else { &mut self.outbound_packets };
+            let supplier = &mut suppliers[packet.state as usize][packet.id as usize];
+            if supplier.is_none() {
+                let read = packet.read;
+                *supplier = Some(Box::new(move |buf| Ok(read(buf)?)));
+            }
+        }
+    }
+
+    pub fn register_packet_supplier<P: Packet, F: 'static + Fn(&mut dyn Buf) -> Result<P, DecodeError> + Send + Sync>(&mut self, transformer: F) {
         let packet_id = P::get_id() as usize;
         let state = P::get_state() as usize;
         if P::is_inbound() {
-            self.inbound_packets[state][packet_id] = Some(Box::new(move |buf| (Box::new(transformer(buf)), P::get_id())));
+            self.inbound_packets[state][packet_id] = Some(Box::new(move |buf| Ok(Box::new(transformer(buf)?))));
         } else {
-            self.outbound_packets[state][packet_id] = Some(Box::new(move |buf| (Box::new(transformer(buf)), P::get_id())));
+            self.outbound_packets[state][packet_id] = Some(Box::new(move |buf| Ok(Box::new(transformer(buf)?))));
         }
     }
 
@@ -145,4 +291,11 @@ impl HandlingContext {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+fn insert_id(ids: &mut Vec<Option<i32>>, index: i32, id: i32) {
+    if ids.len() <= index as usize {
+        ids.resize(index as usize + 1, None);
+    }
+    ids[index as usize] = Some(id);
+}
@@ -1,4 +1,5 @@
 pub mod handling;
+pub mod translation;
 
 pub enum TransformationResult {
     Unchanged,
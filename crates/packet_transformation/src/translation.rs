@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use bytes::{Buf, BufMut};
+use utils::indexed_vec::IndexedVec;
+use utils::buffers::VarIntsMut;
+use utils::error::DecodeError;
+
+/// Writes what a packet of one protocol version becomes in another, it gets the packet data without the id.
+/// Every packet pushed to the output starts with its id, nothing is pushed if the packet has no equivalent.
+/// Malformed packets fail to translate.
+pub type Translator = Box<dyn Fn(&mut dyn Buf, &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> + Send + Sync>;
+
+/// The changes between a protocol version and the one released after it.
+///
+/// Clients are never older than their backend, so clientbound packets are translated from the older version
+/// to the newer one and serverbound packets the other way around.
+/// Packets without a translator keep their data and get the id their packet struct has in the other version,
+/// packets that aren't described by a packet struct in both versions are dropped.
+pub struct TranslationStep {
+    pub(crate) older: i32,
+    pub(crate) newer: i32,
+    // keyed by state and the id in the version the packet comes from
+    pub(crate) clientbound: HashMap<(u8, i32), Translator>,
+    pub(crate) serverbound: HashMap<(u8, i32), Translator>,
+}
+
+impl TranslationStep {
+    pub fn new(older: i32, newer: i32) -> TranslationStep {
+        TranslationStep {
+            older,
+            newer,
+            clientbound: HashMap::new(),
+            serverbound: HashMap::new(),
+        }
+    }
+
+    /// Translates the clientbound packet with `id` in the older version.
+    pub fn clientbound<F: 'static + Fn(&mut dyn Buf, &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> + Send + Sync>(mut self, state: u8, id: i32, translator: F) -> TranslationStep {
+        self.clientbound.insert((state, id), Box::new(translator));
+        self
+    }
+
+    /// Translates the serverbound packet with `id` in the newer version.
+    pub fn serverbound<F: 'static + Fn(&mut dyn Buf, &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> + Send + Sync>(mut self, state: u8, id: i32, translator: F) -> TranslationStep {
+        self.serverbound.insert((state, id), Box::new(translator));
+        self
+    }
+
+    /// Gives a clientbound packet that is encoded the same in both versions the id it has in the newer one.
+    pub fn rename_clientbound(self, state: u8, id: i32, new_id: i32) -> TranslationStep {
+        self.clientbound(state, id, move |buf, out| {
+            out.push(copy_packet(new_id, buf));
+            Ok(())
+        })
+    }
+
+    /// Gives a serverbound packet that is encoded the same in both versions the id it has in the older one.
+    pub fn rename_serverbound(self, state: u8, id: i32, new_id: i32) -> TranslationStep {
+        self.serverbound(state, id, move |buf, out| {
+            out.push(copy_packet(new_id, buf));
+            Ok(())
+        })
+    }
+}
+
+/// A packet with `id` and no data yet.
+pub fn new_packet(id: i32) -> IndexedVec<u8> {
+    let mut packet = IndexedVec::new();
+    packet.put_var_i32(id);
+    packet
+}
+
+/// A packet with `id` and the rest of `buf` as data.
+pub fn copy_packet(id: i32, buf: &mut dyn Buf) -> IndexedVec<u8> {
+    let mut packet = new_packet(id);
+    let data = buf.copy_to_bytes(buf.remaining());
+    packet.put_slice(&data);
+    packet
+}
@@ -0,0 +1,527 @@
+//! Generates the c2s and s2c packet modules from the protocol description in `protocol/`,
+//! and the packet ids of the other supported versions.
+//! The file format is explained at the top of the description.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// The version the packet structs are generated for.
+const PROTOCOL: &str = "protocol/1.16.5.txt";
+/// Versions that only list the ids of the packets they encode like [`PROTOCOL`].
+const OTHER_PROTOCOLS: &[&str] = &["protocol/1.17.1.txt", "protocol/1.18.2.txt"];
+
+struct Field {
+    name: String,
+    ty: Type,
+    condition: Option<String>,
+    docs: Vec<String>,
+}
+
+enum Type {
+    Named(String),
+    Vec(Box<Type>, Option<String>),
+    Option(Box<Type>),
+    Array(Box<Type>, usize),
+}
+
+struct Variant {
+    tag: i64,
+    name: String,
+    docs: Vec<String>,
+    fields: Vec<Field>,
+}
+
+enum ItemKind {
+    Packet(u8),
+    Compound,
+    Enum { tag: String, variants: Vec<Variant> },
+}
+
+struct Item {
+    kind: ItemKind,
+    name: String,
+    docs: Vec<String>,
+    fields: Vec<Field>,
+}
+
+struct Protocol {
+    version: i32,
+    name: String,
+    sections: Vec<Section>,
+}
+
+struct Section {
+    state: String,
+    serverbound: bool,
+    items: Vec<Item>,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=protocol");
+
+    let base = read_protocol(PROTOCOL);
+    let others: Vec<Protocol> = OTHER_PROTOCOLS.iter().map(|path| read_protocol(path)).collect();
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    for (file, serverbound) in [("c2s.rs", true), ("s2c.rs", false)] {
+        let mut out = format!("// Generated by build.rs from {}, do not edit.\n", PROTOCOL);
+        for section in base.sections.iter().filter(|section| section.serverbound == serverbound) {
+            generate_section(&mut out, section);
+        }
+        fs::write(Path::new(&out_dir).join(file), out).unwrap();
+    }
+
+    let mut out = String::from("// Generated by build.rs from the files in protocol/, do not edit.\n\n");
+    out.push_str("/// Every supported version, starting with the one the packet structs are generated for.\n");
+    out.push_str("pub static PROTOCOLS: &[utils::protocol::ProtocolIds] = &[\n");
+    generate_ids(&mut out, &base, &base);
+    for protocol in others.iter() {
+        generate_ids(&mut out, protocol, &base);
+    }
+    out.push_str("];\n");
+    generate_packets(&mut out, &base);
+    fs::write(Path::new(&out_dir).join("protocols.rs"), out).unwrap();
+}
+
+fn read_protocol(path: &str) -> Protocol {
+    let source = fs::read_to_string(path).unwrap_or_else(|e| panic!("could not read {}: {}", path, e));
+    parse(&source)
+}
+
+fn parse(source: &str) -> Protocol {
+    let mut version = None;
+    let mut sections: Vec<Section> = Vec::new();
+    let mut docs = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if let Some(doc) = trimmed.strip_prefix("///") {
+            docs.push(doc.trim().to_string());
+            continue;
+        }
+        let docs = std::mem::take(&mut docs);
+
+        if let Some(line) = trimmed.strip_prefix("version ") {
+            let (protocol_version, name) = line.split_once(' ').unwrap_or_else(|| panic!("line {}: expected `version protocol name`", number));
+            let protocol_version = protocol_version.parse().unwrap_or_else(|_| panic!("line {}: invalid protocol version", number));
+            version = Some((protocol_version, name.to_string()));
+            continue;
+        }
+        if let Some(header) = trimmed.strip_prefix('[') {
+            let header = header.strip_suffix(']').unwrap_or_else(|| panic!("line {}: unclosed section header", number));
+            let (state, direction) = header.split_once(' ').unwrap_or_else(|| panic!("line {}: expected [state direction]", number));
+            let serverbound = match direction {
+                "serverbound" => true,
+                "clientbound" => false,
+                _ => panic!("line {}: unknown direction {}", number, direction)
+            };
+            sections.push(Section { state: state.to_string(), serverbound, items: Vec::new() });
+            continue;
+        }
+
+        let section = sections.last_mut().unwrap_or_else(|| panic!("line {}: definition outside of a section", number));
+        let indent = line.len() - line.trim_start().len();
+        match indent {
+            0 => section.items.push(parse_item(trimmed, docs, number)),
+            4 => {
+                let item = section.items.last_mut().unwrap_or_else(|| panic!("line {}: field outside of a definition", number));
+                match &mut item.kind {
+                    ItemKind::Enum { variants, .. } => {
+                        let (tag, name) = trimmed.split_once(' ').unwrap_or_else(|| panic!("line {}: expected `tag Name`", number));
+                        let tag = tag.parse().unwrap_or_else(|_| panic!("line {}: invalid tag {}", number, tag));
+                        variants.push(Variant { tag, name: name.to_string(), docs, fields: Vec::new() });
+                    }
+                    _ => item.fields.push(parse_field(trimmed, docs, number))
+                }
+            }
+            8 => {
+                let variant = match section.items.last_mut().map(|item| &mut item.kind) {
+                    Some(ItemKind::Enum { variants, .. }) => variants.last_mut(),
+                    _ => None
+                }.unwrap_or_else(|| panic!("line {}: field outside of an enum variant", number));
+                variant.fields.push(parse_field(trimmed, docs, number));
+            }
+            _ => panic!("line {}: unexpected indentation", number)
+        }
+    }
+
+    let (version, name) = version.expect("the protocol version is missing");
+    Protocol { version, name, sections }
+}
+
+fn parse_item(line: &str, docs: Vec<String>, number: usize) -> Item {
+    let (keyword, name) = line.split_once(' ').unwrap_or_else(|| panic!("line {}: expected a definition", number));
+    let kind = if let Some(id) = keyword.strip_prefix("0x") {
+        ItemKind::Packet(u8::from_str_radix(id, 16).unwrap_or_else(|_| panic!("line {}: invalid id {}", number, keyword)))
+    } else if keyword == "type" {
+        ItemKind::Compound
+    } else if keyword == "enum" {
+        let (name, tag) = name.split_once(':').unwrap_or_else(|| panic!("line {}: enum without a tag type", number));
+        return Item {
+            kind: ItemKind::Enum { tag: tag.trim().to_string(), variants: Vec::new() },
+            name: name.to_string(),
+            docs,
+            fields: Vec::new(),
+        };
+    } else {
+        panic!("line {}: unknown definition {}", number, keyword)
+    };
+    Item { kind, name: name.to_string(), docs, fields: Vec::new() }
+}
+
+fn parse_field(line: &str, docs: Vec<String>, number: usize) -> Field {
+    let (name, rest) = line.split_once(':').unwrap_or_else(|| panic!("line {}: expected `name: type`", number));
+    let (ty, condition) = match rest.split_once(" if ") {
+        Some((ty, condition)) => (ty, Some(condition.trim().to_string())),
+        None => (rest, None)
+    };
+    let (ty, remaining) = parse_type(ty.trim(), number);
+    if !remaining.trim().is_empty() {
+        panic!("line {}: unexpected {}", number, remaining);
+    }
+    Field { name: name.trim().to_string(), ty, condition, docs }
+}
+
+/// Parses a type from the start of `source` and returns what follows it.
+fn parse_type(source: &str, number: usize) -> (Type, &str) {
+    let source = source.trim_start();
+    if let Some(inner) = source.strip_prefix('[') {
+        let (element, rest) = parse_type(inner, number);
+        let rest = rest.trim_start().strip_prefix(';').unwrap_or_else(|| panic!("line {}: expected ; in array type", number));
+        let (len, rest) = rest.split_once(']').unwrap_or_else(|| panic!("line {}: unclosed array type", number));
+        let len = len.trim().parse().unwrap_or_else(|_| panic!("line {}: invalid array length {}", number, len));
+        return (Type::Array(Box::new(element), len), rest);
+    }
+
+    let end = source.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(source.len());
+    let (name, rest) = source.split_at(end);
+    match name {
+        "vec" | "option" => {
+            let rest = rest.strip_prefix('<').unwrap_or_else(|| panic!("line {}: {} without an element type", number, name));
+            let (element, rest) = parse_type(rest, number);
+            let rest = rest.trim_start();
+            if name == "option" {
+                let rest = rest.strip_prefix('>').unwrap_or_else(|| panic!("line {}: unclosed option", number));
+                return (Type::Option(Box::new(element)), rest);
+            }
+            if let Some(rest) = rest.strip_prefix('>') {
+                return (Type::Vec(Box::new(element), None), rest);
+            }
+            let rest = rest.strip_prefix(',').unwrap_or_else(|| panic!("line {}: expected , or > in vec", number));
+            let (count, rest) = rest.split_once('>').unwrap_or_else(|| panic!("line {}: unclosed vec", number));
+            (Type::Vec(Box::new(element), Some(count.trim().to_string())), rest)
+        }
+        "" => panic!("line {}: expected a type", number),
+        _ => (Type::Named(name.to_string()), rest)
+    }
+}
+
+/// The rust type of a protocol type, `None` if it can't be decoded yet.
+fn rust_type(ty: &Type, compounds: &HashMap<&str, bool>) -> Option<String> {
+    match ty {
+        Type::Named(name) => {
+            let primitive = match name.as_str() {
+                "bool" | "i8" | "u8" | "i16" | "u16" | "i32" | "i64" | "f32" | "f64" => name.as_str(),
+                "varint" => "utils::sendable::Vari32",
+                "varlong" => "utils::sendable::Vari64",
+                "string" => "String",
+                "identifier" => "utils::sendable::Identifier",
+                "chat" => "utils::chat::Component",
+                "uuid" => "utils::sendable::Uuid",
+                "position" => "utils::sendable::Position",
+                "angle" => "utils::sendable::Angle",
+                "nbt" => "utils::nbt::Nbt",
+                "slot" => "utils::slot::Slot",
+                "metadata" => "utils::metadata::EntityMetadata",
+                "bytes" => "utils::indexed_vec::IndexedVec<u8>",
+                "rest" => "utils::sendable::InferLenVec",
+                _ => return match compounds.get(name.as_str()) {
+                    Some(true) => Some(name.clone()),
+                    _ => None
+                }
+            };
+            Some(primitive.to_string())
+        }
+        Type::Vec(element, count) => {
+            if count.is_some() && prefix(ty).is_none() {
+                return None;
+            }
+            element_type(element, compounds).map(|element| format!("Vec<{}>", element))
+        }
+        Type::Option(element) => element_type(element, compounds).map(|element| format!("Option<{}>", element)),
+        Type::Array(element, len) => element_type(element, compounds).map(|element| format!("[{}; {}]", element, len)),
+    }
+}
+
+/// Arrays with another length prefix than a varint can only be fields, the prefix is an attribute of the field.
+fn element_type(ty: &Type, compounds: &HashMap<&str, bool>) -> Option<String> {
+    match ty {
+        Type::Vec(_, Some(_)) => None,
+        _ => rust_type(ty, compounds)
+    }
+}
+
+/// The `utils::sendable::Prefix` of an array sent with another length prefix than a varint.
+fn prefix(ty: &Type) -> Option<&'static str> {
+    match ty {
+        Type::Vec(_, Some(count)) => match count.as_str() {
+            "u8" => Some("Byte"),
+            "i16" => Some("Short"),
+            "i32" => Some("Int"),
+            "varint" => Some("VarInt"),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn describe_type(ty: &Type) -> String {
+    match ty {
+        Type::Named(name) => name.clone(),
+        Type::Vec(element, None) => format!("vec<{}>", describe_type(element)),
+        Type::Vec(element, Some(count)) => format!("vec<{}, {}>", describe_type(element), count),
+        Type::Option(element) => format!("option<{}>", describe_type(element)),
+        Type::Array(element, len) => format!("[{}; {}]", describe_type(element), len),
+    }
+}
+
+/// A field that can be decoded.
+struct Decodable<'a> {
+    field: &'a Field,
+    ty: String,
+    /// The condition of the field as a rust expression on the earlier fields.
+    condition: Option<String>,
+}
+
+/// The fields up to the first one that can't be decoded, and the remaining ones.
+fn decodable_fields<'a>(fields: &'a [Field], compounds: &HashMap<&str, bool>) -> (Vec<Decodable<'a>>, &'a [Field]) {
+    let mut decodable = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let ty = rust_type(&field.ty, compounds);
+        let condition = match &field.condition {
+            Some(condition) => condition_expr(condition, &decodable).map(Some),
+            None => Some(None)
+        };
+        match (ty, condition) {
+            (Some(ty), Some(condition)) => decodable.push(Decodable { field, ty, condition }),
+            _ => return (decodable, &fields[index..])
+        }
+    }
+    (decodable, &[])
+}
+
+/// Turns `field`, `field == n`, `field != n` or `field & n` into a rust expression,
+/// `None` if the field it depends on isn't a bool or an integer that was decoded.
+fn condition_expr(condition: &str, earlier: &[Decodable]) -> Option<String> {
+    let mut parts = condition.split_whitespace();
+    let name = parts.next()?;
+    let operation = match (parts.next(), parts.next(), parts.next()) {
+        (None, None, None) => None,
+        (Some(operator), Some(value), None) => Some((operator, value.parse::<i64>().ok()?)),
+        _ => return None
+    };
+    let field = earlier.iter().find(|decodable| decodable.field.name == name && decodable.condition.is_none())?;
+    let ty = match &field.field.ty {
+        Type::Named(ty) => ty.as_str(),
+        _ => return None
+    };
+    let value = match ty {
+        "bool" if operation.is_none() => return Some(name.to_string()),
+        "i8" | "u8" | "i16" | "u16" | "i32" | "i64" => name.to_string(),
+        "varint" | "varlong" => format!("{}.val", name),
+        _ => return None
+    };
+    match operation? {
+        ("==", n) => Some(format!("{} == {}", value, n)),
+        ("!=", n) => Some(format!("{} != {}", value, n)),
+        ("&", n) => Some(format!("{} & {} != 0", value, n)),
+        _ => None
+    }
+}
+
+/// The states in the order of their constants.
+const STATES: &[&str] = &["handshaking", "status", "login", "play"];
+
+/// The module, the state constant and the `#[packet(state = ..)]` name of a state.
+fn state_names(state: &str) -> (&'static str, &'static str, &'static str) {
+    match state {
+        "handshaking" => ("handshake", "HANDSHAKING_STATE", "Handshaking"),
+        "status" => ("status", "STATUS_STATE", "Status"),
+        "login" => ("login", "LOGIN_STATE", "Login"),
+        "play" => ("play", "PLAY_STATE", "Play"),
+        state => panic!("unknown state {}", state)
+    }
+}
+
+fn generate_section(out: &mut String, section: &Section) {
+    let (module, _, state) = state_names(&section.state);
+
+    // compounds and enums can only be used if all of their fields can be decoded, they may use each other
+    let mut compounds: HashMap<&str, bool> = HashMap::new();
+    loop {
+        let mut changed = false;
+        for item in section.items.iter() {
+            let decodable = match &item.kind {
+                ItemKind::Packet(_) => continue,
+                ItemKind::Compound => decodable_fields(&item.fields, &compounds).1.is_empty(),
+                ItemKind::Enum { tag, variants } => tag == "varint" && variants.iter()
+                    .all(|variant| decodable_fields(&variant.fields, &compounds).1.is_empty()),
+            };
+            if compounds.insert(&item.name, decodable) != Some(decodable) {
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    writeln!(out, "\npub mod {} {{", module).unwrap();
+    for item in section.items.iter() {
+        match &item.kind {
+            ItemKind::Packet(id) => {
+                let (fields, undecoded) = decodable_fields(&item.fields, &compounds);
+                let direction = if section.serverbound { "Serverbound" } else { "Clientbound" };
+                out.push('\n');
+                write_docs(out, &item.docs, "    ");
+                writeln!(out, "    #[derive(macros::Packet, Debug)]").unwrap();
+                writeln!(out, "    #[packet(id = 0x{:02X}, state = {}, direction = {})]", id, state, direction).unwrap();
+                writeln!(out, "    pub struct {} {{", item.name).unwrap();
+                write_fields(out, &fields, "        ", "pub ");
+                if !undecoded.is_empty() {
+                    let names: Vec<String> = undecoded.iter().map(|field| format!("`{}: {}`", field.name, describe_type(&field.ty))).collect();
+                    writeln!(out, "        /// Not decoded yet, holds {}.", names.join(", ")).unwrap();
+                    writeln!(out, "        pub undecoded: utils::sendable::InferLenVec,").unwrap();
+                }
+                writeln!(out, "    }}").unwrap();
+            }
+            ItemKind::Compound if compounds[item.name.as_str()] => {
+                let (fields, _) = decodable_fields(&item.fields, &compounds);
+                out.push('\n');
+                write_docs(out, &item.docs, "    ");
+                writeln!(out, "    #[derive(macros::Sendable, Debug)]").unwrap();
+                writeln!(out, "    pub struct {} {{", item.name).unwrap();
+                write_fields(out, &fields, "        ", "pub ");
+                writeln!(out, "    }}").unwrap();
+            }
+            ItemKind::Enum { variants, .. } if compounds[item.name.as_str()] => {
+                out.push('\n');
+                write_docs(out, &item.docs, "    ");
+                writeln!(out, "    #[derive(macros::Sendable, Debug)]").unwrap();
+                writeln!(out, "    pub enum {} {{", item.name).unwrap();
+                for variant in variants.iter() {
+                    let (fields, _) = decodable_fields(&variant.fields, &compounds);
+                    write_docs(out, &variant.docs, "        ");
+                    writeln!(out, "        #[packet(tag = {})]", variant.tag).unwrap();
+                    if fields.is_empty() {
+                        writeln!(out, "        {},", variant.name).unwrap();
+                    } else {
+                        writeln!(out, "        {} {{", variant.name).unwrap();
+                        write_fields(out, &fields, "            ", "");
+                        writeln!(out, "        }},").unwrap();
+                    }
+                }
+                writeln!(out, "    }}").unwrap();
+            }
+            // used as raw bytes until they can be decoded
+            ItemKind::Compound | ItemKind::Enum { .. } => {}
+        }
+    }
+    writeln!(out, "}}").unwrap();
+}
+
+fn write_fields(out: &mut String, fields: &[Decodable], indent: &str, visibility: &str) {
+    for decodable in fields.iter() {
+        write_docs(out, &decodable.field.docs, indent);
+        let mut args = Vec::new();
+        if let Some(prefix) = prefix(&decodable.field.ty) {
+            args.push(format!("prefix = {}", prefix));
+        }
+        if let Some(condition) = &decodable.condition {
+            args.push(format!("if = {:?}", condition));
+        }
+        if !args.is_empty() {
+            writeln!(out, "{}#[packet({})]", indent, args.join(", ")).unwrap();
+        }
+        match decodable.condition {
+            Some(_) => writeln!(out, "{}{}{}: Option<{}>,", indent, visibility, decodable.field.name, decodable.ty),
+            None => writeln!(out, "{}{}{}: {},", indent, visibility, decodable.field.name, decodable.ty)
+        }.unwrap();
+    }
+}
+
+fn write_docs(out: &mut String, docs: &[String], indent: &str) {
+    for doc in docs {
+        writeln!(out, "{}/// {}", indent, doc).unwrap();
+    }
+}
+
+/// Writes the ids of every packet of `protocol`, they are checked against the packets of `base`.
+fn generate_ids(out: &mut String, protocol: &Protocol, base: &Protocol) {
+    writeln!(out, "    utils::protocol::ProtocolIds {{").unwrap();
+    writeln!(out, "        protocol_version: {},", protocol.version).unwrap();
+    writeln!(out, "        minecraft_version: {:?},", protocol.name).unwrap();
+    writeln!(out, "        packets: &[").unwrap();
+    for section in protocol.sections.iter() {
+        let base_section = base.sections.iter()
+            .find(|base_section| base_section.state == section.state && base_section.serverbound == section.serverbound)
+            .unwrap_or_else(|| panic!("{} has packets in {} that {} doesn't have", protocol.name, section.state, base.name));
+        let (_, state, _) = state_names(&section.state);
+        for item in section.items.iter() {
+            let id = match item.kind {
+                ItemKind::Packet(id) => id,
+                // types and enums only describe how packets are encoded
+                _ => continue
+            };
+            let struct_id = base_section.items.iter()
+                .find_map(|base_item| match base_item.kind {
+                    ItemKind::Packet(struct_id) if base_item.name == item.name => Some(struct_id),
+                    _ => None
+                })
+                .unwrap_or_else(|| panic!("{} has a packet {} that {} doesn't have", protocol.name, item.name, base.name));
+            writeln!(out, "            utils::protocol::PacketId {{ state: crate::{}, inbound: {}, id: 0x{:02X}, struct_id: 0x{:02X} }},", state, section.serverbound, id, struct_id).unwrap();
+        }
+    }
+    writeln!(out, "        ],\n    }},").unwrap();
+}
+
+/// Writes the table of every packet struct, by state and direction.
+fn generate_packets(out: &mut String, base: &Protocol) {
+    out.push_str("\n/// Every packet struct, `PACKETS[state][inbound as usize]` lists the ones of a state and direction by id.\n");
+    writeln!(out, "pub static PACKETS: [[&[utils::protocol::PacketInfo]; 2]; {}] = [", STATES.len()).unwrap();
+    for state in STATES {
+        let (module, constant, _) = state_names(state);
+        out.push_str("    [\n");
+        for serverbound in [false, true] {
+            let packets: Vec<(u8, &str)> = base.sections.iter()
+                .filter(|section| section.state == *state && section.serverbound == serverbound)
+                .flat_map(|section| section.items.iter())
+                .filter_map(|item| match item.kind {
+                    ItemKind::Packet(id) => Some((id, item.name.as_str())),
+                    _ => None
+                })
+                .collect();
+            if packets.is_empty() {
+                out.push_str("        &[],\n");
+                continue;
+            }
+            out.push_str("        &[\n");
+            let side = if serverbound { "c2s" } else { "s2c" };
+            for (id, name) in packets {
+                writeln!(out, "            utils::protocol::PacketInfo {{ name: {:?}, state: crate::{}, inbound: {}, id: 0x{:02X}, read: utils::protocol::read_packet::<crate::{}::{}::{}> }},",
+                         name, constant, serverbound, id, side, module, name).unwrap();
+            }
+            out.push_str("        ],\n");
+        }
+        out.push_str("    ],\n");
+    }
+    out.push_str("];\n");
+}
@@ -1,7 +1,27 @@
+use utils::protocol::{PacketInfo, ProtocolIds};
+
+// the packet derive refers to the state constants through the crate name
+extern crate self as packets;
+
 pub mod c2s;
 pub mod s2c;
 
+include!(concat!(env!("OUT_DIR"), "/protocols.rs"));
+
 pub const HANDSHAKING_STATE: u8 = 0;
 pub const STATUS_STATE: u8 = 1;
 pub const LOGIN_STATE: u8 = 2;
-pub const PLAY_STATE: u8 = 3;
\ No newline at end of file
+pub const PLAY_STATE: u8 = 3;
+/// Version of the protocol the packets are defined for.
+pub const PROTOCOL_VERSION: i32 = 754;
+pub const MINECRAFT_VERSION: &str = "1.16.5";
+
+/// The packet ids of a supported protocol version.
+pub fn protocol(protocol_version: i32) -> Option<&'static ProtocolIds> {
+    PROTOCOLS.iter().find(|protocol| protocol.protocol_version == protocol_version)
+}
+
+/// The packet struct of a state, direction and [`utils::Packet::get_id`], to decode packets without knowing their type.
+pub fn packet_info(state: u8, inbound: bool, id: i32) -> Option<&'static PacketInfo> {
+    PACKETS.get(state as usize)?[inbound as usize].iter().find(|packet| packet.id == id)
+}
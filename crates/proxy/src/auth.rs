@@ -0,0 +1,389 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+use bytes::BufMut;
+use md5::Md5;
+use rand::RngCore;
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey};
+use rsa::pkcs8::{DecodePublicKey, EncodePublicKey};
+use sha1::{Digest, Sha1};
+
+use packets::{c2s, s2c};
+use utils::chat::Component;
+use mio::Token;
+
+use utils::contexts::{ConnectionContext, NetworkThreadContext, PaxyThread};
+use utils::contexts::Message::LoginVerified;
+use utils::indexed_vec::IndexedVec;
+
+use crate::ProxyError;
+
+pub use utils::profile::{GameProfile, ProfileProperty};
+
+// vanilla sends the same messages
+const NOT_VERIFIED: &str = "Failed to verify username!";
+const SESSION_SERVER_DOWN: &str = "Authentication servers are down. Please try again later, sorry!";
+
+// threads that wait on the session server, and how many logins may wait for one of them
+const VERIFIER_THREADS: usize = 4;
+const VERIFIER_QUEUE: usize = 256;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Checks with a session server that a player joined the proxy, see [`crate::ProxyBuilder::session_verifier`].
+pub trait SessionVerifier: Send + Sync {
+    /// Returns the profile of the player, or `None` if it didn't join with that server hash.
+    /// Called on a separate thread, so it may block.
+    fn has_joined(&self, username: &str, server_hash: &str) -> Result<Option<GameProfile>, ProxyError>;
+}
+
+/// Verifies players with Mojang's session server, or one that implements the same api.
+/// A session server that doesn't answer in time fails the login.
+pub struct MojangSessionVerifier {
+    session_server: String,
+    agent: ureq::Agent,
+}
+
+impl MojangSessionVerifier {
+    pub fn new(session_server: impl Into<String>) -> MojangSessionVerifier {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(READ_TIMEOUT)
+            .build();
+        MojangSessionVerifier { session_server: session_server.into(), agent }
+    }
+}
+
+impl Default for MojangSessionVerifier {
+    fn default() -> MojangSessionVerifier {
+        MojangSessionVerifier::new("https://sessionserver.mojang.com")
+    }
+}
+
+impl SessionVerifier for MojangSessionVerifier {
+    fn has_joined(&self, username: &str, server_hash: &str) -> Result<Option<GameProfile>, ProxyError> {
+        let response = self.agent.get(&format!("{}/session/minecraft/hasJoined", self.session_server))
+            .query("username", username)
+            .query("serverId", server_hash)
+            .call()?;
+        // no content means the player didn't join
+        if response.status() == 204 {
+            return Ok(None);
+        }
+        let profile: serde_json::Value = serde_json::from_str(&response.into_string()?)?;
+        parse_profile(&profile).map(Some)
+    }
+}
+
+fn parse_profile(profile: &serde_json::Value) -> Result<GameProfile, ProxyError> {
+    let id = profile["id"].as_str().ok_or("profile without id")?;
+    let name = profile["name"].as_str().ok_or("profile without name")?;
+    let properties = match profile["properties"].as_array() {
+        Some(properties) => properties.iter()
+            .map(|property| Ok(ProfileProperty {
+                name: property["name"].as_str().ok_or("property without name")?.to_string(),
+                value: property["value"].as_str().ok_or("property without value")?.to_string(),
+                signature: property["signature"].as_str().map(str::to_string),
+            }))
+            .collect::<Result<_, ProxyError>>()?,
+        None => Vec::new()
+    };
+    Ok(GameProfile {
+        id: u128::from_str_radix(&id.replace('-', ""), 16)?,
+        name: name.to_string(),
+        properties,
+    })
+}
+
+/// Accepts every player without asking a session server, they get the uuid they would have in offline mode.
+/// Meant for tests and local setups.
+#[derive(Default)]
+pub struct LocalSessionVerifier;
+
+impl SessionVerifier for LocalSessionVerifier {
+    fn has_joined(&self, username: &str, _server_hash: &str) -> Result<Option<GameProfile>, ProxyError> {
+        Ok(Some(GameProfile {
+            id: offline_uuid(username),
+            name: username.to_string(),
+            properties: Vec::new(),
+        }))
+    }
+}
+
+/// The uuid an offline mode server gives a player, a version 3 uuid of "OfflinePlayer:<name>".
+pub fn offline_uuid(username: &str) -> u128 {
+    let mut hash: [u8; 16] = Md5::digest(format!("OfflinePlayer:{}", username)).into();
+    hash[6] = hash[6] & 0x0f | 0x30;
+    hash[8] = hash[8] & 0x3f | 0x80;
+    u128::from_be_bytes(hash)
+}
+
+/// Key pair and verifier used to authenticate clients when the proxy runs in online mode.
+pub(crate) struct Auth {
+    private_key: RsaPrivateKey,
+    /// DER encoded public key, as sent to clients.
+    public_key: Vec<u8>,
+    /// Logins waiting for a verifier thread, the threads stop once it is dropped.
+    logins: SyncSender<PendingLogin>,
+}
+
+/// A client that still has to be checked with the session server.
+struct PendingLogin {
+    username: String,
+    server_hash: String,
+    thread: Arc<PaxyThread>,
+    token: Token,
+}
+
+impl Auth {
+    pub(crate) fn new(verifier: Arc<dyn SessionVerifier>) -> Result<Auth, ProxyError> {
+        // vanilla uses 1024 bit keys as well
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+        let public_key = private_key.to_public_key().to_public_key_der()?.into_vec();
+
+        let (logins, receiver) = mpsc::sync_channel(VERIFIER_QUEUE);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..VERIFIER_THREADS {
+            let verifier = verifier.clone();
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name("paxy-auth".to_string())
+                .spawn(move || verify_logins(&*verifier, &receiver))?;
+        }
+        Ok(Auth { private_key, public_key, logins })
+    }
+
+    /// Asks the client to enable encryption instead of passing its LoginStart on to the backend.
+    pub(crate) fn start_login(&self, client: &mut ConnectionContext, packet: &c2s::login::LoginStart) {
+        let mut verify_token = [0u8; 4];
+        rand::thread_rng().fill_bytes(&mut verify_token);
+        client.verify_token = Some(verify_token);
+        client.username = Some(packet.name.clone());
+
+        client.send_packet(&s2c::login::EncryptionRequest {
+            server_id: String::new(),
+            public_key: to_indexed_vec(&self.public_key),
+            verify_token: to_indexed_vec(&verify_token),
+        });
+    }
+
+    /// Enables encryption and queues the client to be verified by a verifier thread,
+    /// the network thread gets a [`LoginVerified`] message once that is done.
+    /// The client is disconnected if too many logins are waiting already.
+    pub(crate) fn finish_login(&self, thread_ctx: &NetworkThreadContext, client: &mut ConnectionContext, packet: &c2s::login::EncryptionResponse) {
+        let (verify_token, username) = match (client.verify_token.take(), client.username.clone()) {
+            (Some(verify_token), Some(username)) => (verify_token, username),
+            _ => {
+                println!("unexpected encryption response");
+                client.should_close = true;
+                return;
+            }
+        };
+
+        let shared_secret = self.private_key.decrypt(Pkcs1v15Encrypt, packet.shared_secret.as_slice());
+        let returned_token = self.private_key.decrypt(Pkcs1v15Encrypt, packet.verify_token.as_slice());
+        let shared_secret = match (shared_secret, returned_token) {
+            (Ok(shared_secret), Ok(returned_token)) if returned_token == verify_token => shared_secret,
+            _ => {
+                println!("{} sent an invalid encryption response", username);
+                client.should_close = true;
+                return;
+            }
+        };
+        if !client.enable_encryption(&shared_secret) {
+            println!("{} sent an invalid shared secret", username);
+            client.should_close = true;
+            return;
+        }
+
+        let login = PendingLogin {
+            server_hash: server_hash(&shared_secret, &self.public_key),
+            username,
+            thread: thread_ctx.thread.clone(),
+            token: client.token_self,
+        };
+        if let Err(e) = self.logins.try_send(login) {
+            let (login, reason) = match e {
+                TrySendError::Full(login) => (login, "too many logins are waiting"),
+                TrySendError::Disconnected(login) => (login, "the verifier threads stopped"),
+            };
+            println!("could not verify {}: {}", login.username, reason);
+            client.send_packet(&s2c::login::Disconnect { reason: Component::text(SESSION_SERVER_DOWN) });
+            client.should_close = true;
+        }
+    }
+}
+
+/// Runs on a verifier thread until the [`Auth`] is dropped.
+fn verify_logins(verifier: &dyn SessionVerifier, logins: &Mutex<Receiver<PendingLogin>>) {
+    loop {
+        // the lock is only held while waiting, not while verifying
+        let login = match logins.lock().unwrap().recv() {
+            Ok(login) => login,
+            Err(_) => return
+        };
+        let result = match verifier.has_joined(&login.username, &login.server_hash) {
+            Ok(Some(profile)) => Ok(profile),
+            Ok(None) => Err(Box::new(Component::text(NOT_VERIFIED))),
+            Err(e) => {
+                println!("could not verify {}: {:?}", login.username, e);
+                Err(Box::new(Component::text(SESSION_SERVER_DOWN)))
+            }
+        };
+        // the network thread only stops when the proxy does
+        let _ = login.thread.notify(LoginVerified(login.token, result));
+    }
+}
+
+/// Answers the EncryptionRequest of a backend with a shared secret of the proxy, so the backend side
+/// is encrypted with its own key while the proxy keeps parsing it. The client never sees the request.
+/// A backend that checks players with a session server still refuses them, they never joined with its key.
+pub(crate) fn answer_encryption_request(backend: &mut ConnectionContext, packet: &s2c::login::EncryptionRequest) {
+    let mut rng = rand::thread_rng();
+    let mut shared_secret = [0u8; 16];
+    rng.fill_bytes(&mut shared_secret);
+
+    let public_key = match RsaPublicKey::from_public_key_der(packet.public_key.as_slice()) {
+        Ok(public_key) => public_key,
+        Err(e) => {
+            println!("{:?} sent an invalid public key: {:?}", backend.server, e);
+            backend.should_close = true;
+            return;
+        }
+    };
+    let encrypted_secret = public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &shared_secret);
+    let encrypted_token = public_key.encrypt(&mut rng, Pkcs1v15Encrypt, packet.verify_token.as_slice());
+    let (encrypted_secret, encrypted_token) = match (encrypted_secret, encrypted_token) {
+        (Ok(encrypted_secret), Ok(encrypted_token)) => (encrypted_secret, encrypted_token),
+        _ => {
+            println!("could not encrypt the shared secret for {:?}", backend.server);
+            backend.should_close = true;
+            return;
+        }
+    };
+
+    // the response itself isn't encrypted yet
+    backend.send_packet(&c2s::login::EncryptionResponse {
+        shared_secret: to_indexed_vec(&encrypted_secret),
+        verify_token: to_indexed_vec(&encrypted_token),
+    });
+    backend.enable_encryption(&shared_secret);
+}
+
+/// The server id sent to the session server, a sha1 digest printed as a signed hex number.
+fn server_hash(shared_secret: &[u8], public_key: &[u8]) -> String {
+    // the server id itself is always empty
+    let mut digest: [u8; 20] = Sha1::new()
+        .chain_update(shared_secret)
+        .chain_update(public_key)
+        .finalize()
+        .into();
+
+    let negative = digest[0] & 0x80 != 0;
+    if negative {
+        // two's complement
+        let mut carry = true;
+        for byte in digest.iter_mut().rev() {
+            *byte = !*byte;
+            if carry {
+                let (value, overflow) = byte.overflowing_add(1);
+                *byte = value;
+                carry = overflow;
+            }
+        }
+    }
+
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let hex = hex.trim_start_matches('0');
+    if negative {
+        format!("-{}", hex)
+    } else {
+        hex.to_string()
+    }
+}
+
+fn to_indexed_vec(bytes: &[u8]) -> IndexedVec<u8> {
+    let mut vec = IndexedVec::with_len(bytes.len());
+    vec.put_slice(bytes);
+    vec
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use mio::Token;
+    use utils::buffers::VarInts;
+    use utils::contexts::{Message, PaxyThread};
+    use utils::Packet;
+
+    use super::*;
+
+    #[test]
+    fn server_hash_matches_vanilla() {
+        // digests of the names alone, as listed on wiki.vg
+        assert_eq!(server_hash(b"Notch", b""), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
+        assert_eq!(server_hash(b"jeb_", b""), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+        assert_eq!(server_hash(b"simon", b""), "88e16a1019277b15d58faf0541e11910eb756f6");
+    }
+
+    #[test]
+    fn login_with_local_verifier() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        let mut client = ConnectionContext::new(Token(0), Token(1), mio::net::TcpStream::from_std(stream), true, true);
+
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let thread = Arc::new(PaxyThread { thread: thread::current(), channel: sender });
+        let thread_ctx = NetworkThreadContext { id: 0, connections: HashMap::new(), threads: Arc::new(vec![thread.clone()]), thread };
+
+        let auth = Auth::new(Arc::new(LocalSessionVerifier)).unwrap();
+        auth.start_login(&mut client, &c2s::login::LoginStart { name: "Steve".to_string() });
+
+        // the EncryptionRequest, without compression
+        peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut len = 0;
+        for shift in 0..3 {
+            let mut byte = [0u8; 1];
+            peer.read_exact(&mut byte).unwrap();
+            len |= (byte[0] as usize & 0x7f) << (7 * shift);
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut frame = vec![0u8; len];
+        peer.read_exact(&mut frame).unwrap();
+        let mut frame = &frame[..];
+        assert_eq!(frame.get_var_i32().unwrap().0, s2c::login::EncryptionRequest::get_id());
+        let request = s2c::login::EncryptionRequest::read(&mut frame).unwrap();
+        assert_eq!(request.public_key.as_slice(), auth.public_key.as_slice());
+
+        let shared_secret = [7u8; 16];
+        let public_key = RsaPublicKey::from_public_key_der(request.public_key.as_slice()).unwrap();
+        let mut rng = rand::thread_rng();
+        auth.finish_login(&thread_ctx, &mut client, &c2s::login::EncryptionResponse {
+            shared_secret: to_indexed_vec(&public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &shared_secret).unwrap()),
+            verify_token: to_indexed_vec(&public_key.encrypt(&mut rng, Pkcs1v15Encrypt, request.verify_token.as_slice()).unwrap()),
+        });
+        assert!(!client.should_close);
+        assert!(client.encryption.is_some());
+
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok(Message::LoginVerified(token, Ok(profile))) => {
+                assert_eq!(token, Token(0));
+                assert_eq!(profile.name, "Steve");
+                assert_eq!(profile.id, 0x5627dd98_e6be_3c21_b8a8_e92344183641);
+                assert_eq!(profile.id, offline_uuid("Steve"));
+            }
+            _ => panic!("expected a verified login"),
+        }
+    }
+}
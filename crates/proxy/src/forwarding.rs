@@ -0,0 +1,168 @@
+use bytes::BufMut;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use packets::c2s;
+use utils::buffers::{StringsMut, VarIntsMut};
+use utils::contexts::ConnectionContext;
+use utils::indexed_vec::IndexedVec;
+use utils::sendable::{InferLenVec, Vari32};
+
+use crate::auth::{offline_uuid, GameProfile};
+
+/// How backends learn the address and profile of the players behind the proxy.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Forwarding {
+    /// Backends see the address of the proxy and offline mode uuids.
+    #[default]
+    None,
+    /// BungeeCord's format, the address and profile are appended to the hostname of the handshake.
+    /// Spigot and Paper understand it with `bungeecord: true`.
+    Legacy,
+    /// Velocity's format, the backend asks for the player info during login and the answer is signed with the secret.
+    /// Paper understands it with `velocity-support` enabled and the same secret.
+    Modern { secret: Vec<u8> },
+}
+
+/// Login plugin channel backends ask for the player info on.
+pub(crate) const VELOCITY_CHANNEL: &str = "velocity:player_info";
+// the only version of the player info that exists for 1.16.5
+const VELOCITY_FORWARDING_VERSION: i32 = 1;
+
+/// The profile the backend should get for a client, the one the proxy verified or an offline mode one.
+pub(crate) fn profile(client: &ConnectionContext, username: &str) -> GameProfile {
+    client.profile.clone().unwrap_or_else(|| GameProfile {
+        id: offline_uuid(username),
+        name: username.to_string(),
+        properties: Vec::new(),
+    })
+}
+
+/// Login handshake for a client with its address and profile in BungeeCord's format,
+/// `host\0client ip\0uuid\0properties`, with the protocol version of the backend.
+pub(crate) fn legacy_handshake(client: &ConnectionContext, profile: &GameProfile, protocol_version: i32) -> c2s::handshake::HandshakePacket {
+    // anything appended by forge would end up in the wrong field
+    let hostname = client.hostname.as_deref().unwrap_or_default().split('\0').next().unwrap_or_default();
+    let client_ip = client.stream.as_ref().and_then(|stream| stream.peer_addr().ok()).map(|address| address.ip().to_string()).unwrap_or_default();
+    let properties: Vec<serde_json::Value> = profile.properties.iter()
+        .map(|property| match &property.signature {
+            Some(signature) => serde_json::json!({ "name": property.name, "value": property.value, "signature": signature }),
+            None => serde_json::json!({ "name": property.name, "value": property.value }),
+        })
+        .collect();
+
+    c2s::handshake::HandshakePacket {
+        protocol_version: Vari32 { val: protocol_version },
+        ip: format!("{}\0{}\0{:032x}\0{}", hostname, client_ip, profile.id, serde_json::Value::Array(properties)),
+        port: client.port,
+        next_state: Vari32 { val: packets::LOGIN_STATE as i32 },
+    }
+}
+
+/// Answer to the player info request of a backend, an HMAC-SHA256 signature followed by
+/// the version, client address, uuid, name and properties of the player.
+pub(crate) fn modern_response(client: &ConnectionContext, profile: &GameProfile, message_id: i32, secret: &[u8]) -> c2s::login::LoginPluginResponse {
+    let client_ip = client.stream.as_ref().and_then(|stream| stream.peer_addr().ok()).map(|address| address.ip().to_string()).unwrap_or_default();
+
+    let mut payload = IndexedVec::new();
+    payload.put_var_i32(VELOCITY_FORWARDING_VERSION);
+    payload.put_string(&client_ip);
+    payload.put_u128(profile.id);
+    payload.put_string(&profile.name);
+    payload.put_var_i32(profile.properties.len() as i32);
+    for property in profile.properties.iter() {
+        payload.put_string(&property.name);
+        payload.put_string(&property.value);
+        payload.put_u8(property.signature.is_some() as u8);
+        if let Some(signature) = &property.signature {
+            payload.put_string(signature);
+        }
+    }
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(payload.as_slice());
+    let mut data = IndexedVec::new();
+    data.put_slice(&mac.finalize().into_bytes());
+    data.put_slice(payload.as_slice());
+
+    c2s::login::LoginPluginResponse {
+        message_id: Vari32 { val: message_id },
+        successful: true,
+        data: InferLenVec { inner: data },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, TcpStream};
+
+    use mio::Token;
+    use utils::profile::ProfileProperty;
+    use utils::Packet;
+
+    use super::*;
+
+    /// A client connected from 127.0.0.1, which joined with `hostname`, and the other end of its connection.
+    fn client(hostname: &str) -> (ConnectionContext, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (peer, _) = listener.accept().unwrap();
+        let mut client = ConnectionContext::new(Token(0), Token(1), mio::net::TcpStream::from_std(stream), true, true);
+        client.hostname = Some(hostname.to_string());
+        client.port = 25565;
+        (client, peer)
+    }
+
+    fn steve() -> GameProfile {
+        GameProfile {
+            id: 0x5627dd98_e6be_3c21_b8a8_e92344183641,
+            name: "Steve".to_string(),
+            properties: vec![
+                ProfileProperty { name: "textures".to_string(), value: "abc".to_string(), signature: Some("sig".to_string()) },
+                ProfileProperty { name: "locale".to_string(), value: "en_us".to_string(), signature: None },
+            ],
+        }
+    }
+
+    #[test]
+    fn legacy_handshake_bytes() {
+        // forge appends its marker to the hostname
+        let (client, _peer) = client("play.example.com\0FML\0");
+        let handshake = legacy_handshake(&client, &steve(), 754);
+        let mut bytes = Vec::new();
+        handshake.write(&mut bytes).unwrap();
+
+        let host = concat!(
+            "play.example.com\0127.0.0.1\05627dd98e6be3c21b8a8e92344183641\0",
+            r#"[{"name":"textures","signature":"sig","value":"abc"},{"name":"locale","value":"en_us"}]"#,
+        );
+        let mut expected = vec![0xF2, 0x05];
+        expected.put_var_i32(host.len() as i32);
+        expected.extend_from_slice(host.as_bytes());
+        expected.extend_from_slice(&[0x63, 0xDD, 0x02]);
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn modern_response_bytes() {
+        let (client, _peer) = client("play.example.com");
+        let response = modern_response(&client, &steve(), 3, b"secret");
+        let mut bytes = Vec::new();
+        response.write(&mut bytes).unwrap();
+
+        let mut payload = vec![0x01, 0x09];
+        payload.extend_from_slice(b"127.0.0.1");
+        payload.extend_from_slice(&0x5627dd98_e6be_3c21_b8a8_e92344183641u128.to_be_bytes());
+        payload.extend_from_slice(b"\x05Steve\x02");
+        payload.extend_from_slice(b"\x08textures\x03abc\x01\x03sig");
+        payload.extend_from_slice(b"\x06locale\x05en_us\x00");
+        // message 3, successful, then the HMAC-SHA256 of the payload with the key "secret"
+        let mut expected = vec![
+            0x03, 0x01,
+            0x3A, 0x93, 0x00, 0xCC, 0x8B, 0x52, 0x01, 0x66, 0x70, 0xCE, 0xA7, 0x55, 0xA8, 0x43, 0x7A, 0xB3,
+            0xE1, 0x97, 0x1A, 0xA8, 0x5C, 0xA1, 0x38, 0x26, 0xEC, 0x86, 0x86, 0x94, 0xF6, 0xEB, 0xFD, 0xBE,
+        ];
+        expected.extend_from_slice(&payload);
+        assert_eq!(bytes, expected);
+    }
+}
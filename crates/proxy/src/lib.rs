@@ -1,10 +1,19 @@
+use std::error::Error;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
 
-use mio::{Events, Interest, Poll, Token};
-use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token, Waker};
+use mio::net::TcpListener;
+use libdeflater::CompressionLvl;
 
-use utils::contexts::Message::{Threads, NewConnection};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+
+use utils::chat::Component;
+use utils::contexts::Message::{Threads, NewConnection, Shutdown, SwitchServer};
 use utils::contexts::PaxyThread;
 use packet_transformation::handling::HandlingContext;
 use packets::{c2s, s2c};
@@ -12,107 +21,627 @@ use std::{sync, thread};
 use packet_transformation::TransformationResult::{Unchanged, Modified, Canceled};
 use utils::buffers::{Strings, StringsMut};
 
+use crate::auth::{Auth, MojangSessionVerifier, SessionVerifier};
+use crate::forwarding::Forwarding;
+use crate::networking::NetworkSettings;
+use crate::players::{Player, Players};
+use crate::routing::Routing;
+use crate::status::Status;
+
+pub use crate::status::load_favicon;
+
+pub mod auth;
+pub mod forwarding;
 mod networking;
+pub mod players;
+pub mod routing;
+mod status;
+mod switching;
+mod translation;
+
+// listeners use their index as token
+const WAKER_TOKEN: Token = Token(usize::MAX);
+
+/// Error type returned by the accept thread once it stops.
+pub type ProxyError = Box<dyn Error + Send + Sync>;
 
-fn register_packets(handler_context: &mut HandlingContext) {
-    handler_context.register_transformer(|_thread_ctx, connection_ctx, other_ctx, packet: &mut c2s::handshake::HandshakePacket| {
+/// Registers transformers on the [`HandlingContext`] before the proxy is started.
+///
+/// Implemented for every `Fn(&mut HandlingContext)`, so a closure can be passed to
+/// [`ProxyBuilder::register`] directly.
+pub trait Registrar {
+    fn register(&self, handler_context: &mut HandlingContext);
+}
+
+impl<F: Fn(&mut HandlingContext)> Registrar for F {
+    fn register(&self, handler_context: &mut HandlingContext) {
+        self(handler_context)
+    }
+}
+
+/// Optional builtin behaviour, everything is enabled by default.
+#[derive(Clone, Debug)]
+pub struct Features {
+    /// Prefixes the server brand shown in the F3 screen with "Paxy <-> ".
+    pub brand_rewrite: bool,
+    /// Lets players move themselves to another backend with `/server <name>`.
+    pub server_command: bool,
+}
+
+impl Default for Features {
+    fn default() -> Features {
+        Features {
+            brand_rewrite: true,
+            server_command: true,
+        }
+    }
+}
+
+/// Minecraft versions the backends can run, see [`ProxyBuilder::backend_version`].
+pub fn backend_versions() -> impl Iterator<Item = &'static str> {
+    packets::PROTOCOLS.iter().map(|protocol| protocol.minecraft_version)
+}
+
+fn register_packets(handler_context: &mut HandlingContext, features: &Features, settings: &Arc<NetworkSettings>) {
+    handler_context.register_packets(&packets::PACKETS);
+    for protocol in packets::PROTOCOLS.iter() {
+        handler_context.register_protocol(protocol);
+    }
+    for step in translation::steps() {
+        handler_context.register_translation(step);
+    }
+
+    let handshake_settings = settings.clone();
+    handler_context.register_transformer(move |_thread_ctx, connection_ctx, other_ctx, packet: &mut c2s::handshake::HandshakePacket| {
         connection_ctx.state = packet.next_state.val as u8;
         other_ctx.state = packet.next_state.val as u8;
+        connection_ctx.protocol_version = packet.protocol_version.val;
+        connection_ctx.protocol = packets::protocol(packet.protocol_version.val);
+        let (backend_version, backend_protocol) = handshake_settings.backend_protocol(connection_ctx);
+        other_ctx.protocol_version = backend_version;
+        other_ctx.protocol = backend_protocol;
+        connection_ctx.hostname = Some(packet.ip.clone());
+        connection_ctx.port = packet.port;
+        if handshake_settings.forwarding == Forwarding::Legacy && connection_ctx.state == packets::LOGIN_STATE {
+            // sent along with the LoginStart, once the profile of the client is known
+            return Canceled;
+        }
+        if backend_version != packet.protocol_version.val {
+            // everything after the handshake is translated
+            packet.protocol_version.val = backend_version;
+            return Modified;
+        }
+        Unchanged
+    });
+    handler_context.register_transformer(|_thread_ctx, connection_ctx, _other_ctx, packet: &mut c2s::login::LoginStart| {
+        connection_ctx.username = Some(packet.name.clone());
         Unchanged
     });
-    handler_context.register_transformer(|_thread_ctx, connection_ctx, other_ctx, _packet: &mut s2c::login::LoginSuccess| {
+    let login_settings = settings.clone();
+    handler_context.register_transformer(move |thread_ctx, connection_ctx, other_ctx, packet: &mut s2c::login::LoginSuccess| {
         connection_ctx.state = packets::PLAY_STATE;
         other_ctx.state = packets::PLAY_STATE;
-        Unchanged
+        // an offline mode backend doesn't know the real uuid of a player the proxy authenticated
+        let result = match &other_ctx.profile {
+            Some(profile) => {
+                packet.uuid = profile.id.into();
+                packet.username = profile.name.clone();
+                Modified
+            }
+            None => Unchanged
+        };
+        other_ctx.username = Some(packet.username.clone());
+        login_settings.players.insert(Player {
+            username: packet.username.clone(),
+            uuid: packet.uuid.into(),
+            server: connection_ctx.server.clone().unwrap_or_default(),
+            thread: thread_ctx.id,
+            token: other_ctx.token_self,
+        });
+        result
     });
     handler_context.register_transformer(|_thread_ctx, connection_ctx, other_ctx, packet: &mut s2c::login::SetCompression| {
         connection_ctx.compression_threshold = packet.threshold.val;
         other_ctx.compression_threshold = packet.threshold.val;
         Unchanged
     });
-    handler_context.register_transformer(|_thread_ctx, _connection_ctx, _other_ctx, packet: &mut s2c::play::PluginMessage| {
-        if packet.channel == "minecraft:brand" {
-            let string = packet.data.get_string();
-            packet.data.reset();
-            packet.data.put_string(&format!("Paxy <-> {}", string));
-            Modified
-        } else {
-            Unchanged
+    handler_context.register_transformer(|_thread_ctx, connection_ctx, _other_ctx, packet: &mut s2c::login::EncryptionRequest| {
+        // the backend side gets its own key, the client only ever encrypts with the key of the proxy
+        auth::answer_encryption_request(connection_ctx, packet);
+        Canceled
+    });
+    match &settings.auth {
+        Some(_) => {
+            let auth_settings = settings.clone();
+            handler_context.register_transformer(move |_thread_ctx, connection_ctx, _other_ctx, packet: &mut c2s::login::LoginStart| {
+                // the backend gets the LoginStart once the client is verified
+                auth_settings.auth.as_ref().unwrap().start_login(connection_ctx, packet);
+                Canceled
+            });
+            let auth_settings = settings.clone();
+            handler_context.register_transformer(move |thread_ctx, connection_ctx, _other_ctx, packet: &mut c2s::login::EncryptionResponse| {
+                auth_settings.auth.as_ref().unwrap().finish_login(thread_ctx, connection_ctx, packet);
+                Canceled
+            });
+        }
+        None => {
+            if settings.forwarding == Forwarding::Legacy {
+                handler_context.register_transformer(|_thread_ctx, connection_ctx, other_ctx, packet: &mut c2s::login::LoginStart| {
+                    // the LoginStart follows the handshake that was held back
+                    let profile = forwarding::profile(connection_ctx, &packet.name);
+                    other_ctx.send_packet(&forwarding::legacy_handshake(connection_ctx, &profile, other_ctx.protocol_version));
+                    Unchanged
+                });
+            }
         }
+    }
+    if let Forwarding::Modern { secret } = &settings.forwarding {
+        let secret = secret.clone();
+        handler_context.register_transformer(move |_thread_ctx, connection_ctx, other_ctx, packet: &mut s2c::login::LoginPluginRequest| {
+            if packet.channel != forwarding::VELOCITY_CHANNEL {
+                return Unchanged;
+            }
+            let username = match &other_ctx.username {
+                Some(username) => username.clone(),
+                None => return Unchanged
+            };
+            let profile = forwarding::profile(other_ctx, &username);
+            connection_ctx.send_packet(&forwarding::modern_response(other_ctx, &profile, packet.message_id.val, &secret));
+            Canceled
+        });
+    }
+    if features.brand_rewrite {
+        handler_context.register_transformer(|_thread_ctx, _connection_ctx, _other_ctx, packet: &mut s2c::play::PluginMessage| {
+            if packet.channel == "minecraft:brand" {
+                // a brand that isn't a string is passed on as it is
+                let string = match packet.data.get_string() {
+                    Ok(string) => string,
+                    Err(_) => return Unchanged
+                };
+                packet.data.reset();
+                packet.data.put_string(&format!("Paxy <-> {}", string));
+                Modified
+            } else {
+                Unchanged
+            }
+        });
+    }
+    if features.server_command {
+        let command_settings = settings.clone();
+        handler_context.register_transformer(move |thread_ctx, connection_ctx, _other_ctx, packet: &mut c2s::play::ChatMessage| {
+            let mut args = packet.message.split_whitespace();
+            if args.next() != Some("/server") {
+                return Unchanged;
+            }
+            match args.next() {
+                Some(server) => {
+                    // the switch needs the poll of the network thread, it picks the message up right after this read
+                    if thread_ctx.thread.channel.try_send(SwitchServer(connection_ctx.token_self, server.to_string())).is_err() {
+                        switching::send_message(connection_ctx, "The proxy is busy, try again later");
+                    }
+                }
+                None => {
+                    let current = connection_ctx.server.clone().unwrap_or_default();
+                    let servers = command_settings.routing.backend_names().join(", ");
+                    switching::send_message(connection_ctx, &format!("You are connected to {}, servers: {}", current, servers));
+                }
+            }
+            Canceled
+        });
+    }
+}
+
+fn spawn_thread(handler: Arc<HandlingContext>, id: usize, channel_size: usize, settings: Arc<NetworkSettings>) -> (PaxyThread, JoinHandle<()>) {
+    let (tx, rx) = sync::mpsc::sync_channel(channel_size);
+    let handle = thread::spawn(move || {
+        networking::thread_loop(rx, handler, id, settings);
     });
+    (PaxyThread { thread: handle.thread().clone(), channel: tx }, handle)
 }
 
-fn register_transformers(handler_context: &mut HandlingContext) {
-    /*handler_context.register_transformer(|_thread_ctx, _connection_ctx, _other_ctx, packet: &mut s2c::play::EntityPositionPacket| {
-        packet.delta_x = 0;
-        packet.delta_y = 100;
-        Modified
-    });*/
-    /*handler_context.register_transformer(|_thread_ctx, _connection_ctx, _other_ctx, _packet: &mut c2s::status::Ping| {
-        Canceled
-    });*/
+/// Configures and starts a proxy.
+///
+/// ```no_run
+/// use packet_transformation::TransformationResult::Modified;
+/// use packets::s2c;
+///
+/// let handle = proxy::ProxyBuilder::new("127.0.0.1:25566".parse()?)
+///     .backend("lobby", "127.0.0.1:25565".parse()?)
+///     .backend("survival", "127.0.0.1:25567".parse()?)
+///     .forced_host("survival.example.com", "survival")
+///     .register(|handler_context: &mut packet_transformation::handling::HandlingContext| {
+///         handler_context.register_transformer(|_thread_ctx, _connection_ctx, _other_ctx, packet: &mut s2c::play::EntityPositionPacket| {
+///             packet.delta_y = 100;
+///             Modified
+///         });
+///     })
+///     .start()?;
+/// handle.join()?;
+/// # Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+/// ```
+pub struct ProxyBuilder {
+    proxy_addresses: Vec<SocketAddr>,
+    routing: Routing,
+    thread_count: usize,
+    channel_size: usize,
+    compression_level: i32,
+    connect_timeout: Duration,
+    connect_attempts: u32,
+    backend_unavailable_message: String,
+    shutdown_message: String,
+    online_mode: bool,
+    session_verifier: Option<Arc<dyn SessionVerifier>>,
+    forwarding: Forwarding,
+    status_passthrough: bool,
+    motd: String,
+    max_players: u32,
+    favicon: Option<PathBuf>,
+    features: Features,
+    backend_version: Option<String>,
+    registrars: Vec<Box<dyn Registrar>>,
 }
 
-fn spawn_thread(handler: Arc<HandlingContext>, id: usize) -> PaxyThread {
-    // todo adjust? this prob isnt enough
-    let (tx, rx) = sync::mpsc::sync_channel(1000);
-    let thread = thread::spawn(move || {
-        networking::thread_loop(rx, handler, id);
-    });
-    PaxyThread { thread, channel: tx }
+impl ProxyBuilder {
+    pub fn new(proxy_address: SocketAddr) -> ProxyBuilder {
+        ProxyBuilder {
+            proxy_addresses: vec![proxy_address],
+            routing: Routing::default(),
+            thread_count: num_cpus::get() * 2,
+            channel_size: 1000,
+            compression_level: 1,
+            connect_timeout: Duration::from_secs(5),
+            connect_attempts: 3,
+            backend_unavailable_message: "Could not connect to the server".to_string(),
+            shutdown_message: "Proxy is shutting down".to_string(),
+            online_mode: false,
+            session_verifier: None,
+            forwarding: Forwarding::None,
+            status_passthrough: false,
+            motd: Component::text("A Paxy proxy").to_json(),
+            max_players: 100,
+            favicon: None,
+            features: Features::default(),
+            backend_version: None,
+            registrars: Vec::new(),
+        }
+    }
+
+    /// Accepts connections on another address as well.
+    pub fn listen(mut self, proxy_address: SocketAddr) -> ProxyBuilder {
+        self.proxy_addresses.push(proxy_address);
+        self
+    }
+
+    /// Adds a backend server, the first one added is the default one.
+    pub fn backend(mut self, name: impl Into<String>, address: SocketAddr) -> ProxyBuilder {
+        self.routing.add_backend(name, address);
+        self
+    }
+
+    /// Sets the backend used for hostnames without a forced host.
+    pub fn default_backend(mut self, name: impl Into<String>) -> ProxyBuilder {
+        self.routing.set_default(name);
+        self
+    }
+
+    /// Sends clients that connect through `hostname` to `backend` instead of the default backend.
+    pub fn forced_host(mut self, hostname: &str, backend: impl Into<String>) -> ProxyBuilder {
+        self.routing.add_forced_host(hostname, backend);
+        self
+    }
+
+    /// Sets the amount of network threads, defaults to twice the amount of cpus.
+    pub fn threads(mut self, thread_count: usize) -> ProxyBuilder {
+        self.thread_count = thread_count.max(1);
+        self
+    }
+
+    /// Sets the capacity of the message queue of every network thread, defaults to 1000.
+    pub fn channel_size(mut self, channel_size: usize) -> ProxyBuilder {
+        self.channel_size = channel_size;
+        self
+    }
+
+    /// Sets the libdeflate level (0-12) used to recompress modified packets, defaults to 1.
+    pub fn compression_level(mut self, compression_level: i32) -> ProxyBuilder {
+        self.compression_level = compression_level;
+        self
+    }
+
+    /// Sets how long a single attempt at connecting to the backend may take, defaults to 5 seconds.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> ProxyBuilder {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Sets how many times connecting to the backend is tried before the client is disconnected, defaults to 3.
+    pub fn connect_attempts(mut self, connect_attempts: u32) -> ProxyBuilder {
+        self.connect_attempts = connect_attempts.max(1);
+        self
+    }
+
+    /// Sets the message clients are disconnected with when the backend can't be reached.
+    pub fn backend_unavailable_message(mut self, backend_unavailable_message: impl Into<String>) -> ProxyBuilder {
+        self.backend_unavailable_message = backend_unavailable_message.into();
+        self
+    }
+
+    /// Sets the message clients are disconnected with when the proxy shuts down.
+    pub fn shutdown_message(mut self, shutdown_message: impl Into<String>) -> ProxyBuilder {
+        self.shutdown_message = shutdown_message.into();
+        self
+    }
+
+    /// Authenticates clients on the proxy, the backends should run in offline mode then. Off by default.
+    pub fn online_mode(mut self, online_mode: bool) -> ProxyBuilder {
+        self.online_mode = online_mode;
+        self
+    }
+
+    /// Sets how clients are verified in online mode, defaults to [`MojangSessionVerifier`].
+    pub fn session_verifier<V: 'static + SessionVerifier>(mut self, session_verifier: V) -> ProxyBuilder {
+        self.session_verifier = Some(Arc::new(session_verifier));
+        self
+    }
+
+    /// Sets how the backends learn the address and profile of players, they only see the proxy by default.
+    pub fn forwarding(mut self, forwarding: Forwarding) -> ProxyBuilder {
+        self.forwarding = forwarding;
+        self
+    }
+
+    /// Forwards server list pings to the backend instead of answering them on the proxy.
+    pub fn status_passthrough(mut self, status_passthrough: bool) -> ProxyBuilder {
+        self.status_passthrough = status_passthrough;
+        self
+    }
+
+    /// Sets the description shown in the server list, as chat json.
+    pub fn motd(mut self, motd: impl Into<String>) -> ProxyBuilder {
+        self.motd = motd.into();
+        self
+    }
+
+    /// Sets the maximum amount of players shown in the server list, defaults to 100.
+    pub fn max_players(mut self, max_players: u32) -> ProxyBuilder {
+        self.max_players = max_players;
+        self
+    }
+
+    /// Sets the icon shown in the server list, a 64x64 png.
+    pub fn favicon(mut self, favicon: impl Into<PathBuf>) -> ProxyBuilder {
+        self.favicon = Some(favicon.into());
+        self
+    }
+
+    pub fn features(mut self, features: Features) -> ProxyBuilder {
+        self.features = features;
+        self
+    }
+
+    /// Sets the Minecraft version the backends run, like "1.16.5". Clients on newer versions get their packets translated,
+    /// older ones are refused. By default every backend is joined with the version of the client.
+    ///
+    /// Needs the `translation` feature, block states and items keep the ids of the backend version for now.
+    pub fn backend_version(mut self, minecraft_version: impl Into<String>) -> ProxyBuilder {
+        self.backend_version = Some(minecraft_version.into());
+        self
+    }
+
+    /// Adds a registrar, they are run in order after the builtin transformers.
+    pub fn register<R: 'static + Registrar>(mut self, registrar: R) -> ProxyBuilder {
+        self.registrars.push(Box::new(registrar));
+        self
+    }
+
+    /// Binds the listener, spawns the network threads and starts accepting connections.
+    pub fn start(self) -> Result<ProxyHandle, ProxyError> {
+        println!("Starting Paxy");
+        self.routing.validate()?;
+        let compression_level = CompressionLvl::new(self.compression_level)
+            .map_err(|_| format!("invalid compression level {}, expected 0-12", self.compression_level))?;
+        let status = Status {
+            passthrough: self.status_passthrough,
+            motd: Component::from_json(&self.motd).map_err(|e| format!("invalid motd: {}", e))?,
+            max_players: self.max_players,
+            favicon: self.favicon.as_deref().map(status::load_favicon).transpose()?,
+        };
+        let backend_protocol = match &self.backend_version {
+            Some(_) if !cfg!(feature = "translation") => {
+                return Err("a backend version needs the translation feature".into());
+            }
+            Some(version) => match packets::PROTOCOLS.iter().find(|protocol| protocol.minecraft_version == version) {
+                Some(protocol) => Some(protocol),
+                None => {
+                    let versions: Vec<&str> = backend_versions().collect();
+                    return Err(format!("unsupported backend version {}, expected one of {}", version, versions.join(", ")).into());
+                }
+            },
+            None => None
+        };
+        let auth = if self.online_mode {
+            let verifier = self.session_verifier.unwrap_or_else(|| Arc::new(MojangSessionVerifier::default()));
+            Some(Auth::new(verifier)?)
+        } else {
+            None
+        };
+
+        // Create TCP servers
+        let mut listeners = Vec::with_capacity(self.proxy_addresses.len());
+        let mut local_addresses = Vec::with_capacity(self.proxy_addresses.len());
+        for proxy_address in self.proxy_addresses.iter() {
+            let listener = TcpListener::bind(*proxy_address)?;
+            local_addresses.push(listener.local_addr()?);
+            listeners.push(listener);
+        }
+
+        let settings = Arc::new(NetworkSettings {
+            compression_level,
+            connect_timeout: self.connect_timeout,
+            connect_attempts: self.connect_attempts,
+            backend_unavailable_reason: Component::text(self.backend_unavailable_message.as_str()),
+            routing: self.routing,
+            players: Players::default(),
+            auth,
+            forwarding: self.forwarding,
+            status,
+            backend_protocol,
+        });
+
+        // Registering
+        let mut handler_context = HandlingContext::new();
+        register_packets(&mut handler_context, &self.features, &settings);
+        for registrar in self.registrars.iter() {
+            registrar.register(&mut handler_context);
+        }
+        let handler_context = Arc::new(handler_context);
+
+        // Setup network threads
+        let mut threads = Vec::with_capacity(self.thread_count);
+        let mut handles = Vec::with_capacity(self.thread_count);
+        for thread in 0..self.thread_count {
+            let (paxy_thread, handle) = spawn_thread(handler_context.clone(), thread, self.channel_size, settings.clone());
+            threads.push(Arc::new(paxy_thread));
+            handles.push(handle);
+        }
+        // Finalize the thread list
+        let threads = Arc::new(threads);
+
+        for thread in threads.iter() {
+            thread.notify(Threads(threads.clone()))?
+        }
+
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER_TOKEN)?);
+
+        let reason = Box::new(Component::text(self.shutdown_message.as_str()));
+        let network_threads = threads.clone();
+        let thread = thread::Builder::new()
+            .name("paxy-accept".to_string())
+            .spawn(move || {
+                let result = accept_loop(poll, listeners, &network_threads);
+
+                // the listeners are closed at this point, stop the network threads
+                for thread in network_threads.iter() {
+                    // a thread that already stopped can't receive it
+                    let _ = thread.notify(Shutdown(reason.clone()));
+                }
+                for handle in handles {
+                    if handle.join().is_err() {
+                        println!("network thread panicked");
+                    }
+                }
+                println!("Paxy Stopped");
+                result
+            })?;
+
+        println!("Paxy Started");
+        Ok(ProxyHandle { local_addresses, settings, threads, waker, thread })
+    }
+}
+
+/// Handle to a running proxy.
+pub struct ProxyHandle {
+    local_addresses: Vec<SocketAddr>,
+    settings: Arc<NetworkSettings>,
+    threads: Arc<Vec<Arc<PaxyThread>>>,
+    waker: Arc<Waker>,
+    thread: JoinHandle<Result<(), ProxyError>>,
 }
 
-//todo use generics over dynamic dispatch
-pub fn start(proxy_address: SocketAddr, server_address: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Starting Paxy");
-    // Create TCP server
-    let mut listener = TcpListener::bind(proxy_address)?;
+impl ProxyHandle {
+    /// Addresses the proxy is listening on.
+    pub fn local_addresses(&self) -> &[SocketAddr] {
+        &self.local_addresses
+    }
+
+    /// Players that finished logging in.
+    pub fn players(&self) -> &Players {
+        &self.settings.players
+    }
+
+    /// Moves a player to the backend called `server` without disconnecting it.
+    /// The switch happens in the background, the player is told in chat if it fails.
+    pub fn switch_server(&self, username: &str, server: &str) -> Result<(), ProxyError> {
+        if self.settings.routing.backend(server).is_none() {
+            return Err(format!("there is no backend called {}", server).into());
+        }
+        let player = self.settings.players.get(username)
+            .ok_or_else(|| format!("{} is not online", username))?;
+        self.threads[player.thread].notify(SwitchServer(player.token, server.to_string()))
+            .map_err(|_| "network thread stopped")?;
+        Ok(())
+    }
 
-    // Registering
-    let mut handler_context = HandlingContext::new();
-    register_packets(&mut handler_context);
-    register_transformers(&mut handler_context);
-    let handler_context = Arc::new(handler_context);
+    /// Stops accepting connections, disconnects every client and waits for the network threads to finish.
+    pub fn shutdown(self) -> Result<(), ProxyError> {
+        self.waker.wake()?;
+        self.join()
+    }
 
-    // Setup network threads
-    let thread_count = num_cpus::get() * 2;
-    let mut threads = Vec::with_capacity(thread_count);
-    for thread in 0..thread_count {
-        let paxy_thread = spawn_thread(handler_context.clone(), thread);
-        threads.push(Arc::new(paxy_thread));
+    /// Shuts the proxy down once the process receives SIGINT or SIGTERM.
+    /// Use [`ProxyHandle::join`] to wait for it to finish.
+    pub fn shutdown_on_signals(&self) -> Result<(), ProxyError> {
+        let mut signals = Signals::new([SIGINT, SIGTERM])?;
+        let waker = self.waker.clone();
+        thread::Builder::new()
+            .name("paxy-signals".to_string())
+            .spawn(move || {
+                if let Some(signal) = signals.forever().next() {
+                    println!("Received signal {}, shutting down", signal);
+                    if let Err(e) = waker.wake() {
+                        println!("could not wake the accept thread: {:?}", e);
+                    }
+                }
+            })?;
+        Ok(())
     }
-    // Finalize the thread list
-    let threads = Arc::new(threads);
 
-    for thread in threads.iter() {
-        thread.notify(Threads(threads.clone()))?
+    /// Blocks until the proxy stops.
+    pub fn join(self) -> Result<(), ProxyError> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(_) => Err("accept thread panicked".into())
+        }
     }
+}
 
+// handles accepting connections and messages a thread about it
+fn accept_loop(mut poll: Poll, mut listeners: Vec<TcpListener>, threads: &[Arc<PaxyThread>]) -> Result<(), ProxyError> {
     let mut next_thread = 0usize;
 
     let mut events = Events::with_capacity(128);
-    let mut poll = Poll::new().expect("could not unwrap poll");
 
-    let listener_token = Token(0);
-    poll.registry().register(&mut listener, listener_token, Interest::READABLE).unwrap();
+    // listener tokens are their index
+    for (index, listener) in listeners.iter_mut().enumerate() {
+        poll.registry().register(listener, Token(index), Interest::READABLE)?;
+    }
 
-    println!("Paxy Started");
-    // handles accepting connections and messages a thread about it
     loop {
-        poll.poll(&mut events, None).expect("couldn't poll");
+        poll.poll(&mut events, None)?;
         for event in events.iter() {
-            if event.token() == listener_token {
-                loop {
-                    if let Ok((client_socket, _)) = listener.accept() {
-                        // New client, bind it to a thread
-                        threads[next_thread].notify(NewConnection(client_socket, TcpStream::connect(server_address)?))?;
-                        next_thread += 1;
-                        next_thread %= thread_count;
-                    } else {
-                        break;
-                    }
+            if event.token() == WAKER_TOKEN {
+                // shutdown requested
+                return Ok(());
+            }
+            if let Some(listener) = listeners.get(event.token().0) {
+                while let Ok((client_socket, _)) = listener.accept() {
+                    // New client, bind it to a thread
+                    threads[next_thread].notify(NewConnection(client_socket))
+                        .map_err(|_| "network thread stopped")?;
+                    next_thread += 1;
+                    next_thread %= threads.len();
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Starts a proxy with only the builtin transformers and blocks until it is stopped by a signal.
+pub fn start(proxy_address: SocketAddr, server_address: SocketAddr) -> Result<(), ProxyError> {
+    let handle = ProxyBuilder::new(proxy_address)
+        .backend("default", server_address)
+        .start()?;
+    handle.shutdown_on_signals()?;
+    handle.join()
+}
@@ -1,23 +1,91 @@
 use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::mpsc::Receiver;
 use std::time::{Duration, Instant};
 
 use libdeflater::{CompressionLvl, Compressor, Decompressor};
-use mio::{Events, Poll};
+use mio::{Events, Interest, Poll, Token};
+use mio::net::TcpStream;
 
 use packet_transformation::handling::{HandlingContext, UnparsedPacket};
 use packet_transformation::TransformationResult;
 use utils::buffer_helpers::{buffer_read, copy_slice_to, read_frame, write_socket, write_socket0};
 use utils::buffer_helpers::{compress_packet, decompress_packet, get_needed_data};
+use utils::chat::Component;
 use utils::buffers::{VarInts, VarIntsMut};
 use utils::contexts::{ConnectionContext, Message, NetworkThreadContext};
-use utils::contexts::Message::{NewConnection, Threads};
+use utils::contexts::Message::{LoginVerified, NewConnection, Shutdown, SwitchServer, Threads};
+use utils::error::DecodeError;
+use utils::profile::GameProfile;
+use utils::protocol::ProtocolIds;
+use packets::{c2s, s2c};
+use utils::Packet;
+
+use crate::auth::Auth;
+use crate::forwarding::{self, Forwarding};
+use crate::players::Players;
+use crate::routing::Routing;
+use crate::status::{self, Status};
+use crate::switching;
 use utils::indexed_vec::IndexedVec;
 
+/// Settings shared by every network thread.
+pub struct NetworkSettings {
+    pub compression_level: CompressionLvl,
+    /// How long a single attempt at connecting to the backend may take.
+    pub connect_timeout: Duration,
+    /// How many times connecting to the backend is tried before giving up.
+    pub connect_attempts: u32,
+    /// Sent to clients in the login state when the backend can't be reached.
+    pub backend_unavailable_reason: Component,
+    pub routing: Routing,
+    /// Players that finished logging in, shared with the [`crate::ProxyHandle`].
+    pub players: Players,
+    /// Set when the proxy runs in online mode.
+    pub(crate) auth: Option<Auth>,
+    pub forwarding: Forwarding,
+    pub(crate) status: Status,
+    /// Version the backends run, `None` if they are joined with the version of each client.
+    pub backend_protocol: Option<&'static ProtocolIds>,
+}
+
+impl NetworkSettings {
+    /// Protocol version the proxy talks to the backend of `client` with, the backend version if the client's one can be translated to it.
+    pub(crate) fn backend_protocol(&self, client: &ConnectionContext) -> (i32, Option<&'static ProtocolIds>) {
+        match self.backend_protocol {
+            Some(backend) if client.protocol.is_some() && client.protocol_version > backend.protocol_version => (backend.protocol_version, Some(backend)),
+            _ => (client.protocol_version, client.protocol)
+        }
+    }
+}
+
+// delay between two attempts at connecting to the backend
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+// how long a legacy ping waits for the byte after 0xFE, beta clients never send one
+const LEGACY_PING_DELAY: Duration = Duration::from_millis(100);
+
+/// A backend socket that hasn't finished connecting yet.
+pub(crate) struct PendingConnect {
+    pub(crate) address: SocketAddr,
+    pub(crate) attempts: u32,
+    pub(crate) deadline: Instant,
+    pub(crate) retry_at: Option<Instant>,
+}
+
+/// Buffers and (de)compressors reused for every connection of a thread.
+pub(crate) struct ThreadBuffers {
+    pub(crate) packet_buf: IndexedVec<u8>,
+    pub(crate) caching_buf: IndexedVec<u8>,
+    pub(crate) compression_buf: IndexedVec<u8>,
+    pub(crate) decompressor: Decompressor,
+    pub(crate) compressor: Compressor,
+}
+
 /// Start network thread loop.
 /// Responsible for parsing and transforming every out/incoming packets.
-pub fn thread_loop(rx: Receiver<Message>, handler: Arc<HandlingContext>, id: usize) {
+pub fn thread_loop(rx: Receiver<Message>, handler: Arc<HandlingContext>, id: usize, settings: Arc<NetworkSettings>) {
     // Create thread context
     let mut thread_ctx = {
         let connections = HashMap::new();
@@ -31,6 +99,7 @@ pub fn thread_loop(rx: Receiver<Message>, handler: Arc<HandlingContext>, id: usi
         let thread = threads[id].clone();
 
         NetworkThreadContext {
+            id,
             connections,
             threads,
             thread,
@@ -46,16 +115,17 @@ pub fn thread_loop(rx: Receiver<Message>, handler: Arc<HandlingContext>, id: usi
     let min_delay = Duration::from_millis(10);
 
     //Per thread buffers
-    let mut packet_buf = IndexedVec::new();
-    utils::set_vec_len(&mut packet_buf.vec, 2048);
-    let mut compression_buf = IndexedVec::new();
-    utils::set_vec_len(&mut compression_buf.vec, 2048);
-    let mut caching_buf = IndexedVec::new();
-    utils::set_vec_len(&mut caching_buf.vec, 2048);
-
-    let mut decompressor = Decompressor::new();
-    let mut compressor = Compressor::new(CompressionLvl::fastest());
+    let mut buffers = ThreadBuffers {
+        packet_buf: IndexedVec::with_len(2048),
+        caching_buf: IndexedVec::with_len(2048),
+        compression_buf: IndexedVec::with_len(2048),
+        decompressor: Decompressor::new(),
+        compressor: Compressor::new(settings.compression_level),
+    };
 
+    let mut pending_connects = HashMap::new();
+    // clients that only sent 0xFE so far, with when it arrived
+    let mut pending_legacy_pings = HashMap::new();
     let mut id_counter = 0;
 
     // Start parsing loop
@@ -63,37 +133,91 @@ pub fn thread_loop(rx: Receiver<Message>, handler: Arc<HandlingContext>, id: usi
         let ins = Instant::now();
         poll.poll(&mut events, Some(max_delay)).expect("couldn't poll");
         for event in events.iter() {
+            // connecting sockets are handled by update_connects
+            if pending_connects.contains_key(&event.token()) {
+                continue;
+            }
             // FIXME: I used remove to get around the borrow checker hopefully there is a better way. also i assume this is slower.
             if let Some(mut player) = thread_ctx.connections.remove(&event.token()) {
                 if event.is_writable() {
                     process_write(&mut player);
                 }
-                if event.is_readable() {
-                    let mut other = thread_ctx.connections.remove(&player.token_other).unwrap();
-                    process_read(&mut thread_ctx, &mut player, &mut other, &mut packet_buf, &mut caching_buf, handler.clone(), &mut compression_buf, &mut decompressor, &mut compressor);
+                // a connection that is about to close doesn't read anything else
+                if event.is_readable() && !player.close_when_flushed {
+                    if let Some(mut other) = thread_ctx.connections.remove(&player.token_other) {
+                        if switching::is_switching(&player, &other) {
+                            switching::process_read(&mut thread_ctx, &mut player, &mut other, &mut buffers, &handler, &settings);
+                        } else {
+                            process_read(&mut thread_ctx, &mut player, &mut other, &mut buffers, &handler);
+                        }
 
-                    thread_ctx.connections.insert(player.token_other.clone(), other);
+                        thread_ctx.connections.insert(player.token_other, other);
+                    } else if !player.inbound {
+                        // the client of this backend is gone
+                        player.should_close = true;
+                    } else if player.state == packets::STATUS_STATE {
+                        // the proxy answers the ping itself
+                        status::process_read(&mut player, &mut buffers, &settings);
+                    } else {
+                        // the backend gets picked once the handshake arrives
+                        if let Some((mut other, pending_connect)) = process_handshake(&mut player, &mut buffers, &poll, &settings) {
+                            pending_connects.insert(other.token_self, pending_connect);
+                            process_read(&mut thread_ctx, &mut player, &mut other, &mut buffers, &handler);
+
+                            thread_ctx.connections.insert(player.token_other, other);
+                        } else if player.state == packets::STATUS_STATE {
+                            status::process_read(&mut player, &mut buffers, &settings);
+                        } else if status::is_legacy_ping(player.read_buffering.as_slice()) {
+                            pending_legacy_pings.entry(player.token_self).or_insert_with(Instant::now);
+                        }
+                    }
                 }
 
+                if player.close_when_flushed && player.write_buffering.readable_bytes() == 0 {
+                    player.should_close = true;
+                }
                 if player.should_close {
                     // Connection socket is not active anymore, remove context
-                    thread_ctx.connections.remove(&player.token_other);
+                    close(&mut thread_ctx, player, &settings);
                     continue;
                 }
 
-                thread_ctx.connections.insert(player.token_self.clone(), player);
+                thread_ctx.connections.insert(player.token_self, player);
             }
         }
 
+        update_connects(&mut thread_ctx, &mut pending_connects, &poll, &settings);
+        update_legacy_pings(&mut thread_ctx, &mut pending_legacy_pings, &settings);
+
         // Process all incoming messages
         for msg in rx.try_iter() {
             match msg {
-                NewConnection(c2s, s2c) => {
+                NewConnection(c2s) => {
                     // New connection has been associated to this thread
                     println!("Player connection");
-                    // Create connection context
-                    ConnectionContext::create_pair(id_counter, c2s, s2c, &poll, &mut thread_ctx.connections);
+                    // Create connection context, the backend token is reserved for later
+                    let mut client = ConnectionContext::new(Token(id_counter * 2), Token(id_counter * 2 + 1), c2s, true, true);
                     id_counter += 1;
+                    if let Err(e) = client.register(&poll) {
+                        println!("could not register client: {:?}", e);
+                        continue;
+                    }
+                    thread_ctx.connections.insert(client.token_self, client);
+                }
+                SwitchServer(client_token, server) => {
+                    let token = Token(id_counter * 2 + 1);
+                    if let Some((backend, pending_connect)) = switching::start(&mut thread_ctx, client_token, &server, token, &poll, &settings) {
+                        id_counter += 1;
+                        pending_connects.insert(token, pending_connect);
+                        thread_ctx.connections.insert(token, backend);
+                    }
+                }
+                LoginVerified(client_token, result) => {
+                    login_verified(&mut thread_ctx, client_token, result, &settings);
+                }
+                Shutdown(reason) => {
+                    shutdown(&mut thread_ctx, &mut poll, &mut events, reason);
+                    return;
                 }
                 _ => { println!("got unexpected message"); }
             }
@@ -106,26 +230,380 @@ pub fn thread_loop(rx: Receiver<Message>, handler: Arc<HandlingContext>, id: usi
     }
 }
 
+/// Waits for the handshake of a client that has no backend yet and starts connecting to the backend it asked for.
+/// The handshake is left in the read buffer so it goes through the transformers like every other packet.
+/// Pings the proxy answers itself don't get a backend, the client is left in the status state instead.
+fn process_handshake(client: &mut ConnectionContext, buffers: &mut ThreadBuffers, poll: &Poll, settings: &NetworkSettings) -> Option<(ConnectionContext, PendingConnect)> {
+    let read_buf = &mut buffers.packet_buf;
+    read_buf.reset();
+
+    get_needed_data(read_buf, client);
+    if client.should_close {
+        return None;
+    }
+    if status::is_legacy_ping(read_buf.as_slice()) {
+        if read_buf.readable_bytes() < 2 {
+            // 1.4 to 1.6 follow 0xFE with 0x01, wait for it before picking the format, see update_legacy_pings
+            buffer_read(client, read_buf);
+            return None;
+        }
+        status::answer_legacy_ping(client, read_buf.as_slice(), settings);
+        return None;
+    }
+
+    let readable = read_buf.readable_bytes();
+    let (handshake, handshake_len) = match read_frame(read_buf, 0, readable, client) {
+        Some((packet_len, packet_len_bytes_red)) if readable >= packet_len_bytes_red + packet_len => {
+            let mut working_buf = &read_buf.vec[packet_len_bytes_red..packet_len_bytes_red + packet_len];
+            let handshake = working_buf.get_var_i32().and_then(|(id, _id_bytes)| {
+                if id != c2s::handshake::HandshakePacket::get_id() {
+                    return Err(DecodeError::InvalidValue(format!("expected a handshake, got packet {:#04x}", id)));
+                }
+                c2s::handshake::HandshakePacket::read(&mut working_buf)
+            });
+            match handshake {
+                Ok(handshake) => (handshake, packet_len_bytes_red + packet_len),
+                Err(e) => {
+                    println!("malformed handshake: {}", e);
+                    client.should_close = true;
+                    return None;
+                }
+            }
+        }
+        _ => {
+            // wait for the rest of the handshake
+            read_buf.reset_reader();
+            buffer_read(client, read_buf);
+            return None;
+        }
+    };
+    if handshake.next_state.val as u8 == packets::LOGIN_STATE {
+        let protocol_version = handshake.protocol_version.val;
+        // clients older than the backends can't be translated
+        let oldest = settings.backend_protocol.map_or(0, |backend| backend.protocol_version);
+        let versions: Vec<&str> = packets::PROTOCOLS.iter()
+            .filter(|protocol| protocol.protocol_version >= oldest)
+            .map(|protocol| protocol.minecraft_version)
+            .collect();
+        if packets::protocol(protocol_version).is_none() || protocol_version < oldest {
+            client.state = packets::LOGIN_STATE;
+            client.send_packet(&s2c::login::Disconnect { reason: Component::text(format!("Unsupported version, please join with {}", versions.join(", "))) });
+            client.should_close = true;
+            return None;
+        }
+    }
+    if handshake.next_state.val as u8 == packets::STATUS_STATE && !settings.status.passthrough {
+        client.state = packets::STATUS_STATE;
+        client.protocol_version = handshake.protocol_version.val;
+        client.protocol = packets::protocol(handshake.protocol_version.val);
+        client.hostname = Some(handshake.ip);
+        client.port = handshake.port;
+        // whatever follows the handshake is for the proxy
+        read_buf.set_reader_index(handshake_len);
+        buffer_read(client, read_buf);
+        return None;
+    }
+    read_buf.reset_reader();
+    buffer_read(client, read_buf);
+
+    let (name, address) = settings.routing.route(&handshake.ip);
+    println!("Routing {:?} to {}", handshake.ip, name);
+    client.server = Some(name.to_string());
+    let (mut backend, pending_connect) = match connect(client.token_other, client.token_self, address, settings) {
+        Some(connect) => connect,
+        None => {
+            if handshake.next_state.val as u8 == packets::LOGIN_STATE {
+                client.state = packets::LOGIN_STATE;
+                client.send_packet(&s2c::login::Disconnect { reason: settings.backend_unavailable_reason.clone() });
+            }
+            client.should_close = true;
+            return None;
+        }
+    };
+    if let Err(e) = backend.register(poll) {
+        println!("could not register backend: {:?}", e);
+        client.should_close = true;
+        return None;
+    }
+    backend.server = Some(name.to_string());
+
+    Some((backend, pending_connect))
+}
+
+/// Lets the client log in to its backend once the proxy verified it, or disconnects it.
+fn login_verified(thread_ctx: &mut NetworkThreadContext, client_token: Token, result: Result<GameProfile, Box<Component>>, settings: &NetworkSettings) {
+    let mut client = match thread_ctx.connections.remove(&client_token) {
+        Some(client) => client,
+        // the client left in the meantime
+        None => return
+    };
+    match result {
+        Ok(profile) => {
+            println!("Authenticated {}", profile.name);
+            if let Some(backend) = thread_ctx.connections.get_mut(&client.token_other) {
+                if settings.forwarding == Forwarding::Legacy {
+                    backend.send_packet(&forwarding::legacy_handshake(&client, &profile, backend.protocol_version));
+                }
+                backend.send_packet(&c2s::login::LoginStart { name: profile.name.clone() });
+            }
+            client.profile = Some(profile);
+            thread_ctx.connections.insert(client_token, client);
+        }
+        Err(reason) => {
+            client.send_packet(&s2c::login::Disconnect { reason: *reason });
+            close(thread_ctx, client, settings);
+        }
+    }
+}
+
+/// Starts connecting to the backend, the context still needs to be registered.
+/// An attempt that fails right away is retried by [`update_connects`] after [`RETRY_DELAY`] like any other,
+/// `None` if it was the only attempt.
+pub(crate) fn connect(token_self: Token, token_other: Token, address: SocketAddr, settings: &NetworkSettings) -> Option<(ConnectionContext, PendingConnect)> {
+    let mut pending_connect = PendingConnect {
+        address,
+        attempts: 1,
+        deadline: Instant::now() + settings.connect_timeout,
+        retry_at: None,
+    };
+    let backend = match TcpStream::connect(address) {
+        Ok(stream) => ConnectionContext::new(token_self, token_other, stream, false, false),
+        Err(e) => {
+            println!("could not connect to {}: {:?}", address, e);
+            if settings.connect_attempts <= 1 {
+                return None;
+            }
+            pending_connect.attempts += 1;
+            pending_connect.retry_at = Some(Instant::now() + RETRY_DELAY);
+            ConnectionContext::without_stream(token_self, token_other)
+        }
+    };
+    Some((backend, pending_connect))
+}
+
+/// Answers the legacy pings that are still waiting for the byte after 0xFE in the format of beta clients,
+/// which only send 0xFE.
+fn update_legacy_pings(thread_ctx: &mut NetworkThreadContext, pending_legacy_pings: &mut HashMap<Token, Instant>, settings: &NetworkSettings) {
+    if pending_legacy_pings.is_empty() {
+        return;
+    }
+
+    let now = Instant::now();
+    pending_legacy_pings.retain(|token, since| {
+        let mut client = match thread_ctx.connections.remove(token) {
+            Some(client) => client,
+            // the client went away in the meantime
+            None => return false
+        };
+        // the next byte may have arrived and been answered already
+        let waiting = !client.close_when_flushed && !client.should_close;
+        if waiting && now >= *since + LEGACY_PING_DELAY {
+            let data = client.read_buffering.as_slice().to_vec();
+            client.read_buffering.reset();
+            status::answer_legacy_ping(&mut client, &data, settings);
+        }
+        let keep = waiting && !client.close_when_flushed;
+        if client.close_when_flushed && client.write_buffering.readable_bytes() == 0 {
+            close(thread_ctx, client, settings);
+        } else {
+            thread_ctx.connections.insert(*token, client);
+        }
+        keep
+    });
+}
+
+/// Checks on the connecting backend sockets.
+/// Sockets that connected get their buffered data flushed, the others are retried until they run out of attempts.
+fn update_connects(thread_ctx: &mut NetworkThreadContext, pending_connects: &mut HashMap<Token, PendingConnect>, poll: &Poll, settings: &NetworkSettings) {
+    if pending_connects.is_empty() {
+        return;
+    }
+
+    let now = Instant::now();
+    let mut failed = Vec::new();
+    pending_connects.retain(|token, connect| {
+        let backend = match thread_ctx.connections.get_mut(token) {
+            Some(backend) => backend,
+            // the client went away in the meantime
+            None => return false
+        };
+
+        let result = match connect.retry_at {
+            Some(retry_at) if now < retry_at => return true,
+            Some(_) => {
+                connect.retry_at = None;
+                reconnect(backend, connect, poll, settings)
+            }
+            None => match &backend.stream {
+                Some(stream) => connect_status(stream),
+                // only backends that wait for a retry have no socket
+                None => return true
+            },
+        };
+
+        match result {
+            Ok(true) => {
+                // flush everything the client sent in the meantime
+                process_write(backend);
+                return false;
+            }
+            Ok(false) if now < connect.deadline => return true,
+            Ok(false) => println!("connecting to {} timed out", connect.address),
+            Err(e) => println!("could not connect to {}: {:?}", connect.address, e),
+        }
+
+        if connect.attempts >= settings.connect_attempts {
+            failed.push(*token);
+            return false;
+        }
+        connect.attempts += 1;
+        connect.retry_at = Some(now + RETRY_DELAY);
+        true
+    });
+
+    for token in failed {
+        backend_unavailable(thread_ctx, token, settings);
+    }
+}
+
+/// Replaces the socket of a backend connection with a new attempt.
+fn reconnect(backend: &mut ConnectionContext, connect: &mut PendingConnect, poll: &Poll, settings: &NetworkSettings) -> io::Result<bool> {
+    let mut stream = TcpStream::connect(connect.address)?;
+    let registry = poll.registry();
+    if let Some(old) = &mut backend.stream {
+        registry.deregister(old)?;
+    }
+    registry.register(&mut stream, backend.token_self, Interest::READABLE | Interest::WRITABLE)?;
+    backend.stream = Some(stream);
+    connect.deadline = Instant::now() + settings.connect_timeout;
+    Ok(false)
+}
+
+/// Whether the socket finished connecting, see [`TcpStream::connect`].
+fn connect_status(stream: &TcpStream) -> io::Result<bool> {
+    if let Some(e) = stream.take_error()? {
+        return Err(e);
+    }
+    match stream.peer_addr() {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotConnected => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Tells the client the backend is unavailable and closes both sides.
+/// A client that was being moved to the backend stays on its current one.
+fn backend_unavailable(thread_ctx: &mut NetworkThreadContext, backend_token: Token, settings: &NetworkSettings) {
+    if let Some(backend) = thread_ctx.connections.remove(&backend_token) {
+        if let Some(client) = thread_ctx.connections.get_mut(&backend.token_other) {
+            if switching::is_switching(&backend, client) {
+                switching::failed(client, backend.server.as_deref().unwrap_or_default(), None);
+                return;
+            }
+        }
+        if let Some(mut client) = thread_ctx.connections.remove(&backend.token_other) {
+            // status pings and clients that didn't handshake yet just get closed
+            if client.state == packets::LOGIN_STATE {
+                client.send_packet(&s2c::login::Disconnect { reason: settings.backend_unavailable_reason.clone() });
+            }
+            forget_player(&client, thread_ctx.id, settings);
+        }
+    }
+}
+
+/// Removes a connection that closed along with the other side of it.
+/// A backend that was being switched to is dropped on its own, the client stays on its current backend.
+fn close(thread_ctx: &mut NetworkThreadContext, connection: ConnectionContext, settings: &NetworkSettings) {
+    if !connection.inbound {
+        if let Some(client) = thread_ctx.connections.get(&connection.token_other) {
+            if switching::is_switching(&connection, client) {
+                return;
+            }
+        }
+    }
+
+    let other = thread_ctx.connections.remove(&connection.token_other);
+    let client = if connection.inbound { Some(&connection) } else { other.as_ref() };
+    if let Some(client) = client {
+        forget_player(client, thread_ctx.id, settings);
+        // backends the client was being switched to
+        let client_token = client.token_self;
+        thread_ctx.connections.retain(|_, backend| backend.inbound || backend.token_other != client_token);
+    }
+}
+
+fn forget_player(client: &ConnectionContext, thread: usize, settings: &NetworkSettings) {
+    if let Some(username) = &client.username {
+        settings.players.remove(username, thread, client.token_self);
+    }
+}
+
 // write buffered data
 fn process_write(ctx: &mut ConnectionContext) {
+    let stream = match &mut ctx.stream {
+        Some(stream) => stream,
+        None => return
+    };
     ctx.is_writable = true;
-    if !write_socket0(&mut ctx.stream, &mut ctx.write_buffering, &mut ctx.should_close) {
+    if !write_socket0(stream, &mut ctx.write_buffering, &mut ctx.should_close) {
         ctx.is_writable = false;
+    } else {
+        // everything got written, start over at the front of the buffer
+        ctx.write_buffering.reset();
+    }
+}
+
+/// Disconnects every client and gives the sockets a few seconds to flush their buffered data.
+fn shutdown(thread_ctx: &mut NetworkThreadContext, poll: &mut Poll, events: &mut Events, reason: Box<Component>) {
+    for connection in thread_ctx.connections.values_mut() {
+        if !connection.inbound {
+            continue;
+        }
+        match connection.state {
+            packets::LOGIN_STATE => connection.send_packet(&s2c::login::Disconnect { reason: *reason.clone() }),
+            packets::PLAY_STATE => connection.send_packet(&s2c::play::Disconnect { reason: *reason.clone() }),
+            _ => {}
+        }
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        for connection in thread_ctx.connections.values_mut() {
+            if !connection.should_close && connection.write_buffering.readable_bytes() > 0 {
+                process_write(connection);
+            }
+        }
+
+        let pending = thread_ctx.connections.values()
+            .any(|connection| !connection.should_close && connection.write_buffering.readable_bytes() > 0);
+        let now = Instant::now();
+        if !pending || now >= deadline {
+            break;
+        }
+        // wait for the sockets to become writable again
+        if poll.poll(events, Some(deadline - now)).is_err() {
+            break;
+        }
     }
+
+    // dropping the contexts closes the sockets
+    thread_ctx.connections.clear();
 }
 
 // todo handle protocol state switching. right now we only check packet ids
-// todo handle encryption
 // todo handle compression
-fn process_read(mut thread_ctx: &mut NetworkThreadContext,
+pub(crate) fn process_read(thread_ctx: &mut NetworkThreadContext,
                 connection_ctx: &mut ConnectionContext,
                 other_ctx: &mut ConnectionContext,
-                read_buf: &mut IndexedVec<u8>,
-                caching_buf: &mut IndexedVec<u8>,
-                handler: Arc<HandlingContext>,
-                compression_buffer: &mut IndexedVec<u8>,
-                decompressor: &mut Decompressor,
-                compressor: &mut Compressor) {
+                buffers: &mut ThreadBuffers,
+                handler: &HandlingContext) {
+    let ThreadBuffers {
+        packet_buf: read_buf,
+        caching_buf,
+        compression_buf: compression_buffer,
+        decompressor,
+        compressor,
+    } = buffers;
 
     let mut pointer = 0;
     let mut next;
@@ -144,58 +622,60 @@ fn process_read(mut thread_ctx: &mut NetworkThreadContext,
     while readable > pointer {
         if let Some((packet_len, packet_len_bytes_red)) = read_frame(read_buf, pointer, readable, connection_ctx) {
             let offset = pointer + packet_len_bytes_red;
-            next = offset + packet_len as usize;
+            next = offset + packet_len;
 
             // the full packet is available
             if readable >= next {
-                let mut working_buf = &read_buf.vec[offset..offset + packet_len];
-
+                // both sides usually agree, except for a backend that was switched to
                 let compression_threshold = connection_ctx.compression_threshold;
-
-                if compression_threshold > 0 {
-                    let real_length = working_buf.get_var_i32();
-                    if real_length.0 > 0 {
-                        compression_buffer.reset();
-                        decompress_packet(real_length.0 as usize, &mut working_buf, decompressor, compression_buffer);
+                let target_threshold = other_ctx.compression_threshold;
+
+                let frame = &read_buf.vec[offset..offset + packet_len];
+                let (packet_data, id, working_buf) = match unpack_frame(frame, compression_threshold > 0, decompressor, compression_buffer) {
+                    Ok(packet) => packet,
+                    Err(e) => {
+                        println!("malformed packet from {} {:?}: {}", side(connection_ctx), connection_ctx.username, e);
+                        connection_ctx.should_close = true;
+                        break;
                     }
-                }
+                };
 
-                let (id, _id_bytes) = working_buf.get_var_i32();
+                // the transformers may move the connection to another state or version, the packet is still in the current one
+                let state = connection_ctx.state;
+                let (from_version, to_version) = (connection_ctx.protocol_version, other_ctx.protocol_version);
 
                 let unparsed_packet = UnparsedPacket::new(id, working_buf);
                 let processing_result =
-                    handler.handle_packet(&mut thread_ctx, connection_ctx, other_ctx, unparsed_packet, connection_ctx.inbound);
+                    handler.handle_packet(thread_ctx, connection_ctx, other_ctx, unparsed_packet, connection_ctx.inbound);
 
                 match processing_result.0 {
-                    TransformationResult::Unchanged => {
+                    TransformationResult::Unchanged | TransformationResult::Modified if from_version != to_version => {
+                        let packet = match &processing_result.1 {
+                            Some(buffer) => buffer.as_slice(),
+                            None => packet_data
+                        };
+                        let mut translated = Vec::new();
+                        match handler.translate(state, connection_ctx.inbound, from_version, to_version, packet, &mut translated) {
+                            Ok(()) => for packet in translated.iter() {
+                                write_frame(packet.as_slice(), target_threshold, compressor, compression_buffer, caching_buf);
+                            },
+                            Err(e) => {
+                                println!("malformed packet {:#04x} from {} {:?}: {}", id, side(connection_ctx), connection_ctx.username, e);
+                                connection_ctx.should_close = true;
+                            }
+                        }
+                    }
+                    TransformationResult::Unchanged if compression_threshold == target_threshold => {
                         copy_slice_to(&read_buf.vec[pointer..next], caching_buf);
                     }
+                    TransformationResult::Unchanged => {
+                        // the packet data may live in the compression buffer
+                        let packet_data = packet_data.to_vec();
+                        write_frame(&packet_data, target_threshold, compressor, compression_buffer, caching_buf);
+                    }
                     TransformationResult::Modified => {
                         let buffer = processing_result.1.unwrap();
-                        let mut final_buffer = buffer.as_slice();
-
-                        let mut is_uncompressed = false;
-                        if compression_threshold > 0 {
-                            let length = final_buffer.len();
-                            if length > compression_threshold as usize {
-                                compression_buffer.reset();
-                                compress_packet(&mut final_buffer, compressor, compression_buffer);
-                            } else {
-                                is_uncompressed = true;
-                            }
-                        }
-
-                        // write in 2 steps to avoid extra copy
-                        let len = final_buffer.len() as i32 + if is_uncompressed { 1 } else { 0 };
-                        let mut frame = IndexedVec::new();
-                        frame.ensure_writable(4);
-                        frame.put_var_i32(len);
-                        if is_uncompressed {
-                            frame.put_var_i32(0);
-                        }
-
-                        copy_slice_to(frame.as_slice(), caching_buf);
-                        copy_slice_to(final_buffer, caching_buf);
+                        write_frame(buffer.as_slice(), target_threshold, compressor, compression_buffer, caching_buf);
                     }
                     TransformationResult::Canceled => {
                         // NOOP
@@ -203,8 +683,8 @@ fn process_read(mut thread_ctx: &mut NetworkThreadContext,
                 }
 
                 if connection_ctx.should_close {
-                    write_socket(connection_ctx, caching_buf);
-                    return;
+                    // what was read up to here still goes to the other side
+                    break;
                 }
 
                 pointer = next;
@@ -223,4 +703,54 @@ fn process_read(mut thread_ctx: &mut NetworkThreadContext,
 
     buffer_read(connection_ctx, read_buf);
     write_socket(other_ctx, caching_buf);
+}
+
+/// Decompresses a frame if the connection uses compression and reads the id of the packet in it.
+/// Returns the packet (id and data), its id, and its data.
+pub(crate) fn unpack_frame<'a>(mut frame: &'a [u8], compressed: bool, decompressor: &mut Decompressor, compression_buffer: &'a mut IndexedVec<u8>) -> Result<(&'a [u8], i32, &'a [u8]), DecodeError> {
+    if compressed {
+        let (real_length, _real_length_bytes) = frame.get_var_i32()?;
+        if real_length < 0 {
+            return Err(DecodeError::InvalidLength(real_length as i64));
+        }
+        if real_length > 0 {
+            compression_buffer.reset();
+            decompress_packet(real_length as usize, &mut frame, decompressor, compression_buffer)?;
+        }
+    }
+    let packet = frame;
+    let (id, _id_bytes) = frame.get_var_i32()?;
+    Ok((packet, id, frame))
+}
+
+fn side(connection_ctx: &ConnectionContext) -> &'static str {
+    if connection_ctx.inbound { "client" } else { "backend" }
+}
+
+/// Frames a packet (id and data), compressing it if needed, and appends it to `caching_buf`.
+fn write_frame(packet: &[u8], compression_threshold: i32, compressor: &mut Compressor, compression_buffer: &mut IndexedVec<u8>, caching_buf: &mut IndexedVec<u8>) {
+    let mut final_buffer = packet;
+
+    let mut is_uncompressed = false;
+    if compression_threshold > 0 {
+        let length = final_buffer.len();
+        if length > compression_threshold as usize {
+            compression_buffer.reset();
+            compress_packet(&mut final_buffer, compressor, compression_buffer);
+        } else {
+            is_uncompressed = true;
+        }
+    }
+
+    // write in 2 steps to avoid extra copy
+    let len = final_buffer.len() as i32 + if is_uncompressed { 1 } else { 0 };
+    let mut frame = IndexedVec::new();
+    frame.ensure_writable(4);
+    frame.put_var_i32(len);
+    if is_uncompressed {
+        frame.put_var_i32(0);
+    }
+
+    copy_slice_to(frame.as_slice(), caching_buf);
+    copy_slice_to(final_buffer, caching_buf);
 }
\ No newline at end of file
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use mio::Token;
+
+/// A player that finished logging in.
+#[derive(Clone, Debug)]
+pub struct Player {
+    pub username: String,
+    pub uuid: u128,
+    /// Name of the backend the player is on.
+    pub server: String,
+    pub(crate) thread: usize,
+    pub(crate) token: Token,
+}
+
+/// Players connected through the proxy, shared by every network thread.
+#[derive(Default)]
+pub struct Players {
+    // keyed by lowercase username
+    players: RwLock<HashMap<String, Player>>,
+}
+
+impl Players {
+    pub fn get(&self, username: &str) -> Option<Player> {
+        self.players.read().unwrap().get(&username.to_lowercase()).cloned()
+    }
+
+    pub fn list(&self) -> Vec<Player> {
+        self.players.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.players.read().unwrap().len()
+    }
+
+    pub(crate) fn insert(&self, player: Player) {
+        self.players.write().unwrap().insert(player.username.to_lowercase(), player);
+    }
+
+    /// Removes the player if it is still the one on that connection, a newer login with the same name is kept.
+    pub(crate) fn remove(&self, username: &str, thread: usize, token: Token) {
+        let mut players = self.players.write().unwrap();
+        let key = username.to_lowercase();
+        if let Some(player) = players.get(&key) {
+            if player.thread == thread && player.token == token {
+                players.remove(&key);
+            }
+        }
+    }
+
+    pub(crate) fn set_server(&self, username: &str, server: &str) {
+        if let Some(player) = self.players.write().unwrap().get_mut(&username.to_lowercase()) {
+            player.server = server.to_string();
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+/// Picks the backend a client is sent to, based on the hostname it used to connect.
+#[derive(Clone, Debug, Default)]
+pub struct Routing {
+    backends: HashMap<String, SocketAddr>,
+    forced_hosts: HashMap<String, String>,
+    default_backend: Option<String>,
+}
+
+impl Routing {
+    /// Adds a named backend, the first one added is the default unless [`Routing::set_default`] is used.
+    pub fn add_backend(&mut self, name: impl Into<String>, address: SocketAddr) {
+        let name = name.into();
+        if self.default_backend.is_none() {
+            self.default_backend = Some(name.clone());
+        }
+        self.backends.insert(name, address);
+    }
+
+    pub fn set_default(&mut self, name: impl Into<String>) {
+        self.default_backend = Some(name.into());
+    }
+
+    /// Sends clients connecting through `hostname` to the backend called `backend`.
+    pub fn add_forced_host(&mut self, hostname: &str, backend: impl Into<String>) {
+        self.forced_hosts.insert(normalize_hostname(hostname), backend.into());
+    }
+
+    pub fn backend(&self, name: &str) -> Option<SocketAddr> {
+        self.backends.get(name).copied()
+    }
+
+    /// Names of every backend, sorted.
+    pub fn backend_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.backends.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the name and address of the backend for the hostname from a handshake.
+    pub fn route(&self, hostname: &str) -> (&str, SocketAddr) {
+        let name = self.forced_hosts.get(&normalize_hostname(hostname))
+            .or(self.default_backend.as_ref())
+            .expect("routing wasn't validated");
+        (name, self.backends[name])
+    }
+
+    /// Checks that the default backend and every forced host point at a known backend.
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.default_backend {
+            None => return Err("no backend was added".to_string()),
+            Some(name) if !self.backends.contains_key(name) => return Err(format!("the default backend {} doesn't exist", name)),
+            _ => {}
+        }
+        for (hostname, backend) in self.forced_hosts.iter() {
+            if !self.backends.contains_key(backend) {
+                return Err(format!("the backend {} of forced host {} doesn't exist", backend, hostname));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Strips what clients and mods append to the hostname (like forge's `\0FML\0`) and the trailing dot of fully qualified names.
+pub fn normalize_hostname(hostname: &str) -> String {
+    let hostname = hostname.split('\0').next().unwrap_or_default();
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
@@ -0,0 +1,237 @@
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::BufMut;
+
+use packets::{c2s, s2c};
+use utils::buffer_helpers::{buffer_read, get_needed_data, read_frame, write_socket};
+use utils::chat::Component;
+use utils::buffers::VarInts;
+use utils::contexts::ConnectionContext;
+use utils::error::DecodeError;
+use utils::indexed_vec::IndexedVec;
+use utils::sendable::Uuid;
+use utils::Packet;
+
+use crate::networking::{NetworkSettings, ThreadBuffers};
+use crate::ProxyError;
+
+// vanilla shows at most 12 players when hovering the player count
+const SAMPLE_SIZE: usize = 12;
+// protocol version vanilla answers legacy pings with, no legacy client has it so they all show the version name
+const LEGACY_PROTOCOL_VERSION: i32 = 127;
+
+/// What the proxy answers server list pings with.
+pub(crate) struct Status {
+    /// Forward pings to the backend instead of answering them.
+    pub(crate) passthrough: bool,
+    pub(crate) motd: Component,
+    pub(crate) max_players: u32,
+    /// The icon as a data url.
+    pub(crate) favicon: Option<String>,
+}
+
+impl Status {
+    /// The status json, with the version of the client if it is supported.
+    fn response(&self, client: &ConnectionContext, settings: &NetworkSettings) -> String {
+        let players = settings.players.list();
+        let sample: Vec<serde_json::Value> = players.iter()
+            .take(SAMPLE_SIZE)
+            .map(|player| serde_json::json!({ "name": player.username, "id": Uuid(player.uuid).to_string() }))
+            .collect();
+
+        let (version_name, protocol_version) = match client.protocol {
+            Some(protocol) => (protocol.minecraft_version, protocol.protocol_version),
+            None => (packets::MINECRAFT_VERSION, packets::PROTOCOL_VERSION)
+        };
+
+        let mut response = serde_json::json!({
+            "version": { "name": version_name, "protocol": protocol_version },
+            "players": { "max": self.max_players, "online": players.len(), "sample": sample },
+            "description": self.motd,
+        });
+        if let Some(favicon) = &self.favicon {
+            response["favicon"] = serde_json::Value::String(favicon.clone());
+        }
+        response.to_string()
+    }
+}
+
+/// Reads a server icon, it has to be a 64x64 png.
+pub fn load_favicon(path: &Path) -> Result<String, ProxyError> {
+    let png = std::fs::read(path).map_err(|e| format!("could not read favicon {}: {}", path.display(), e))?;
+    // the signature is followed by the IHDR chunk, which starts with the width and height
+    if png.len() < 24 || &png[..8] != b"\x89PNG\r\n\x1a\n" || &png[12..16] != b"IHDR" {
+        return Err(format!("favicon {} is not a png", path.display()).into());
+    }
+    let width = u32::from_be_bytes([png[16], png[17], png[18], png[19]]);
+    let height = u32::from_be_bytes([png[20], png[21], png[22], png[23]]);
+    if width != 64 || height != 64 {
+        return Err(format!("favicon {} is {}x{}, it has to be 64x64", path.display(), width, height).into());
+    }
+    Ok(format!("data:image/png;base64,{}", STANDARD.encode(&png)))
+}
+
+/// Answers the status request and ping of a client that handshook into the status state.
+/// The client closes the connection once it got the pong.
+pub(crate) fn process_read(client: &mut ConnectionContext, buffers: &mut ThreadBuffers, settings: &NetworkSettings) {
+    let read_buf = &mut buffers.packet_buf;
+    let mut pointer = 0;
+    read_buf.reset();
+
+    get_needed_data(read_buf, client);
+    if client.should_close {
+        return;
+    }
+
+    let readable = read_buf.readable_bytes();
+    while readable > pointer {
+        let (packet_len, packet_len_bytes_red) = match read_frame(read_buf, pointer, readable, client) {
+            Some(frame) => frame,
+            None => break
+        };
+        let offset = pointer + packet_len_bytes_red;
+        let next = offset + packet_len;
+        if readable < next {
+            break;
+        }
+
+        let mut working_buf = &read_buf.vec[offset..next];
+        pointer = next;
+
+        if let Err(e) = answer_packet(client, &mut working_buf, settings) {
+            println!("malformed status packet: {}", e);
+            client.should_close = true;
+            return;
+        }
+    }
+    read_buf.set_reader_index(pointer);
+
+    buffer_read(client, read_buf);
+}
+
+/// Answers a status request or ping, the only packets a client sends in the status state.
+fn answer_packet(client: &mut ConnectionContext, packet: &mut &[u8], settings: &NetworkSettings) -> Result<(), DecodeError> {
+    let (id, _id_bytes) = packet.get_var_i32()?;
+    if id == c2s::status::Request::get_id() {
+        let json = settings.status.response(client, settings);
+        client.send_packet(&s2c::status::Response { json });
+    } else if id == c2s::status::Ping::get_id() {
+        let ping = c2s::status::Ping::read(packet)?;
+        client.send_packet(&s2c::status::Pong { payload: ping.payload });
+    } else {
+        return Err(DecodeError::InvalidValue(format!("unexpected status packet {:#04x}", id)));
+    }
+    Ok(())
+}
+
+/// Whether the first bytes of a connection are a ping from a client older than 1.7,
+/// those start with 0xFE where newer clients send the length of the handshake.
+pub(crate) fn is_legacy_ping(data: &[u8]) -> bool {
+    data.first() == Some(&0xFE)
+}
+
+/// Answers a ping from a client older than 1.7 with the kick packet it expects, the connection is closed once it was sent.
+/// `data` has to hold the byte after 0xFE unless the client didn't send one, which picks the format of the answer.
+/// Legacy pings are always answered by the proxy, even in passthrough mode.
+pub(crate) fn answer_legacy_ping(client: &mut ConnectionContext, data: &[u8], settings: &NetworkSettings) {
+    let status = &settings.status;
+    let online = settings.players.count();
+    let response = if data.get(1) == Some(&0x01) {
+        // 1.4 to 1.6 send 0xFE 0x01, 1.6 follows it with a plugin message the answer doesn't depend on
+        format!("§1\0{}\0{}\0{}\0{}\0{}", LEGACY_PROTOCOL_VERSION, packets::MINECRAFT_VERSION, status.motd.to_legacy(), online, status.max_players)
+    } else {
+        // beta 1.8 to 1.3 only send 0xFE and split the fields with §
+        format!("{}§{}§{}", status.motd.plain_text().replace('§', ""), online, status.max_players)
+    };
+
+    // a kick packet, the length is in utf-16 code units
+    let response: Vec<u16> = response.encode_utf16().collect();
+    let mut packet = IndexedVec::new();
+    packet.put_u8(0xFF);
+    packet.put_u16(response.len() as u16);
+    for unit in response {
+        packet.put_u16(unit);
+    }
+    write_socket(client, &mut packet);
+    client.close_when_flushed = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+    use std::time::Duration;
+
+    use libdeflater::CompressionLvl;
+    use mio::Token;
+
+    use crate::forwarding::Forwarding;
+    use crate::players::Players;
+    use crate::routing::Routing;
+
+    use super::*;
+
+    /// What the proxy answers to a legacy ping starting with `data`, with 0 of 20 players online.
+    fn legacy_answer(data: &[u8], len: usize) -> Vec<u8> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        let mut client = ConnectionContext::new(Token(0), Token(1), mio::net::TcpStream::from_std(stream), true, true);
+        let settings = NetworkSettings {
+            compression_level: CompressionLvl::default(),
+            connect_timeout: Duration::from_secs(5),
+            connect_attempts: 1,
+            backend_unavailable_reason: Component::text(""),
+            routing: Routing::default(),
+            players: Players::default(),
+            auth: None,
+            forwarding: Forwarding::None,
+            status: Status {
+                passthrough: false,
+                motd: Component::from_json(r#"{"text":"Hello","color":"gold"}"#).unwrap(),
+                max_players: 20,
+                favicon: None,
+            },
+            backend_protocol: None,
+        };
+
+        answer_legacy_ping(&mut client, data, &settings);
+        assert!(client.close_when_flushed);
+        peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut answer = vec![0u8; len];
+        peer.read_exact(&mut answer).unwrap();
+        answer
+    }
+
+    #[test]
+    fn legacy_ping_answer_bytes() {
+        // a kick packet of 26 utf-16 code units
+        let expected = [
+            0xFF, 0x00, 0x1A,
+            // §1
+            0x00, 0xA7, 0x00, 0x31, 0x00, 0x00,
+            // protocol 127
+            0x00, 0x31, 0x00, 0x32, 0x00, 0x37, 0x00, 0x00,
+            // 1.16.5
+            0x00, 0x31, 0x00, 0x2E, 0x00, 0x31, 0x00, 0x36, 0x00, 0x2E, 0x00, 0x35, 0x00, 0x00,
+            // §6Hello
+            0x00, 0xA7, 0x00, 0x36, 0x00, 0x48, 0x00, 0x65, 0x00, 0x6C, 0x00, 0x6C, 0x00, 0x6F, 0x00, 0x00,
+            // 0 of 20 players
+            0x00, 0x30, 0x00, 0x00, 0x00, 0x32, 0x00, 0x30,
+        ];
+        assert_eq!(legacy_answer(&[0xFE, 0x01], expected.len()), expected);
+    }
+
+    #[test]
+    fn beta_ping_answer_bytes() {
+        // Hello§0§20, without colors
+        let expected = [
+            0xFF, 0x00, 0x0A,
+            0x00, 0x48, 0x00, 0x65, 0x00, 0x6C, 0x00, 0x6C, 0x00, 0x6F,
+            0x00, 0xA7, 0x00, 0x30, 0x00, 0xA7, 0x00, 0x32, 0x00, 0x30,
+        ];
+        assert_eq!(legacy_answer(&[0xFE], expected.len()), expected);
+    }
+}
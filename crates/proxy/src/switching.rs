@@ -0,0 +1,276 @@
+use mio::{Poll, Token};
+
+use packet_transformation::handling::HandlingContext;
+use packet_transformation::translation::new_packet;
+use packet_transformation::TransformationResult;
+use packets::{c2s, s2c};
+use utils::buffer_helpers::{buffer_read, get_needed_data, read_frame};
+use utils::chat::{Color, Component};
+use utils::contexts::{ConnectionContext, NetworkThreadContext};
+use utils::error::DecodeError;
+use utils::indexed_vec::IndexedVec;
+use utils::sendable::{Identifier, InferLenVec, Uuid, Vari32};
+use utils::Packet;
+
+use crate::auth;
+use crate::forwarding::{self, Forwarding};
+use crate::networking::{connect, process_read as forward, unpack_frame, NetworkSettings, PendingConnect, ThreadBuffers};
+
+// Moving a client to another backend works like this:
+// 1. a new backend context is created that points at the client, while the client still points at its old backend
+// 2. the proxy logs in to the new backend itself with the name of the client
+// 3. once the new backend sends JoinGame the old backend is closed, the client is pointed at the new one
+//    and gets the JoinGame followed by the respawns it needs to load the new world
+//
+// A backend is being switched to as long as its client doesn't point back at it.
+
+/// Whether `backend` is a backend the client is being moved to, that hasn't finished logging in yet.
+pub(crate) fn is_switching(backend: &ConnectionContext, client: &ConnectionContext) -> bool {
+    !backend.inbound && client.token_other != backend.token_self
+}
+
+/// Starts moving the client to the backend called `server`, the new backend uses `token`.
+pub(crate) fn start(thread_ctx: &mut NetworkThreadContext, client_token: Token, server: &str, token: Token, poll: &Poll, settings: &NetworkSettings) -> Option<(ConnectionContext, PendingConnect)> {
+    // every backend pointing at the client, except the one it is on, is one it is being switched to
+    let current_backend = thread_ctx.connections.get(&client_token)?.token_other;
+    let already_switching = thread_ctx.connections.values()
+        .any(|connection| !connection.inbound && connection.token_other == client_token && connection.token_self != current_backend);
+    let client = match thread_ctx.connections.get_mut(&client_token) {
+        Some(client) if client.inbound && client.state == packets::PLAY_STATE => client,
+        _ => return None
+    };
+
+    let (backend_version, backend_protocol) = settings.backend_protocol(client);
+    if backend_protocol.and_then(|protocol| protocol.id_of::<s2c::play::JoinGame>().and(protocol.id_of::<s2c::play::Respawn>())).is_none() {
+        // the world of the new backend is loaded with those
+        send_message(client, "Switching servers isn't supported on your version");
+        return None;
+    }
+    if already_switching {
+        send_message(client, "You are already being moved to another server");
+        return None;
+    }
+    if client.server.as_deref() == Some(server) {
+        send_message(client, &format!("You are already connected to {}", server));
+        return None;
+    }
+    let address = match settings.routing.backend(server) {
+        Some(address) => address,
+        None => {
+            send_message(client, &format!("There is no server called {}", server));
+            return None;
+        }
+    };
+    let username = client.username.clone()?;
+
+    let (mut backend, pending_connect) = match connect(token, client_token, address, settings) {
+        Some(connect) => connect,
+        None => {
+            failed(client, server, None);
+            return None;
+        }
+    };
+    if let Err(e) = backend.register(poll) {
+        println!("could not register backend: {:?}", e);
+        failed(client, server, None);
+        return None;
+    }
+    println!("Moving {} to {}", username, server);
+    backend.protocol_version = backend_version;
+    backend.protocol = backend_protocol;
+    backend.server = Some(server.to_string());
+
+    // buffered until the socket connects
+    let handshake = match settings.forwarding {
+        Forwarding::Legacy => forwarding::legacy_handshake(client, &forwarding::profile(client, &username), backend_version),
+        // modern forwarding happens during the login
+        Forwarding::None | Forwarding::Modern { .. } => c2s::handshake::HandshakePacket {
+            protocol_version: Vari32 { val: backend_version },
+            ip: address.ip().to_string(),
+            port: address.port(),
+            next_state: Vari32 { val: packets::LOGIN_STATE as i32 },
+        },
+    };
+    backend.send_packet(&handshake);
+    backend.state = packets::LOGIN_STATE;
+    backend.send_packet(&c2s::login::LoginStart { name: username });
+
+    Some((backend, pending_connect))
+}
+
+/// Handles the packets of a backend that is still being switched to.
+pub(crate) fn process_read(thread_ctx: &mut NetworkThreadContext, backend: &mut ConnectionContext, client: &mut ConnectionContext, buffers: &mut ThreadBuffers, handler: &HandlingContext, settings: &NetworkSettings) {
+    match read_login(thread_ctx, backend, client, buffers, handler) {
+        Ok(Some(join_game)) => {
+            complete(thread_ctx, backend, client, join_game, handler, settings);
+            // the packets after JoinGame are regular play packets
+            forward(thread_ctx, backend, client, buffers, handler);
+        }
+        Ok(None) => {
+            if backend.should_close {
+                failed(client, backend.server.as_deref().unwrap_or_default(), None);
+            }
+        }
+        Err(reason) => {
+            failed(client, backend.server.as_deref().unwrap_or_default(), reason.map(|reason| *reason));
+            backend.should_close = true;
+        }
+    }
+}
+
+/// Logs in to the backend and returns its JoinGame once it arrives.
+/// Fails with the chat json the backend kicked the client with, if there is one.
+fn read_login(thread_ctx: &mut NetworkThreadContext, backend: &mut ConnectionContext, client: &mut ConnectionContext, buffers: &mut ThreadBuffers, handler: &HandlingContext) -> Result<Option<s2c::play::JoinGame>, Option<Box<Component>>> {
+    let ThreadBuffers {
+        packet_buf: read_buf,
+        compression_buf: compression_buffer,
+        decompressor,
+        ..
+    } = buffers;
+
+    let mut pointer = 0;
+    read_buf.reset();
+
+    get_needed_data(read_buf, backend);
+    if backend.should_close {
+        return Ok(None);
+    }
+
+    let readable = read_buf.readable_bytes();
+    let mut join_game = None;
+    let join_game_id = backend.protocol.and_then(|protocol| protocol.id_of::<s2c::play::JoinGame>());
+
+    while readable > pointer && join_game.is_none() {
+        let (packet_len, packet_len_bytes_red) = match read_frame(read_buf, pointer, readable, backend) {
+            Some(frame) => frame,
+            None => break
+        };
+        let offset = pointer + packet_len_bytes_red;
+        let next = offset + packet_len;
+        if readable < next {
+            break;
+        }
+
+        let frame = &read_buf.vec[offset..next];
+        let (_packet, id, mut working_buf) = unpack_frame(frame, backend.compression_threshold > 0, decompressor, compression_buffer)
+            .map_err(|e| malformed(backend, e))?;
+        pointer = next;
+
+        match backend.state {
+            packets::LOGIN_STATE => match id {
+                0x00 => return Err(Some(Box::new(s2c::login::Disconnect::read(&mut working_buf).map_err(|e| malformed(backend, e))?.reason))),
+                0x01 => {
+                    auth::answer_encryption_request(backend, &s2c::login::EncryptionRequest::read(&mut working_buf).map_err(|e| malformed(backend, e))?);
+                    if backend.should_close {
+                        return Err(None);
+                    }
+                }
+                0x02 => backend.state = packets::PLAY_STATE,
+                0x03 => backend.compression_threshold = s2c::login::SetCompression::read(&mut working_buf).map_err(|e| malformed(backend, e))?.threshold.val,
+                0x04 => {
+                    let mut request = s2c::login::LoginPluginRequest::read(&mut working_buf).map_err(|e| malformed(backend, e))?;
+                    // a transformer may answer it, otherwise say we don't understand it
+                    if let TransformationResult::Canceled = handler.transform_packet(thread_ctx, backend, client, &mut request) {
+                        continue;
+                    }
+                    backend.send_packet(&c2s::login::LoginPluginResponse {
+                        message_id: request.message_id,
+                        successful: false,
+                        data: InferLenVec { inner: IndexedVec::new() },
+                    });
+                }
+                _ => println!("unexpected login packet {} while switching", id)
+            },
+            _ => {
+                // nothing is sent before JoinGame
+                if Some(id) == join_game_id {
+                    join_game = Some(s2c::play::JoinGame::read(&mut working_buf).map_err(|e| malformed(backend, e))?);
+                }
+            }
+        }
+    }
+    read_buf.set_reader_index(pointer);
+
+    buffer_read(backend, read_buf);
+    Ok(join_game)
+}
+
+/// Logs a packet the backend that is being switched to sent and couldn't be read, switching fails without a reason.
+fn malformed(backend: &ConnectionContext, e: DecodeError) -> Option<Box<Component>> {
+    println!("malformed packet from {:?} while switching: {}", backend.server, e);
+    None
+}
+
+/// Drops the old backend and makes the client load the world of the new one.
+fn complete(thread_ctx: &mut NetworkThreadContext, backend: &mut ConnectionContext, client: &mut ConnectionContext, join_game: s2c::play::JoinGame, handler: &HandlingContext, settings: &NetworkSettings) {
+    // dropping the old backend closes its socket
+    thread_ctx.connections.remove(&client.token_other);
+    client.token_other = backend.token_self;
+    client.server = backend.server.clone();
+    if let (Some(username), Some(server)) = (&client.username, &client.server) {
+        settings.players.set_server(username, server);
+        println!("Moved {} to {}", username, server);
+    }
+
+    // the client only throws away its world when it respawns into a world with another name
+    let temporary_world = if join_game.world_name == "minecraft:overworld" {
+        "minecraft:the_nether"
+    } else {
+        "minecraft:overworld"
+    };
+    let respawn = |world_name: Identifier| s2c::play::Respawn {
+        dimension: join_game.dimension.clone(),
+        world_name,
+        hashed_seed: join_game.hashed_seed,
+        gamemode: join_game.gamemode,
+        previous_gamemode: join_game.previous_gamemode as u8,
+        is_debug: join_game.is_debug,
+        is_flat: join_game.is_flat,
+        copy_metadata: false,
+    };
+    let temporary_respawn = respawn(Identifier::from(temporary_world));
+    let final_respawn = respawn(join_game.world_name.clone());
+
+    send_from_backend(client, backend, handler, &join_game);
+    send_from_backend(client, backend, handler, &temporary_respawn);
+    send_from_backend(client, backend, handler, &final_respawn);
+}
+
+/// Sends a packet to the client as if the backend sent it, translated if the backend runs an older version.
+fn send_from_backend<P: Packet>(client: &mut ConnectionContext, backend: &ConnectionContext, handler: &HandlingContext, packet: &P) {
+    if client.protocol_version == backend.protocol_version {
+        client.send_packet(packet);
+        return;
+    }
+    let id = match backend.protocol.and_then(|protocol| protocol.id_of::<P>()) {
+        Some(id) => id,
+        None => return
+    };
+    let mut encoded = new_packet(id);
+    if let Err(e) = packet.write(&mut encoded) {
+        println!("could not write packet {:#04x} for {:?}: {}", id, client.username, e);
+        return;
+    }
+    let mut translated = Vec::new();
+    if let Err(e) = handler.translate(P::get_state(), P::is_inbound(), backend.protocol_version, client.protocol_version, encoded.as_slice(), &mut translated) {
+        println!("could not translate packet {:#04x} for {:?}: {}", id, client.username, e);
+        return;
+    }
+    for packet in translated.iter() {
+        client.send_raw_packet(packet.as_slice());
+    }
+}
+
+/// Tells the client that it couldn't be moved, `reason` is what the backend kicked it with.
+pub(crate) fn failed(client: &mut ConnectionContext, server: &str, reason: Option<Component>) {
+    let message = match reason {
+        Some(reason) => Component::text(format!("Could not connect to {}: ", server)).append(reason),
+        None => Component::text(format!("Could not connect to {}", server)),
+    };
+    client.send_packet(&s2c::play::ChatMessage { json: message.color(Color::Red), position: 1, sender: Uuid::default() });
+}
+
+/// Sends a system chat message to a client in the play state.
+pub(crate) fn send_message(client: &mut ConnectionContext, message: &str) {
+    client.send_packet(&s2c::play::ChatMessage { json: Component::text(message), position: 1, sender: Uuid::default() });
+}
@@ -0,0 +1,109 @@
+//! Lets clients join backends that run an older version, see [`crate::ProxyBuilder::backend_version`].
+//!
+//! Every step translates the packets that changed between two consecutive versions, the encodings of packets are
+//! translated but registry ids (blocks, items, entities, sounds...) are passed through as they are. Block states
+//! and items changed ids in 1.17 and 1.18, so clients see the wrong blocks and items in chunks, block changes and
+//! slots until they get remap tables. That is why setting a backend version needs the `translation` feature.
+
+use bytes::{Buf, BufMut};
+
+use packet_transformation::translation::{new_packet, TranslationStep};
+use packets::s2c;
+use utils::buffers::{Strings, StringsMut, VarInts, VarIntsMut};
+use utils::error::DecodeError;
+use utils::indexed_vec::IndexedVec;
+use utils::nbt::{Compound, Nbt};
+use utils::Packet;
+
+mod v1_17_1;
+mod v1_18_2;
+
+/// Every step, from the oldest version to the newest.
+pub(crate) fn steps() -> Vec<TranslationStep> {
+    vec![v1_17_1::step(), v1_18_2::step()]
+}
+
+/// Makes the server list entry of a backend show up as compatible, the client never sees the older version.
+fn status_response(step: TranslationStep, older: i32, newer: i32) -> TranslationStep {
+    step.clientbound(packets::STATUS_STATE, s2c::status::Response::get_id(), move |mut buf, out| {
+        let json = buf.get_string()?;
+        let json = match serde_json::from_str::<serde_json::Value>(&json) {
+            Ok(mut response) if response["version"]["protocol"] == older => {
+                response["version"]["protocol"] = newer.into();
+                response.to_string()
+            }
+            _ => json
+        };
+        let mut packet = new_packet(s2c::status::Response::get_id());
+        packet.put_string(&json);
+        out.push(packet);
+        Ok(())
+    })
+}
+
+/// Moves `len` bytes from `buf` to `out`.
+fn copy_bytes(buf: &mut dyn Buf, out: &mut IndexedVec<u8>, len: usize) -> Result<(), DecodeError> {
+    if buf.remaining() < len {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let bytes = buf.copy_to_bytes(len);
+    out.put_slice(&bytes);
+    Ok(())
+}
+
+/// Moves the rest of `buf` to `out`.
+fn copy_rest(buf: &mut dyn Buf, out: &mut IndexedVec<u8>) {
+    let bytes = buf.copy_to_bytes(buf.remaining());
+    out.put_slice(&bytes);
+}
+
+fn copy_var_i32(mut buf: &mut dyn Buf, out: &mut IndexedVec<u8>) -> Result<i32, DecodeError> {
+    let (value, _bytes) = buf.get_var_i32()?;
+    out.put_var_i32(value);
+    Ok(value)
+}
+
+/// Moves a varint prefixed byte array from `buf` to `out`.
+fn copy_byte_array(mut buf: &mut dyn Buf, out: &mut IndexedVec<u8>) -> Result<(), DecodeError> {
+    let (len, _len_bytes) = buf.get_var_i32()?;
+    if len < 0 {
+        return Err(DecodeError::InvalidLength(len as i64));
+    }
+    out.put_var_i32(len);
+    copy_bytes(buf, out, len as usize)
+}
+
+/// Writes a 1.16.5 section bit mask as a 1.17 bit set, an array of longs.
+fn put_bit_set(out: &mut IndexedVec<u8>, mask: u64) {
+    if mask == 0 {
+        out.put_var_i32(0);
+    } else {
+        out.put_var_i32(1);
+        out.put_u64(mask);
+    }
+}
+
+/// The dimension types of a dimension codec, or the one dimension type sent without a codec.
+fn dimension_types(nbt: &mut Nbt) -> Vec<&mut Compound> {
+    let root = match nbt.root_mut() {
+        Some(root) => root,
+        None => return Vec::new()
+    };
+    if root.contains("logical_height") {
+        return vec![root];
+    }
+    root.get_compound_mut("minecraft:dimension_type")
+        .and_then(|registry| registry.get_list_mut("value"))
+        .map(|entries| entries.compounds_mut().filter_map(|entry| entry.get_compound_mut("element")).collect())
+        .unwrap_or_default()
+}
+
+/// Translates `packet`, its id followed by its data, from `from` to `to` with `step` alone.
+#[cfg(test)]
+fn translate(step: TranslationStep, inbound: bool, from: i32, to: i32, packet: &[u8]) -> Vec<Vec<u8>> {
+    let mut handling = packet_transformation::handling::HandlingContext::new();
+    handling.register_translation(step);
+    let mut out = Vec::new();
+    handling.translate(packets::PLAY_STATE, inbound, from, to, packet, &mut out).unwrap();
+    out.iter().map(|packet| packet.as_slice().to_vec()).collect()
+}
@@ -0,0 +1,502 @@
+//! 1.16.5 backends for 1.17.1 clients, following https://wiki.vg/index.php?title=Protocol&oldid=16918
+
+use bytes::{Buf, BufMut};
+
+use packet_transformation::translation::{new_packet, TranslationStep};
+use packets::{c2s, s2c};
+use utils::buffers::{Bools, BoolsMut, Strings, StringsMut, VarInts, VarIntsMut};
+use utils::error::DecodeError;
+use utils::indexed_vec::IndexedVec;
+use utils::nbt::Compound;
+use utils::sendable::{RawNbt, Sendable};
+use utils::slot::Slot;
+use utils::Packet;
+
+use super::{copy_byte_array, copy_bytes, copy_rest, copy_var_i32, dimension_types, put_bit_set, status_response};
+
+const OLDER: i32 = 754;
+const NEWER: i32 = 756;
+
+// ids of the 1.17.1 packets that aren't encoded like a packet struct, the next step renames them
+pub(super) const SCULK_VIBRATION_SIGNAL: i32 = 0x05;
+pub(super) const CLEAR_TITLES: i32 = 0x10;
+pub(super) const WINDOW_ITEMS: i32 = 0x14;
+pub(super) const SET_SLOT: i32 = 0x16;
+pub(super) const EXPLOSION: i32 = 0x1C;
+pub(super) const INITIALIZE_WORLD_BORDER: i32 = 0x20;
+pub(super) const CHUNK_DATA: i32 = 0x22;
+pub(super) const UPDATE_LIGHT: i32 = 0x25;
+// encoded like the packet struct, the next step translates it
+pub(super) const JOIN_GAME: i32 = 0x26;
+pub(super) const MAP_DATA: i32 = 0x27;
+pub(super) const PING: i32 = 0x30;
+pub(super) const END_COMBAT: i32 = 0x33;
+pub(super) const ENTER_COMBAT: i32 = 0x34;
+pub(super) const DEATH_COMBAT: i32 = 0x35;
+pub(super) const PLAYER_POSITION_AND_LOOK: i32 = 0x38;
+pub(super) const RESOURCE_PACK_SEND: i32 = 0x3C;
+pub(super) const RESPAWN: i32 = 0x3D;
+pub(super) const ACTION_BAR: i32 = 0x41;
+pub(super) const WORLD_BORDER_CENTER: i32 = 0x42;
+pub(super) const WORLD_BORDER_LERP_SIZE: i32 = 0x43;
+pub(super) const WORLD_BORDER_SIZE: i32 = 0x44;
+pub(super) const WORLD_BORDER_WARNING_DELAY: i32 = 0x45;
+pub(super) const WORLD_BORDER_WARNING_REACH: i32 = 0x46;
+pub(super) const SPAWN_POSITION: i32 = 0x4B;
+pub(super) const SET_TITLE_SUBTITLE: i32 = 0x57;
+pub(super) const SET_TITLE_TEXT: i32 = 0x59;
+pub(super) const SET_TITLE_TIMES: i32 = 0x5A;
+pub(super) const ENTITY_PROPERTIES: i32 = 0x63;
+pub(super) const TAGS: i32 = 0x66;
+
+pub(super) const CLIENT_SETTINGS: i32 = 0x05;
+pub(super) const CLICK_WINDOW: i32 = 0x08;
+pub(super) const EDIT_BOOK: i32 = 0x0B;
+pub(super) const PONG: i32 = 0x1D;
+
+// set in the ids of the pings that stand in for window confirmations
+const WINDOW_CONFIRMATION_PING: i32 = 1 << 30;
+
+// the client disconnects when one of the tags it knows is missing, the ones 1.16.5 doesn't have are sent empty
+const BLOCK_TAGS: &[&str] = &[
+    "minecraft:candles", "minecraft:candle_cakes", "minecraft:cauldrons", "minecraft:crystal_sound_blocks",
+    "minecraft:inside_step_sound_blocks", "minecraft:occludes_vibration_signals", "minecraft:dripstone_replaceable_blocks",
+    "minecraft:cave_vines", "minecraft:moss_replaceable", "minecraft:lush_ground_replaceable", "minecraft:azalea_log_replaceable",
+    "minecraft:small_dripleaf_placeable", "minecraft:snow", "minecraft:mineable/axe", "minecraft:mineable/hoe",
+    "minecraft:mineable/pickaxe", "minecraft:mineable/shovel", "minecraft:needs_diamond_tool", "minecraft:needs_iron_tool",
+    "minecraft:needs_stone_tool", "minecraft:feature_cannot_replace", "minecraft:lava_pool_stone_replaceables",
+    "minecraft:geode_invalid_blocks", "minecraft:deepslate_ore_replaceables", "minecraft:stone_ore_replaceables",
+    "minecraft:coal_ores", "minecraft:iron_ores", "minecraft:copper_ores", "minecraft:diamond_ores", "minecraft:redstone_ores",
+    "minecraft:lapis_ores", "minecraft:emerald_ores", "minecraft:dirt", "minecraft:fall_damage_resetting",
+];
+const ITEM_TAGS: &[&str] = &[
+    "minecraft:candles", "minecraft:freeze_immune_wearables", "minecraft:axolotl_tempt_items", "minecraft:occludes_vibration_signals",
+    "minecraft:cluster_max_harvestables", "minecraft:fox_food", "minecraft:coal_ores", "minecraft:iron_ores", "minecraft:copper_ores",
+    "minecraft:diamond_ores", "minecraft:redstone_ores", "minecraft:lapis_ores", "minecraft:emerald_ores",
+];
+const ENTITY_TYPE_TAGS: &[&str] = &[
+    "minecraft:powder_snow_walkable_mobs", "minecraft:axolotl_always_hostiles", "minecraft:axolotl_hunt_targets",
+    "minecraft:freeze_immune_entity_types", "minecraft:freeze_hurts_extra_types",
+];
+const GAME_EVENT_TAGS: &[&str] = &["minecraft:vibrations", "minecraft:ignore_vibrations_sneaking"];
+
+pub(super) fn step() -> TranslationStep {
+    let step = TranslationStep::new(OLDER, NEWER)
+        .clientbound(packets::PLAY_STATE, s2c::play::WindowConfirmation::get_id(), window_confirmation)
+        .clientbound(packets::PLAY_STATE, s2c::play::WindowItems::get_id(), window_items)
+        .clientbound(packets::PLAY_STATE, s2c::play::SetSlot::get_id(), set_slot)
+        .clientbound(packets::PLAY_STATE, s2c::play::Explosion::get_id(), explosion)
+        .clientbound(packets::PLAY_STATE, s2c::play::ChunkData::get_id(), chunk_data)
+        .clientbound(packets::PLAY_STATE, s2c::play::UpdateLight::get_id(), update_light)
+        .clientbound(packets::PLAY_STATE, s2c::play::JoinGame::get_id(), join_game)
+        .clientbound(packets::PLAY_STATE, s2c::play::MapData::get_id(), map_data)
+        .clientbound(packets::PLAY_STATE, s2c::play::CombatEvent::get_id(), combat_event)
+        .clientbound(packets::PLAY_STATE, s2c::play::PlayerPositionAndLook::get_id(), player_position_and_look)
+        .clientbound(packets::PLAY_STATE, s2c::play::ResourcePackSend::get_id(), resource_pack_send)
+        .clientbound(packets::PLAY_STATE, s2c::play::Respawn::get_id(), respawn)
+        .clientbound(packets::PLAY_STATE, s2c::play::WorldBorder::get_id(), world_border)
+        .clientbound(packets::PLAY_STATE, s2c::play::SpawnPosition::get_id(), spawn_position)
+        .clientbound(packets::PLAY_STATE, s2c::play::Title::get_id(), title)
+        .clientbound(packets::PLAY_STATE, s2c::play::EntityProperties::get_id(), entity_properties)
+        .clientbound(packets::PLAY_STATE, s2c::play::Tags::get_id(), tags)
+        .serverbound(packets::PLAY_STATE, CLIENT_SETTINGS, client_settings)
+        .serverbound(packets::PLAY_STATE, CLICK_WINDOW, click_window)
+        .serverbound(packets::PLAY_STATE, PONG, pong);
+    // books are edited with a whole item stack in 1.16.5, EditBook is dropped
+    status_response(step, OLDER, NEWER)
+}
+
+/// Window confirmations are gone, the server asks for one with a ping instead.
+fn window_confirmation(mut buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let window_id = buf.try_get_i8()?;
+    let action_number = buf.try_get_i16()?;
+    let accepted = buf.get_bool()?;
+    // accepted clicks don't need an answer
+    if !accepted {
+        let mut packet = new_packet(PING);
+        packet.put_i32(WINDOW_CONFIRMATION_PING | (window_id as u8 as i32) << 16 | action_number as u16 as i32);
+        out.push(packet);
+    }
+    Ok(())
+}
+
+fn pong(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let id = buf.try_get_i32()?;
+    if id & WINDOW_CONFIRMATION_PING != 0 {
+        let mut packet = new_packet(c2s::play::WindowConfirmation::get_id());
+        packet.put_i8((id >> 16) as i8);
+        packet.put_i16(id as i16);
+        packet.put_bool(true);
+        out.push(packet);
+    }
+    Ok(())
+}
+
+fn window_items(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(WINDOW_ITEMS);
+    packet.put_u8(buf.try_get_u8()?);
+    // the state id, the client echoes it back in ClickWindow
+    packet.put_var_i32(0);
+    packet.put_var_i32(buf.try_get_i16()? as i32);
+    copy_rest(buf, &mut packet);
+    // the item held by the cursor isn't sent by 1.16.5
+    packet.put_bool(false);
+    out.push(packet);
+    Ok(())
+}
+
+fn set_slot(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(SET_SLOT);
+    packet.put_i8(buf.try_get_i8()?);
+    packet.put_var_i32(0);
+    copy_rest(buf, &mut packet);
+    out.push(packet);
+    Ok(())
+}
+
+fn explosion(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(EXPLOSION);
+    // position and strength
+    copy_bytes(buf, &mut packet, 16)?;
+    packet.put_var_i32(buf.try_get_i32()?);
+    copy_rest(buf, &mut packet);
+    out.push(packet);
+    Ok(())
+}
+
+fn chunk_data(mut buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(CHUNK_DATA);
+    copy_bytes(buf, &mut packet, 8)?;
+    // chunks are always sent whole in 1.17, a vanilla 1.16.5 server never sends partial ones
+    if !buf.get_bool()? {
+        return Ok(());
+    }
+    let (mask, _mask_bytes) = buf.get_var_i32()?;
+    put_bit_set(&mut packet, mask as u32 as u64);
+    // heightmaps
+    RawNbt::write(&mut packet, &RawNbt::read(buf)?)?;
+    let biomes = copy_var_i32(buf, &mut packet)?;
+    for _ in 0..biomes {
+        copy_var_i32(buf, &mut packet)?;
+    }
+    // the sections and block entities
+    copy_rest(buf, &mut packet);
+    out.push(packet);
+    Ok(())
+}
+
+fn update_light(mut buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(UPDATE_LIGHT);
+    copy_var_i32(buf, &mut packet)?;
+    copy_var_i32(buf, &mut packet)?;
+    packet.put_bool(buf.get_bool()?);
+    let sky_light_mask = buf.get_var_i32()?.0 as u32;
+    let block_light_mask = buf.get_var_i32()?.0 as u32;
+    put_bit_set(&mut packet, sky_light_mask as u64);
+    put_bit_set(&mut packet, block_light_mask as u64);
+    put_bit_set(&mut packet, buf.get_var_i32()?.0 as u32 as u64);
+    put_bit_set(&mut packet, buf.get_var_i32()?.0 as u32 as u64);
+    // an array for every bit set in the masks, they are counted now
+    packet.put_var_i32(sky_light_mask.count_ones() as i32);
+    for _ in 0..sky_light_mask.count_ones() {
+        copy_byte_array(buf, &mut packet)?;
+    }
+    packet.put_var_i32(block_light_mask.count_ones() as i32);
+    copy_rest(buf, &mut packet);
+    out.push(packet);
+    Ok(())
+}
+
+fn join_game(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut join_game = s2c::play::JoinGame::read(buf)?;
+    dimension_types(&mut join_game.dimension_codec).into_iter().for_each(add_height);
+    dimension_types(&mut join_game.dimension).into_iter().for_each(add_height);
+    let mut packet = new_packet(JOIN_GAME);
+    join_game.write(&mut packet)?;
+    out.push(packet);
+    Ok(())
+}
+
+fn respawn(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut respawn = s2c::play::Respawn::read(buf)?;
+    dimension_types(&mut respawn.dimension).into_iter().for_each(add_height);
+    let mut packet = new_packet(RESPAWN);
+    respawn.write(&mut packet)?;
+    out.push(packet);
+    Ok(())
+}
+
+/// Dimension types have a height and a lowest y, 1.16.5 worlds are always 256 blocks high starting at 0.
+fn add_height(dimension_type: &mut Compound) {
+    if !dimension_type.contains("height") {
+        dimension_type.insert("min_y", 0);
+        dimension_type.insert("height", 256);
+    }
+}
+
+fn map_data(mut buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(MAP_DATA);
+    copy_var_i32(buf, &mut packet)?;
+    packet.put_i8(buf.try_get_i8()?);
+    // the icons are sent whether the position is tracked or not
+    let _tracking_position = buf.get_bool()?;
+    packet.put_bool(buf.get_bool()?);
+    // they are optional now, absent ones keep the current icons
+    packet.put_bool(true);
+    copy_rest(buf, &mut packet);
+    out.push(packet);
+    Ok(())
+}
+
+fn combat_event(mut buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let id = match buf.get_var_i32()?.0 {
+        0 => ENTER_COMBAT,
+        1 => END_COMBAT,
+        2 => DEATH_COMBAT,
+        _ => return Ok(())
+    };
+    let mut packet = new_packet(id);
+    copy_rest(buf, &mut packet);
+    out.push(packet);
+    Ok(())
+}
+
+fn player_position_and_look(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(PLAYER_POSITION_AND_LOOK);
+    copy_rest(buf, &mut packet);
+    // dismount vehicle
+    packet.put_bool(false);
+    out.push(packet);
+    Ok(())
+}
+
+fn resource_pack_send(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(RESOURCE_PACK_SEND);
+    copy_rest(buf, &mut packet);
+    // not forced, without a prompt
+    packet.put_bool(false);
+    packet.put_bool(false);
+    out.push(packet);
+    Ok(())
+}
+
+fn world_border(mut buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let id = match buf.get_var_i32()?.0 {
+        0 => WORLD_BORDER_SIZE,
+        1 => WORLD_BORDER_LERP_SIZE,
+        2 => WORLD_BORDER_CENTER,
+        3 => INITIALIZE_WORLD_BORDER,
+        4 => WORLD_BORDER_WARNING_DELAY,
+        5 => WORLD_BORDER_WARNING_REACH,
+        _ => return Ok(())
+    };
+    let mut packet = new_packet(id);
+    copy_rest(buf, &mut packet);
+    out.push(packet);
+    Ok(())
+}
+
+fn spawn_position(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(SPAWN_POSITION);
+    copy_rest(buf, &mut packet);
+    // angle
+    packet.put_f32(0.0);
+    out.push(packet);
+    Ok(())
+}
+
+fn title(mut buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let (action, _action_bytes) = buf.get_var_i32()?;
+    let mut packet = match action {
+        0 => new_packet(SET_TITLE_TEXT),
+        1 => new_packet(SET_TITLE_SUBTITLE),
+        2 => new_packet(ACTION_BAR),
+        3 => new_packet(SET_TITLE_TIMES),
+        4 | 5 => {
+            let mut packet = new_packet(CLEAR_TITLES);
+            // hide doesn't reset the times
+            packet.put_bool(action == 5);
+            out.push(packet);
+            return Ok(());
+        }
+        _ => return Ok(())
+    };
+    copy_rest(buf, &mut packet);
+    out.push(packet);
+    Ok(())
+}
+
+fn entity_properties(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(ENTITY_PROPERTIES);
+    copy_var_i32(buf, &mut packet)?;
+    packet.put_var_i32(buf.try_get_i32()?);
+    copy_rest(buf, &mut packet);
+    out.push(packet);
+    Ok(())
+}
+
+/// The four tag lists become a list of tag lists with the registry they are for.
+fn tags(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(TAGS);
+    packet.put_var_i32(5);
+    let registries: [(&str, &[&str]); 4] = [
+        ("minecraft:block", BLOCK_TAGS),
+        ("minecraft:item", ITEM_TAGS),
+        ("minecraft:fluid", &[]),
+        ("minecraft:entity_type", ENTITY_TYPE_TAGS),
+    ];
+    for (registry, required) in registries {
+        packet.put_string(registry);
+        copy_tag_list(buf, &mut packet, required)?;
+    }
+    // game events didn't exist in 1.16.5
+    packet.put_string("minecraft:game_event");
+    packet.put_var_i32(GAME_EVENT_TAGS.len() as i32);
+    for tag in GAME_EVENT_TAGS {
+        packet.put_string(tag);
+        packet.put_var_i32(0);
+    }
+    out.push(packet);
+    Ok(())
+}
+
+/// Moves a list of tags from `buf` to `out`, adding the `required` tags that are missing without any entries.
+fn copy_tag_list(mut buf: &mut dyn Buf, out: &mut IndexedVec<u8>, required: &[&str]) -> Result<(), DecodeError> {
+    let mut tags = IndexedVec::new();
+    let mut names = Vec::new();
+    let (count, _count_bytes) = buf.get_var_i32()?;
+    for _ in 0..count {
+        let name = buf.get_string()?;
+        tags.put_string(&name);
+        names.push(name);
+        let entries = copy_var_i32(buf, &mut tags)?;
+        for _ in 0..entries {
+            copy_var_i32(buf, &mut tags)?;
+        }
+    }
+    let missing: Vec<&&str> = required.iter().filter(|tag| !names.iter().any(|name| name == *tag)).collect();
+    out.put_var_i32(count + missing.len() as i32);
+    out.put_slice(tags.as_slice());
+    for tag in missing {
+        out.put_string(tag);
+        out.put_var_i32(0);
+    }
+    Ok(())
+}
+
+/// Text filtering was added at the end.
+fn client_settings(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(c2s::play::ClientSettings::get_id());
+    let len = buf.remaining().saturating_sub(1);
+    copy_bytes(buf, &mut packet, len)?;
+    out.push(packet);
+    Ok(())
+}
+
+/// The client sends the slots it changed and a state id instead of the clicked item and an action number.
+fn click_window(mut buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(c2s::play::ClickWindow::get_id());
+    packet.put_u8(buf.try_get_u8()?);
+    let _state_id = buf.get_var_i32()?;
+    // slot and button
+    copy_bytes(buf, &mut packet, 3)?;
+    // the server answers with a window confirmation for it, which is turned into a ping
+    packet.put_i16(0);
+    copy_var_i32(buf, &mut packet)?;
+    let (changed_slots, _changed_slots_bytes) = buf.get_var_i32()?;
+    for _ in 0..changed_slots {
+        let _slot = buf.try_get_i16()?;
+        Slot::read(buf)?;
+    }
+    // the item now on the cursor, usually the one that was clicked. the server resyncs the window if it disagrees
+    Slot::write(&mut packet, &Slot::read(buf)?)?;
+    out.push(packet);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clientbound(packet: &[u8]) -> Vec<Vec<u8>> {
+        super::super::translate(step(), false, OLDER, NEWER, packet)
+    }
+
+    fn serverbound(packet: &[u8]) -> Vec<Vec<u8>> {
+        super::super::translate(step(), true, NEWER, OLDER, packet)
+    }
+
+    #[test]
+    fn window_confirmation_becomes_ping() {
+        let id = s2c::play::WindowConfirmation::get_id() as u8;
+        // window 2, action -3, rejected
+        assert_eq!(clientbound(&[id, 0x02, 0xFF, 0xFD, 0x00]), vec![vec![0x30, 0x40, 0x02, 0xFF, 0xFD]]);
+        assert!(clientbound(&[id, 0x02, 0xFF, 0xFD, 0x01]).is_empty());
+    }
+
+    #[test]
+    fn pong_becomes_window_confirmation() {
+        let id = c2s::play::WindowConfirmation::get_id() as u8;
+        assert_eq!(serverbound(&[0x1D, 0x40, 0x02, 0xFF, 0xFD]), vec![vec![id, 0x02, 0xFF, 0xFD, 0x01]]);
+        // answers to pings of the backend itself
+        assert!(serverbound(&[0x1D, 0x00, 0x00, 0x00, 0x05]).is_empty());
+    }
+
+    #[test]
+    fn chunk_data_mask_becomes_bit_set() {
+        let id = s2c::play::ChunkData::get_id() as u8;
+        let mut packet = vec![id, 0x00, 0x00, 0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        // mask, heightmaps, two biomes
+        packet.extend_from_slice(&[0x05, 0x0A, 0x00, 0x00, 0x00, 0x02, 0x01, 0xAC, 0x02]);
+        // sections and block entities
+        packet.extend_from_slice(&[0x02, 0xAA, 0xBB, 0x00]);
+        assert_eq!(clientbound(&packet), vec![vec![
+            0x22, 0x00, 0x00, 0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xFF,
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05,
+            0x0A, 0x00, 0x00, 0x00, 0x02, 0x01, 0xAC, 0x02,
+            0x02, 0xAA, 0xBB, 0x00,
+        ]]);
+
+        // partial chunks are dropped
+        packet[9] = 0x00;
+        assert!(clientbound(&packet).is_empty());
+    }
+
+    #[test]
+    fn update_light_masks_become_bit_sets() {
+        let id = s2c::play::UpdateLight::get_id() as u8;
+        let mut packet = vec![id, 0x03, 0xFE, 0xFF, 0xFF, 0xFF, 0x0F, 0x01];
+        // sky, block, empty sky and empty block light masks
+        packet.extend_from_slice(&[0x03, 0x02, 0x00, 0x04]);
+        // two sky light arrays and a block light array
+        packet.extend_from_slice(&[0x02, 0x01, 0x02, 0x02, 0x03, 0x04, 0x02, 0x05, 0x06]);
+        assert_eq!(clientbound(&packet), vec![vec![
+            0x25, 0x03, 0xFE, 0xFF, 0xFF, 0xFF, 0x0F, 0x01,
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03,
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+            0x00,
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04,
+            0x02, 0x02, 0x01, 0x02, 0x02, 0x03, 0x04,
+            0x01, 0x02, 0x05, 0x06,
+        ]]);
+    }
+
+    #[test]
+    fn click_window_sends_the_carried_item() {
+        let id = c2s::play::ClickWindow::get_id() as u8;
+        // window 1, state id 5, slot 10, button 0, mode 0
+        let mut packet = vec![0x08, 0x01, 0x05, 0x00, 0x0A, 0x00, 0x00];
+        // slot 10 is now empty, 3 of item 32 are carried
+        packet.extend_from_slice(&[0x01, 0x00, 0x0A, 0x00, 0x01, 0x20, 0x03, 0x00]);
+        assert_eq!(serverbound(&packet), vec![vec![id, 0x01, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x01, 0x20, 0x03, 0x00]]);
+    }
+
+    #[test]
+    fn copy_tag_list_adds_missing_tags() {
+        // "a" with the entries 1 and 2
+        let tags = [0x01, 0x01, b'a', 0x02, 0x01, 0x02];
+        let mut out = IndexedVec::new();
+        copy_tag_list(&mut &tags[..], &mut out, &["a", "b"]).unwrap();
+        assert_eq!(out.as_slice(), &[0x02, 0x01, b'a', 0x02, 0x01, 0x02, 0x01, b'b', 0x00]);
+    }
+}
@@ -0,0 +1,320 @@
+//! 1.17.1 packets for 1.18.2 clients, following https://wiki.vg/index.php?title=Protocol&oldid=17499
+
+use bytes::{Buf, BufMut};
+
+use packet_transformation::translation::{new_packet, TranslationStep};
+use packets::s2c;
+use utils::buffers::{VarInts, VarIntsMut};
+use utils::error::DecodeError;
+use utils::indexed_vec::IndexedVec;
+use utils::nbt::{Compound, Tag};
+use utils::sendable::{RawNbt, Sendable};
+use utils::Packet;
+
+use super::{copy_bytes, copy_rest, dimension_types, status_response};
+use super::v1_17_1::*;
+
+const OLDER: i32 = 756;
+const NEWER: i32 = 758;
+
+// ids of the 1.18.2 packets that aren't encoded like a packet struct
+const BLOCK_ENTITY_DATA: i32 = 0x0A;
+const CHUNK_DATA_AND_UPDATE_LIGHT: i32 = 0x22;
+
+// biomes are stored in cells of 4x4x4 blocks
+const BIOMES_PER_SECTION: usize = 64;
+// with more biomes in a section the global palette would be needed, its size depends on the dimension codec
+const MAX_SECTION_BIOMES: usize = 8;
+
+pub(super) fn step() -> TranslationStep {
+    let mut step = TranslationStep::new(OLDER, NEWER)
+        .clientbound(packets::PLAY_STATE, BLOCK_ENTITY_DATA, block_entity_data)
+        .clientbound(packets::PLAY_STATE, CHUNK_DATA, chunk_data)
+        .clientbound(packets::PLAY_STATE, JOIN_GAME, join_game)
+        .clientbound(packets::PLAY_STATE, RESPAWN, respawn)
+        .serverbound(packets::PLAY_STATE, CLIENT_SETTINGS, client_settings);
+
+    // added or changed in 1.17, the same in 1.18.2
+    let unchanged = [
+        SCULK_VIBRATION_SIGNAL, CLEAR_TITLES, WINDOW_ITEMS, SET_SLOT, EXPLOSION, INITIALIZE_WORLD_BORDER, UPDATE_LIGHT, MAP_DATA,
+        PING, END_COMBAT, ENTER_COMBAT, DEATH_COMBAT, PLAYER_POSITION_AND_LOOK, RESOURCE_PACK_SEND, ACTION_BAR,
+        WORLD_BORDER_CENTER, WORLD_BORDER_LERP_SIZE, WORLD_BORDER_SIZE, WORLD_BORDER_WARNING_DELAY, WORLD_BORDER_WARNING_REACH,
+        SPAWN_POSITION,
+    ];
+    for id in unchanged {
+        step = step.rename_clientbound(packets::PLAY_STATE, id, id);
+    }
+    // UpdateSimulationDistance was added before them
+    for id in [SET_TITLE_SUBTITLE, SET_TITLE_TEXT, SET_TITLE_TIMES, ENTITY_PROPERTIES, TAGS] {
+        step = step.rename_clientbound(packets::PLAY_STATE, id, id + 1);
+    }
+    for id in [CLICK_WINDOW, EDIT_BOOK, PONG] {
+        step = step.rename_serverbound(packets::PLAY_STATE, id, id);
+    }
+    status_response(step, OLDER, NEWER)
+}
+
+/// The simulation distance was added after the view distance.
+fn join_game(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut join_game = s2c::play::JoinGame::read(buf)?;
+    dimension_types(&mut join_game.dimension_codec).into_iter().for_each(infiniburn_tag);
+    dimension_types(&mut join_game.dimension).into_iter().for_each(infiniburn_tag);
+    let mut encoded = IndexedVec::new();
+    join_game.write(&mut encoded)?;
+    // followed by 4 booleans
+    let (start, end) = encoded.as_slice().split_at(encoded.as_slice().len() - 4);
+
+    let mut packet = new_packet(JOIN_GAME);
+    packet.put_slice(start);
+    packet.put_var_i32(join_game.view_distance.val);
+    packet.put_slice(end);
+    out.push(packet);
+    Ok(())
+}
+
+fn respawn(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut respawn = s2c::play::Respawn::read(buf)?;
+    dimension_types(&mut respawn.dimension).into_iter().for_each(infiniburn_tag);
+    let mut packet = new_packet(RESPAWN);
+    respawn.write(&mut packet)?;
+    out.push(packet);
+    Ok(())
+}
+
+/// The infiniburn block tag of a dimension type is referred to with a leading #.
+fn infiniburn_tag(dimension_type: &mut Compound) {
+    if let Some(Tag::String(infiniburn)) = dimension_type.get_mut("infiniburn") {
+        if !infiniburn.starts_with('#') {
+            infiniburn.insert(0, '#');
+        }
+    }
+}
+
+/// The action became the id of the block entity type.
+fn block_entity_data(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(BLOCK_ENTITY_DATA);
+    // location
+    copy_bytes(buf, &mut packet, 8)?;
+    let block_entity_type = match buf.try_get_u8()? {
+        1 => 8,   // mob spawner
+        2 => 21,  // command block
+        3 => 13,  // beacon
+        4 => 14,  // skull
+        5 => 24,  // conduit
+        6 => 18,  // banner
+        7 => 19,  // structure block
+        8 => 20,  // end gateway
+        9 => 7,   // sign
+        11 => 23, // bed
+        12 => 30, // jigsaw
+        13 => 31, // campfire
+        14 => 32, // beehive
+        _ => return Ok(())
+    };
+    packet.put_var_i32(block_entity_type);
+    copy_rest(buf, &mut packet);
+    out.push(packet);
+    Ok(())
+}
+
+/// Chunks come with their light and every section now, with the biomes of each section stored in it.
+fn chunk_data(mut buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(CHUNK_DATA_AND_UPDATE_LIGHT);
+    copy_bytes(buf, &mut packet, 8)?;
+    let (mask_len, _mask_len_bytes) = buf.get_var_i32()?;
+    let mask: Vec<u64> = (0..mask_len).map(|_| buf.try_get_u64()).collect::<Result<_, _>>()?;
+    // heightmaps
+    RawNbt::write(&mut packet, &RawNbt::read(buf)?)?;
+    let (biome_count, _biome_count_bytes) = buf.get_var_i32()?;
+    let biomes: Vec<i32> = (0..biome_count).map(|_| buf.get_var_i32().map(|(biome, _biome_bytes)| biome)).collect::<Result<_, _>>()?;
+    let (data_len, _data_len_bytes) = buf.get_var_i32()?;
+    if data_len < 0 || buf.remaining() < data_len as usize {
+        return Err(DecodeError::InvalidLength(data_len as i64));
+    }
+    let data = buf.copy_to_bytes(data_len as usize);
+    let mut data = &data[..];
+
+    // the biomes cover the whole height of the world
+    let mut sections = IndexedVec::new();
+    for (section, section_biomes) in biomes.chunks(BIOMES_PER_SECTION).enumerate() {
+        let present = mask.get(section / 64).is_some_and(|bits| bits & (1 << (section % 64)) != 0);
+        if present {
+            copy_section(&mut data, &mut sections)?;
+        } else {
+            // no blocks, a single value palette of air
+            sections.put_i16(0);
+            sections.put_u8(0);
+            sections.put_var_i32(0);
+            sections.put_var_i32(0);
+        }
+        put_biomes(&mut sections, section_biomes);
+    }
+    packet.put_var_i32(sections.as_slice().len() as i32);
+    packet.put_slice(sections.as_slice());
+
+    // block entities need their type id now, which can't be told from their nbt without the registry.
+    // their blocks are still there, what they show is filled in once they update
+    packet.put_var_i32(0);
+
+    // the light comes separately in UpdateLight, which is still sent
+    packet.put_u8(1);
+    for _ in 0..4 {
+        packet.put_var_i32(0);
+    }
+    packet.put_var_i32(0);
+    packet.put_var_i32(0);
+    out.push(packet);
+    Ok(())
+}
+
+/// Moves a 1.17 chunk section to `out`, it is encoded like the blocks of a 1.18 one.
+fn copy_section(data: &mut &[u8], out: &mut IndexedVec<u8>) -> Result<(), DecodeError> {
+    // block count
+    out.put_i16(data.try_get_i16()?);
+    let bits_per_block = data.try_get_u8()?;
+    out.put_u8(bits_per_block);
+    // larger ones use the global palette
+    if bits_per_block <= 8 {
+        let (palette_len, _palette_len_bytes) = data.get_var_i32()?;
+        out.put_var_i32(palette_len);
+        for _ in 0..palette_len {
+            out.put_var_i32(data.get_var_i32()?.0);
+        }
+    }
+    let (longs, _longs_bytes) = data.get_var_i32()?;
+    out.put_var_i32(longs);
+    if longs < 0 {
+        return Err(DecodeError::InvalidLength(longs as i64));
+    }
+    copy_bytes(data, out, longs as usize * 8)
+}
+
+/// Writes the biomes of a section as a paletted container.
+fn put_biomes(out: &mut IndexedVec<u8>, biomes: &[i32]) {
+    let mut palette: Vec<i32> = Vec::new();
+    for biome in biomes {
+        if !palette.contains(biome) && palette.len() < MAX_SECTION_BIOMES {
+            palette.push(*biome);
+        }
+    }
+    if palette.len() == 1 {
+        out.put_u8(0);
+        out.put_var_i32(palette[0]);
+        out.put_var_i32(0);
+        return;
+    }
+
+    let bits = (usize::BITS - (palette.len() - 1).leading_zeros()) as usize;
+    // entries don't span two longs
+    let per_long = 64 / bits;
+    let mut longs = vec![0u64; BIOMES_PER_SECTION.div_ceil(per_long)];
+    for (i, biome) in biomes.iter().enumerate() {
+        // biomes that didn't fit in the palette become the first one
+        let index = palette.iter().position(|entry| entry == biome).unwrap_or(0) as u64;
+        longs[i / per_long] |= index << (i % per_long * bits);
+    }
+    out.put_u8(bits as u8);
+    out.put_var_i32(palette.len() as i32);
+    for biome in palette {
+        out.put_var_i32(biome);
+    }
+    out.put_var_i32(longs.len() as i32);
+    for long in longs {
+        out.put_u64(long);
+    }
+}
+
+/// Allowing the player to show up in server listings was added at the end.
+fn client_settings(buf: &mut dyn Buf, out: &mut Vec<IndexedVec<u8>>) -> Result<(), DecodeError> {
+    let mut packet = new_packet(CLIENT_SETTINGS);
+    let len = buf.remaining().saturating_sub(1);
+    copy_bytes(buf, &mut packet, len)?;
+    out.push(packet);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clientbound(packet: &[u8]) -> Vec<Vec<u8>> {
+        super::super::translate(step(), false, OLDER, NEWER, packet)
+    }
+
+    // 16 blocks from a palette of air and block state 9
+    const SECTION: [u8; 15] = [0x00, 0x10, 0x04, 0x02, 0x00, 0x09, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF];
+
+    #[test]
+    fn copy_section_keeps_the_blocks() {
+        let mut out = IndexedVec::new();
+        copy_section(&mut &SECTION[..], &mut out).unwrap();
+        assert_eq!(out.as_slice(), &SECTION[..]);
+
+        // the global palette has no entries
+        let global = [0x10, 0x00, 0x0F, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2A];
+        let mut out = IndexedVec::new();
+        copy_section(&mut &global[..], &mut out).unwrap();
+        assert_eq!(out.as_slice(), &global[..]);
+    }
+
+    #[test]
+    fn put_biomes_single_value() {
+        let mut out = IndexedVec::new();
+        put_biomes(&mut out, &[300; BIOMES_PER_SECTION]);
+        assert_eq!(out.as_slice(), &[0x00, 0xAC, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn put_biomes_palette() {
+        // the lower half of the section is biome 1, the upper half biome 2
+        let mut biomes = [1; BIOMES_PER_SECTION];
+        biomes[32..].fill(2);
+        let mut out = IndexedVec::new();
+        put_biomes(&mut out, &biomes);
+        assert_eq!(out.as_slice(), &[0x01, 0x02, 0x01, 0x02, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn chunk_data_stores_biomes_in_sections() {
+        // chunk 1, -1 with only its lowest section
+        let mut packet = vec![0x22, 0x00, 0x00, 0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0, 0, 0, 0, 0, 0, 0, 0x01];
+        // heightmaps and the biomes of two sections
+        packet.extend_from_slice(&[0x0A, 0x00, 0x00, 0x00, 0x80, 0x01]);
+        packet.extend_from_slice(&[0x01; 2 * BIOMES_PER_SECTION]);
+        packet.push(SECTION.len() as u8);
+        packet.extend_from_slice(&SECTION);
+        // a block entity
+        packet.extend_from_slice(&[0x01, 0x00, 0x00, 0x00, 0x00]);
+
+        let mut expected = vec![0x22, 0x00, 0x00, 0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x0A, 0x00, 0x00, 0x00, 0x1A];
+        expected.extend_from_slice(&SECTION);
+        expected.extend_from_slice(&[0x00, 0x01, 0x00]);
+        // the empty section
+        expected.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00]);
+        // no block entities, no light
+        expected.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(clientbound(&packet), vec![expected]);
+    }
+
+    #[test]
+    fn block_entity_data_action_becomes_type() {
+        let location = [0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x10, 0x05];
+        let mut packet = vec![0x0A];
+        packet.extend_from_slice(&location);
+        // a sign without nbt
+        packet.extend_from_slice(&[0x09, 0x00]);
+        let mut expected = vec![0x0A];
+        expected.extend_from_slice(&location);
+        expected.extend_from_slice(&[0x07, 0x00]);
+        assert_eq!(clientbound(&packet), vec![expected]);
+    }
+
+    #[test]
+    fn infiniburn_tag_is_prefixed() {
+        let mut dimension_type = Compound::new();
+        dimension_type.insert("infiniburn", "minecraft:infiniburn_overworld");
+        infiniburn_tag(&mut dimension_type);
+        infiniburn_tag(&mut dimension_type);
+        assert_eq!(dimension_type.get_mut("infiniburn"), Some(&mut Tag::String("#minecraft:infiniburn_overworld".to_string())));
+    }
+}
@@ -6,12 +6,24 @@ use mio::net::TcpStream;
 
 use crate::buffers::{VarInts, VarIntsMut};
 use crate::contexts::ConnectionContext;
+use crate::error::DecodeError;
 use crate::indexed_vec::IndexedVec;
 
+/// Largest size a compressed packet may claim to have once it is decompressed, the limit vanilla uses.
+pub const MAX_DECOMPRESSED_LEN: usize = 2 * 1024 * 1024;
+
 pub fn read_socket(ctx: &mut ConnectionContext, packet: &mut IndexedVec<u8>) -> bool {
-    let result = ctx.stream.read(packet.as_mut_write_slice());
+    let stream = match &mut ctx.stream {
+        Some(stream) => stream,
+        None => return false
+    };
+    let result = stream.read(packet.as_mut_write_slice());
     match result {
         Ok(read) => {
+            if let Some(encryption) = &mut ctx.encryption {
+                let start = packet.get_writer_index();
+                encryption.decrypt(&mut packet.vec[start..start + read]);
+            }
             packet.advance_writer_index(read);
             if read == 0 && packet.vec.len() > packet.get_writer_index() {
                 println!("read 0");
@@ -35,21 +47,33 @@ pub fn read_socket(ctx: &mut ConnectionContext, packet: &mut IndexedVec<u8>) ->
 }
 
 pub fn write_socket(ctx: &mut ConnectionContext, packet: &mut IndexedVec<u8>) {
-    if ctx.is_writable {
-        if !write_socket0(&mut ctx.stream, packet, &mut ctx.should_close) {
-            buffer_write(ctx, packet);
-            ctx.is_writable = false;
+    if let Some(encryption) = &mut ctx.encryption {
+        let range = packet.get_reader_index()..packet.get_writer_index();
+        encryption.encrypt(&mut packet.vec[range]);
+    }
+    match &mut ctx.stream {
+        Some(stream) if ctx.is_writable => {
+            if !write_socket0(stream, packet, &mut ctx.should_close) {
+                buffer_write(ctx, packet);
+                ctx.is_writable = false;
+            }
         }
-    } else {
-        buffer_write(ctx, packet);
+        _ => buffer_write(ctx, packet)
     }
 }
 
 pub fn write_socket_slice(ctx: &mut ConnectionContext, packet: &[u8]) {
-    if ctx.is_writable {
+    if ctx.encryption.is_some() {
+        // the slice can't be encrypted in place
+        let mut buf = IndexedVec::new();
+        buf.put_slice(packet);
+        write_socket(ctx, &mut buf);
+        return;
+    }
+    if let (Some(stream), true) = (&mut ctx.stream, ctx.is_writable) {
         let mut total_written = 0;
         loop {
-            let result = ctx.stream.write(&packet[total_written..]);
+            let result = stream.write(&packet[total_written..]);
             match result {
                 Ok(written) => {
                     total_written += written;
@@ -184,14 +208,22 @@ pub fn get_needed_data(read_buf: &mut IndexedVec<u8>, connection_ctx: &mut Conne
     }
 }
 
-pub fn decompress_packet<'a>(real_length: usize, working_buf: &mut &'a[u8], decompressor: &mut Decompressor, compression_buffer: &'a mut IndexedVec<u8>) {
+pub fn decompress_packet<'a>(real_length: usize, working_buf: &mut &'a[u8], decompressor: &mut Decompressor, compression_buffer: &'a mut IndexedVec<u8>) -> Result<(), DecodeError> {
+    if real_length > MAX_DECOMPRESSED_LEN {
+        return Err(DecodeError::InvalidLength(real_length as i64));
+    }
     compression_buffer.ensure_writable(real_length);
 
     //decompress
-    decompressor.zlib_decompress(working_buf, compression_buffer.as_mut_write_slice()).unwrap();
+    let written = decompressor.zlib_decompress(working_buf, &mut compression_buffer.as_mut_write_slice()[..real_length])
+        .map_err(|_| DecodeError::Decompression)?;
+    if written != real_length {
+        return Err(DecodeError::Decompression);
+    }
     compression_buffer.set_writer_index(real_length);
 
     *working_buf = compression_buffer.as_slice();
+    Ok(())
 }
 
 pub fn compress_packet<'a>(packet: &mut &'a[u8], compressor: &mut Compressor, compression_buffer: &'a mut IndexedVec<u8>) {
@@ -1,12 +1,13 @@
 use bytes::{Buf, BufMut};
+use crate::error::DecodeError;
 use crate::set_vec_len;
 
 pub trait VarInts {
-    fn get_var_i32(&mut self) -> (i32, i32);
+    fn get_var_i32(&mut self) -> Result<(i32, i32), DecodeError>;
 
     fn get_var_i32_limit(&mut self, max_size: u32) -> Option<(i32, i32)>;
 
-    fn get_var_i64(&mut self) -> (i64, i64);
+    fn get_var_i64(&mut self) -> Result<(i64, i64), DecodeError>;
 }
 
 pub trait VarIntsMut {
@@ -16,22 +17,22 @@ pub trait VarIntsMut {
 }
 
 impl<T: Buf> VarInts for T {
-    fn get_var_i32(&mut self) -> (i32, i32) {
+    fn get_var_i32(&mut self) -> Result<(i32, i32), DecodeError> {
         let mut num_read = 0i32;
         let mut result = 0i32;
         let mut read;
         loop {
-            read = self.get_u8() as i32;
+            read = self.try_get_u8()? as i32;
             result |= (read & 0b01111111).overflowing_shl((7 * num_read) as u32).0;
             num_read += 1;
             if num_read > 5 {
-                panic!("VarInt is too big")
+                return Err(DecodeError::VarIntTooBig);
             }
             if read & 0b10000000 == 0 {
                 break;
             }
         }
-        (result, num_read)
+        Ok((result, num_read))
     }
 
     fn get_var_i32_limit(&mut self, max_size: u32) -> Option<(i32, i32)> {
@@ -39,7 +40,7 @@ impl<T: Buf> VarInts for T {
         let mut result = 0i32;
         let mut read;
         while num_read < max_size as i32 {
-            read = self.get_u8() as i32;
+            read = self.try_get_u8().ok()? as i32;
             result |= (read & 0b01111111).overflowing_shl((7 * num_read) as u32).0;
             num_read += 1;
             if num_read > 5 {
@@ -53,22 +54,22 @@ impl<T: Buf> VarInts for T {
         None
     }
 
-    fn get_var_i64(&mut self) -> (i64, i64) {
+    fn get_var_i64(&mut self) -> Result<(i64, i64), DecodeError> {
         let mut num_read = 0i64;
         let mut result = 0i64;
         let mut read;
         loop {
-            read = self.get_u8() as i64;
+            read = self.try_get_u8()? as i64;
             result |= (read & 0b01111111).overflowing_shl((7 * num_read) as u32).0;
             num_read += 1;
-            if num_read > 5 {
-                panic!("VarInt is too big")
+            if num_read > 10 {
+                return Err(DecodeError::VarIntTooBig);
             }
             if read & 0b10000000 == 0 {
                 break;
             }
         }
-        (result, num_read)
+        Ok((result, num_read))
     }
 }
 
@@ -115,7 +116,7 @@ impl<T: BufMut> VarIntsMut for T {
 }
 
 pub trait Bools {
-    fn get_bool(&mut self) -> bool;
+    fn get_bool(&mut self) -> Result<bool, DecodeError>;
 }
 
 pub trait BoolsMut {
@@ -123,8 +124,8 @@ pub trait BoolsMut {
 }
 
 impl<T: Buf> Bools for T {
-    fn get_bool(&mut self) -> bool {
-        self.get_u8() != 0
+    fn get_bool(&mut self) -> Result<bool, DecodeError> {
+        Ok(self.try_get_u8()? != 0)
     }
 }
 
@@ -135,7 +136,7 @@ impl<T: BufMut> BoolsMut for T {
 }
 
 pub trait Strings {
-    fn get_string(&mut self) -> String;
+    fn get_string(&mut self) -> Result<String, DecodeError>;
 }
 
 pub trait StringsMut {
@@ -143,12 +144,16 @@ pub trait StringsMut {
 }
 
 impl<T: Buf> Strings for T {
-    fn get_string(&mut self) -> String {
-        let len = self.get_var_i32().0;
+    fn get_string(&mut self) -> Result<String, DecodeError> {
+        let len = self.get_var_i32()?.0;
+        // checked before allocating
+        if len < 0 || len as usize > self.remaining() {
+            return Err(DecodeError::InvalidLength(len as i64));
+        }
         let mut slice = Vec::new();
         set_vec_len(&mut slice, len as usize);
         self.copy_to_slice(&mut slice);
-        String::from_utf8_lossy(&slice).to_string()
+        Ok(String::from_utf8_lossy(&slice).to_string())
     }
 }
 
@@ -0,0 +1,617 @@
+//! Chat components, the json text of chat messages, disconnect reasons, titles and the server list.
+
+use bytes::{Buf, BufMut};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::buffers::{Strings, StringsMut};
+use crate::error::{DecodeError, EncodeError};
+use crate::sendable::Sendable;
+
+/// A piece of text with its style, followed by its `extra` components which inherit the style.
+///
+/// Built with [`Component::text`] or [`Component::translate`] and the style methods:
+/// `Component::text("Hello").color(Color::Gold).bold(true).append(" world")`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Component {
+    pub text: Option<String>,
+    /// A key of the client's language file, `with` fills in its arguments.
+    pub translate: Option<String>,
+    pub with: Vec<Component>,
+    /// Shows the key bound to a control, like `key.jump`.
+    pub keybind: Option<String>,
+    pub color: Option<Color>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+    /// Inserted into the chat box when the text is shift clicked.
+    pub insertion: Option<String>,
+    pub font: Option<String>,
+    pub click_event: Option<ClickEvent>,
+    pub hover_event: Option<HoverEvent>,
+    pub extra: Vec<Component>,
+    // keys this type doesn't model, like score and selector contents, written back as they were
+    other: Map<String, Value>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    DarkBlue,
+    DarkGreen,
+    DarkAqua,
+    DarkRed,
+    DarkPurple,
+    Gold,
+    Gray,
+    DarkGray,
+    Blue,
+    Green,
+    Aqua,
+    Red,
+    LightPurple,
+    Yellow,
+    White,
+    /// An rgb color, `0xRRGGBB`.
+    Hex(u32),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClickEvent {
+    pub action: ClickAction,
+    pub value: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickAction {
+    OpenUrl,
+    RunCommand,
+    SuggestCommand,
+    ChangePage,
+    CopyToClipboard,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HoverEvent {
+    ShowText(Box<Component>),
+    /// Items and entities, the hover event json as it was.
+    Other(Value),
+}
+
+// the named colors in the order of their legacy codes
+const NAMED_COLORS: [(Color, &str); 16] = [
+    (Color::Black, "black"),
+    (Color::DarkBlue, "dark_blue"),
+    (Color::DarkGreen, "dark_green"),
+    (Color::DarkAqua, "dark_aqua"),
+    (Color::DarkRed, "dark_red"),
+    (Color::DarkPurple, "dark_purple"),
+    (Color::Gold, "gold"),
+    (Color::Gray, "gray"),
+    (Color::DarkGray, "dark_gray"),
+    (Color::Blue, "blue"),
+    (Color::Green, "green"),
+    (Color::Aqua, "aqua"),
+    (Color::Red, "red"),
+    (Color::LightPurple, "light_purple"),
+    (Color::Yellow, "yellow"),
+    (Color::White, "white"),
+];
+
+const LEGACY_CODES: &str = "0123456789abcdef";
+const SECTION_SIGN: char = '§';
+
+impl Component {
+    pub fn text(text: impl Into<String>) -> Component {
+        Component { text: Some(text.into()), ..Component::default() }
+    }
+
+    pub fn translate(key: impl Into<String>) -> Component {
+        Component { translate: Some(key.into()), ..Component::default() }
+    }
+
+    pub fn keybind(key: impl Into<String>) -> Component {
+        Component { keybind: Some(key.into()), ..Component::default() }
+    }
+
+    /// Adds an argument of the translation.
+    pub fn with(mut self, argument: impl Into<Component>) -> Component {
+        self.with.push(argument.into());
+        self
+    }
+
+    pub fn color(mut self, color: Color) -> Component {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn bold(mut self, bold: bool) -> Component {
+        self.bold = Some(bold);
+        self
+    }
+
+    pub fn italic(mut self, italic: bool) -> Component {
+        self.italic = Some(italic);
+        self
+    }
+
+    pub fn underlined(mut self, underlined: bool) -> Component {
+        self.underlined = Some(underlined);
+        self
+    }
+
+    pub fn strikethrough(mut self, strikethrough: bool) -> Component {
+        self.strikethrough = Some(strikethrough);
+        self
+    }
+
+    pub fn obfuscated(mut self, obfuscated: bool) -> Component {
+        self.obfuscated = Some(obfuscated);
+        self
+    }
+
+    pub fn insertion(mut self, insertion: impl Into<String>) -> Component {
+        self.insertion = Some(insertion.into());
+        self
+    }
+
+    pub fn font(mut self, font: impl Into<String>) -> Component {
+        self.font = Some(font.into());
+        self
+    }
+
+    pub fn click_event(mut self, click_event: ClickEvent) -> Component {
+        self.click_event = Some(click_event);
+        self
+    }
+
+    pub fn hover_event(mut self, hover_event: HoverEvent) -> Component {
+        self.hover_event = Some(hover_event);
+        self
+    }
+
+    /// Shows `text` when the component is hovered.
+    pub fn hover_text(self, text: impl Into<Component>) -> Component {
+        self.hover_event(HoverEvent::ShowText(Box::new(text.into())))
+    }
+
+    /// Adds a component after this one, it inherits the style of this one.
+    pub fn append(mut self, component: impl Into<Component>) -> Component {
+        self.extra.push(component.into());
+        self
+    }
+
+    /// Parses chat json, which can also be a plain string or an array of components.
+    pub fn from_json(json: &str) -> serde_json::Result<Component> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    /// The text without any formatting, translations show their key.
+    pub fn plain_text(&self) -> String {
+        let mut text = self.content().to_string();
+        for component in self.extra.iter() {
+            text.push_str(&component.plain_text());
+        }
+        text
+    }
+
+    /// The text with its colors and formatting as `§` codes, like clients before 1.7 show it.
+    /// Hex colors use the `§x§r§r§g§g§b§b` form, events and fonts are lost.
+    pub fn to_legacy(&self) -> String {
+        let mut legacy = String::new();
+        self.put_legacy(&mut legacy, LegacyStyle::default(), &mut LegacyStyle::default());
+        legacy
+    }
+
+    /// Reads text with `§` color and formatting codes.
+    pub fn from_legacy(legacy: &str) -> Component {
+        let mut components = Vec::new();
+        let mut style = LegacyStyle::default();
+        let mut text = String::new();
+        let mut chars = legacy.chars().peekable();
+        while let Some(char) = chars.next() {
+            let code = match chars.peek() {
+                Some(code) if char == SECTION_SIGN => code.to_ascii_lowercase(),
+                _ => {
+                    text.push(char);
+                    continue;
+                }
+            };
+            chars.next();
+            if !text.is_empty() {
+                components.push(style.apply(Component::text(std::mem::take(&mut text))));
+            }
+            match code {
+                'k' => style.obfuscated = true,
+                'l' => style.bold = true,
+                'm' => style.strikethrough = true,
+                'n' => style.underlined = true,
+                'o' => style.italic = true,
+                'r' => style = LegacyStyle::default(),
+                'x' => {
+                    // six more codes, one per hex digit
+                    let digits: String = chars.clone().take(12).collect();
+                    let digits: Option<String> = digits.chars().collect::<Vec<_>>().chunks(2)
+                        .map(|pair| if pair.len() == 2 && pair[0] == SECTION_SIGN { Some(pair[1]) } else { None })
+                        .collect();
+                    if let Some(rgb) = digits.filter(|digits| digits.len() == 6).and_then(|digits| u32::from_str_radix(&digits, 16).ok()) {
+                        chars.nth(11);
+                        style = LegacyStyle { color: Some(Color::Hex(rgb)), ..LegacyStyle::default() };
+                    }
+                }
+                code => if let Some(color) = Color::from_legacy(code) {
+                    // colors reset the formatting
+                    style = LegacyStyle { color: Some(color), ..LegacyStyle::default() };
+                }
+            }
+        }
+        if !text.is_empty() {
+            components.push(style.apply(Component::text(text)));
+        }
+
+        if components.len() == 1 {
+            components.pop().unwrap()
+        } else {
+            Component { extra: components, ..Component::text("") }
+        }
+    }
+
+    /// What the component shows itself, without its extra components.
+    fn content(&self) -> &str {
+        self.text.as_deref()
+            .or(self.translate.as_deref())
+            .or(self.keybind.as_deref())
+            .unwrap_or_default()
+    }
+
+    fn put_legacy(&self, legacy: &mut String, parent: LegacyStyle, current: &mut LegacyStyle) {
+        let style = LegacyStyle {
+            color: self.color.or(parent.color),
+            bold: self.bold.unwrap_or(parent.bold),
+            italic: self.italic.unwrap_or(parent.italic),
+            underlined: self.underlined.unwrap_or(parent.underlined),
+            strikethrough: self.strikethrough.unwrap_or(parent.strikethrough),
+            obfuscated: self.obfuscated.unwrap_or(parent.obfuscated),
+        };
+        let content = self.content();
+        if !content.is_empty() {
+            if style != *current {
+                // formatting can only be added, everything else needs a reset which a color code does as well
+                let only_added = style.color == current.color && current.formats().iter().zip(style.formats().iter()).all(|(old, new)| !old.0 || new.0);
+                if !only_added {
+                    match style.color {
+                        Some(color) => color.put_legacy(legacy),
+                        None => legacy.extend([SECTION_SIGN, 'r']),
+                    }
+                    *current = LegacyStyle { color: style.color, ..LegacyStyle::default() };
+                }
+                for ((enabled, code), (was_enabled, _)) in style.formats().iter().zip(current.formats().iter()) {
+                    if *enabled && !was_enabled {
+                        legacy.extend([SECTION_SIGN, *code]);
+                    }
+                }
+                *current = style;
+            }
+            legacy.push_str(content);
+        }
+        for component in self.extra.iter() {
+            component.put_legacy(legacy, style, current);
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        let mut object = Map::new();
+        if let Some(translate) = &self.translate {
+            object.insert("translate".to_string(), translate.as_str().into());
+            if !self.with.is_empty() {
+                object.insert("with".to_string(), self.with.iter().map(Component::to_value).collect());
+            }
+        }
+        if let Some(keybind) = &self.keybind {
+            object.insert("keybind".to_string(), keybind.as_str().into());
+        }
+        // every component needs contents
+        if self.text.is_some() || (object.is_empty() && self.other.is_empty()) {
+            object.insert("text".to_string(), self.text.as_deref().unwrap_or_default().into());
+        }
+        if let Some(color) = self.color {
+            object.insert("color".to_string(), color.name().into());
+        }
+        let formats = [
+            ("bold", self.bold),
+            ("italic", self.italic),
+            ("underlined", self.underlined),
+            ("strikethrough", self.strikethrough),
+            ("obfuscated", self.obfuscated),
+        ];
+        for (name, value) in formats {
+            if let Some(value) = value {
+                object.insert(name.to_string(), value.into());
+            }
+        }
+        if let Some(insertion) = &self.insertion {
+            object.insert("insertion".to_string(), insertion.as_str().into());
+        }
+        if let Some(font) = &self.font {
+            object.insert("font".to_string(), font.as_str().into());
+        }
+        if let Some(click_event) = &self.click_event {
+            object.insert("clickEvent".to_string(), serde_json::to_value(click_event).unwrap());
+        }
+        match &self.hover_event {
+            Some(HoverEvent::ShowText(text)) => {
+                object.insert("hoverEvent".to_string(), serde_json::json!({ "action": "show_text", "contents": text.to_value() }));
+            }
+            Some(HoverEvent::Other(hover_event)) => {
+                object.insert("hoverEvent".to_string(), hover_event.clone());
+            }
+            None => {}
+        }
+        for (key, value) in self.other.iter() {
+            object.insert(key.clone(), value.clone());
+        }
+        if !self.extra.is_empty() {
+            object.insert("extra".to_string(), self.extra.iter().map(Component::to_value).collect());
+        }
+        Value::Object(object)
+    }
+
+    /// Components that can't be understood become their json as text.
+    fn from_value(value: Value) -> Component {
+        let mut object = match value {
+            Value::String(text) => return Component::text(text),
+            Value::Array(components) => {
+                // the first component is the parent of the others
+                let mut components = components.into_iter().map(Component::from_value);
+                let first = components.next().unwrap_or_default();
+                return components.fold(first, Component::append);
+            }
+            Value::Object(object) => object,
+            value => return Component::text(value.to_string())
+        };
+
+        let mut component = Component::default();
+        let mut take_string = |key: &str| match object.remove(key) {
+            Some(Value::String(value)) => Some(value),
+            // numbers and booleans are allowed as text
+            Some(value @ (Value::Number(_) | Value::Bool(_))) => Some(value.to_string()),
+            Some(value) => {
+                object.insert(key.to_string(), value);
+                None
+            }
+            None => None
+        };
+        component.text = take_string("text");
+        component.translate = take_string("translate");
+        component.keybind = take_string("keybind");
+        component.insertion = take_string("insertion");
+        component.font = take_string("font");
+        if let Some(color) = take_string("color") {
+            component.color = Color::from_name(&color);
+        }
+        for (name, format) in [
+            ("bold", &mut component.bold),
+            ("italic", &mut component.italic),
+            ("underlined", &mut component.underlined),
+            ("strikethrough", &mut component.strikethrough),
+            ("obfuscated", &mut component.obfuscated),
+        ] {
+            *format = object.remove(name).and_then(|value| value.as_bool());
+        }
+        for (name, components) in [("with", &mut component.with), ("extra", &mut component.extra)] {
+            if let Some(Value::Array(values)) = object.remove(name) {
+                *components = values.into_iter().map(Component::from_value).collect();
+            }
+        }
+        if let Some(click_event) = object.remove("clickEvent") {
+            component.click_event = serde_json::from_value(click_event).ok();
+        }
+        if let Some(hover_event) = object.remove("hoverEvent") {
+            component.hover_event = Some(match hover_event["action"].as_str() {
+                // contents since 1.16, value before
+                Some("show_text") => match hover_event.get("contents").or_else(|| hover_event.get("value")) {
+                    Some(text) => HoverEvent::ShowText(Box::new(Component::from_value(text.clone()))),
+                    None => HoverEvent::Other(hover_event)
+                },
+                _ => HoverEvent::Other(hover_event)
+            });
+        }
+        component.other = object;
+        component
+    }
+}
+
+impl From<&str> for Component {
+    fn from(text: &str) -> Self {
+        Component::text(text)
+    }
+}
+
+impl From<String> for Component {
+    fn from(text: String) -> Self {
+        Component::text(text)
+    }
+}
+
+impl Serialize for Component {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Component {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Value::deserialize(deserializer).map(Component::from_value)
+    }
+}
+
+/// Sent as a json string, json that can't be parsed is kept as text.
+impl Sendable for Component {
+    fn read(mut buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        let json = buffer.get_string()?;
+        Ok(Component::from_json(&json).unwrap_or_else(|_| Component::text(json)))
+    }
+
+    fn write(mut buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_string(&data.to_json());
+        Ok(())
+    }
+}
+
+impl Color {
+    /// The name the color has in json, `#rrggbb` for hex colors.
+    pub fn name(&self) -> String {
+        match self {
+            Color::Hex(rgb) => format!("#{:06x}", rgb),
+            color => NAMED_COLORS.iter().find(|(named, _)| named == color).unwrap().1.to_string(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Color> {
+        match name.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 => u32::from_str_radix(hex, 16).ok().map(Color::Hex),
+            Some(_) => None,
+            None => NAMED_COLORS.iter().find(|(_, named)| *named == name).map(|(color, _)| *color)
+        }
+    }
+
+    fn from_legacy(code: char) -> Option<Color> {
+        LEGACY_CODES.find(code).map(|index| NAMED_COLORS[index].0)
+    }
+
+    fn put_legacy(&self, legacy: &mut String) {
+        match self {
+            Color::Hex(rgb) => {
+                legacy.extend([SECTION_SIGN, 'x']);
+                for digit in format!("{:06x}", rgb).chars() {
+                    legacy.extend([SECTION_SIGN, digit]);
+                }
+            }
+            color => {
+                let index = NAMED_COLORS.iter().position(|(named, _)| named == color).unwrap();
+                legacy.extend([SECTION_SIGN, LEGACY_CODES.as_bytes()[index] as char]);
+            }
+        }
+    }
+}
+
+impl ClickEvent {
+    pub fn open_url(url: impl Into<String>) -> ClickEvent {
+        ClickEvent { action: ClickAction::OpenUrl, value: url.into() }
+    }
+
+    pub fn run_command(command: impl Into<String>) -> ClickEvent {
+        ClickEvent { action: ClickAction::RunCommand, value: command.into() }
+    }
+
+    pub fn suggest_command(command: impl Into<String>) -> ClickEvent {
+        ClickEvent { action: ClickAction::SuggestCommand, value: command.into() }
+    }
+
+    /// Turns the page of a book.
+    pub fn change_page(page: u32) -> ClickEvent {
+        ClickEvent { action: ClickAction::ChangePage, value: page.to_string() }
+    }
+
+    pub fn copy_to_clipboard(text: impl Into<String>) -> ClickEvent {
+        ClickEvent { action: ClickAction::CopyToClipboard, value: text.into() }
+    }
+}
+
+/// The style legacy codes can express, with inherited values filled in.
+#[derive(Clone, Copy, Default, PartialEq)]
+struct LegacyStyle {
+    color: Option<Color>,
+    bold: bool,
+    italic: bool,
+    underlined: bool,
+    strikethrough: bool,
+    obfuscated: bool,
+}
+
+impl LegacyStyle {
+    /// Each format with its code, in the order they are written.
+    fn formats(&self) -> [(bool, char); 5] {
+        [(self.obfuscated, 'k'), (self.bold, 'l'), (self.strikethrough, 'm'), (self.underlined, 'n'), (self.italic, 'o')]
+    }
+
+    fn apply(&self, component: Component) -> Component {
+        let enabled = |enabled: bool| if enabled { Some(true) } else { None };
+        Component {
+            color: self.color,
+            bold: enabled(self.bold),
+            italic: enabled(self.italic),
+            underlined: enabled(self.underlined),
+            strikethrough: enabled(self.strikethrough),
+            obfuscated: enabled(self.obfuscated),
+            ..component
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffers::StringsMut;
+
+    use super::*;
+
+    #[test]
+    fn legacy_round_trip() {
+        for legacy in ["plain", "§6Hello §lworld§r plain", "§c§l§nWarning§7: §oquiet", "§kx§ay"] {
+            assert_eq!(Component::from_legacy(legacy).to_legacy(), legacy);
+        }
+        // a color resets the formatting on its own
+        assert_eq!(Component::from_legacy("§kx§r§ay").to_legacy(), "§kx§ay");
+
+        let component = Component::text("Hello ").color(Color::Gold)
+            .append(Component::text("world").bold(true))
+            .append(Component::text("!").color(Color::Red));
+        let legacy = component.to_legacy();
+        assert_eq!(legacy, "§6Hello §lworld§c!");
+        assert_eq!(Component::from_legacy(&legacy).to_legacy(), legacy);
+    }
+
+    #[test]
+    fn legacy_hex_colors() {
+        let component = Component::from_legacy("§x§F§F§8§0§0§0orange");
+        assert_eq!(component.text.as_deref(), Some("orange"));
+        assert_eq!(component.color, Some(Color::Hex(0xff8000)));
+        assert_eq!(component.to_legacy(), "§x§f§f§8§0§0§0orange");
+
+        // too few digits, the codes are read on their own
+        let component = Component::from_legacy("§x§f§fwhite");
+        assert_eq!(component.color, Some(Color::White));
+        assert_eq!(component.plain_text(), "white");
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let component = Component::text("Hello").color(Color::Hex(0x123456)).bold(true)
+            .click_event(ClickEvent::run_command("/spawn"))
+            .hover_text("Go home")
+            .append(Component::translate("chat.type.text").with("Steve").with("hi"));
+        assert_eq!(Component::from_json(&component.to_json()).unwrap(), component);
+    }
+
+    #[test]
+    fn unparsable_json() {
+        assert!(Component::from_json("{\"text\":").is_err());
+
+        // sent as text, not an error
+        let mut bytes = Vec::new();
+        bytes.put_string("{\"text\":");
+        let component = Component::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(component, Component::text("{\"text\":"));
+
+        // valid json that isn't a component
+        assert_eq!(Component::from_json("42").unwrap(), Component::text("42"));
+        assert_eq!(Component::from_json("\"hi\"").unwrap(), Component::text("hi"));
+    }
+}
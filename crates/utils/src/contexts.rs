@@ -1,12 +1,18 @@
 use std::collections::HashMap;
+use std::io;
 use std::sync::Arc;
 use std::sync::mpsc::{SendError, SyncSender};
-use std::thread::JoinHandle;
+use std::thread::Thread;
 
 use mio::{Interest, Poll, Token};
 use mio::net::TcpStream;
 
+use crate::chat::Component;
+use crate::encryption::Encryption;
+use crate::error::EncodeError;
 use crate::indexed_vec::IndexedVec;
+use crate::profile::GameProfile;
+use crate::protocol::ProtocolIds;
 use crate::{Packet, get_var_i32_size};
 use crate::buffers::VarIntsMut;
 use crate::buffer_helpers::{compress_packet, write_socket};
@@ -14,7 +20,7 @@ use libdeflater::{Compressor, CompressionLvl};
 use bytes::BufMut;
 
 pub struct PaxyThread {
-    pub thread: JoinHandle<()>,
+    pub thread: Thread,
     pub channel: SyncSender<Message>,
 }
 
@@ -29,56 +35,127 @@ impl PaxyThread {
 pub struct ConnectionContext {
     pub token_self: Token,
     pub token_other: Token,
-    pub stream: TcpStream,
+    /// `None` for a backend whose first attempt at connecting failed right away, until it is retried.
+    pub stream: Option<TcpStream>,
     pub compression_threshold: i32,
     pub state: u8,
     pub should_close: bool,
+    /// Closes the connection once everything buffered for it was written, for answers that end the connection.
+    pub close_when_flushed: bool,
     pub read_buffering: IndexedVec<u8>,
     pub write_buffering: IndexedVec<u8>,
     pub is_writable: bool,
     pub inbound: bool,
+    /// Protocol version from the handshake, set on both sides.
+    pub protocol_version: i32,
+    /// Packet ids of the protocol version, `None` until the handshake or if the version isn't supported.
+    pub protocol: Option<&'static ProtocolIds>,
+    /// Hostname and port the client connected with, from the handshake. Only set on the client side.
+    pub hostname: Option<String>,
+    pub port: u16,
+    /// Name the client logged in with, only set on the client side.
+    pub username: Option<String>,
+    /// Name of the backend server, set on both sides once it is picked.
+    pub server: Option<String>,
+    /// Set once encryption is enabled, every byte read or written after that goes through it.
+    /// Each side has its own, the client and backend sides never share a key.
+    pub encryption: Option<Encryption>,
+    /// Token the client has to send back encrypted when the proxy authenticates it.
+    pub verify_token: Option<[u8; 4]>,
+    /// Profile of a client that was authenticated by the proxy.
+    pub profile: Option<GameProfile>,
 }
 
 impl ConnectionContext {
-    pub fn create_pair(id: usize, mut c2s: TcpStream, mut s2c: TcpStream, poll: &Poll, connections: &mut HashMap<Token, ConnectionContext>) {
-        let c2s_token = Token(id * 2);
-        let s2c_token = Token(id * 2 + 1);
-        let registry = poll.registry();
-        registry.register(&mut c2s, c2s_token, Interest::READABLE | Interest::WRITABLE).unwrap();
-        registry.register(&mut s2c, s2c_token, Interest::READABLE | Interest::WRITABLE).unwrap();
-        let c2s_context = ConnectionContext {
-            token_self: c2s_token,
-            token_other: s2c_token,
-            stream: c2s,
-            compression_threshold: 0,
-            state: 0,
-            should_close: false,
-            read_buffering: IndexedVec::new(),
-            write_buffering: IndexedVec::new(),
-            is_writable: true,
-            inbound: true,
-        };
-        let s2c_context = ConnectionContext {
-            token_self: s2c_token,
-            token_other: c2s_token,
-            stream: s2c,
+    /// Creates the context of a socket, it still needs to be registered with [`ConnectionContext::register`].
+    /// Data sent to a socket that isn't writable yet is buffered until it becomes writable.
+    pub fn new(token_self: Token, token_other: Token, stream: TcpStream, inbound: bool, is_writable: bool) -> ConnectionContext {
+        ConnectionContext::with_stream(token_self, token_other, Some(stream), inbound, is_writable)
+    }
+
+    /// Creates the context of a backend that has no socket yet, data sent to it is buffered until it gets one and connects.
+    pub fn without_stream(token_self: Token, token_other: Token) -> ConnectionContext {
+        ConnectionContext::with_stream(token_self, token_other, None, false, false)
+    }
+
+    fn with_stream(token_self: Token, token_other: Token, stream: Option<TcpStream>, inbound: bool, is_writable: bool) -> ConnectionContext {
+        ConnectionContext {
+            token_self,
+            token_other,
+            stream,
             compression_threshold: 0,
             state: 0,
             should_close: false,
+            close_when_flushed: false,
             read_buffering: IndexedVec::new(),
             write_buffering: IndexedVec::new(),
-            is_writable: true,
-            inbound: false,
-        };
-        connections.insert(c2s_token, c2s_context);
-        connections.insert(s2c_token, s2c_context);
+            is_writable,
+            inbound,
+            protocol_version: 0,
+            protocol: None,
+            hostname: None,
+            port: 0,
+            username: None,
+            server: None,
+            encryption: None,
+            verify_token: None,
+            profile: None,
+        }
+    }
+
+    /// Encrypts everything sent and received from now on, returns false if the shared secret is invalid.
+    pub fn enable_encryption(&mut self, shared_secret: &[u8]) -> bool {
+        self.encryption = Encryption::new(shared_secret);
+        self.encryption.is_some()
+    }
+
+    /// Registers the socket for read and write events under its own token, if there is one.
+    pub fn register(&mut self, poll: &Poll) -> io::Result<()> {
+        match &mut self.stream {
+            Some(stream) => poll.registry().register(stream, self.token_self, Interest::READABLE | Interest::WRITABLE),
+            None => Ok(())
+        }
     }
 
     pub fn get_other<'a>(&self, thread_ctx: &'a mut NetworkThreadContext) -> &'a mut ConnectionContext {
         thread_ctx.connections.get_mut(&self.token_other).unwrap()
     }
 
+    /// Sends a packet with its id in the protocol version of the connection.
+    /// Packets the version encodes differently are dropped, the connection is closed if the packet can't be written.
     pub fn send_packet<P: Packet>(&mut self, packet: &P) {
+        let id = match self.protocol {
+            Some(protocol) => match protocol.id_of::<P>() {
+                Some(id) => id,
+                None => {
+                    println!("can't send packet {:#04x} to a {} connection", P::get_id(), protocol.minecraft_version);
+                    return;
+                }
+            },
+            // only status packets and login disconnects are sent to those, their ids never changed
+            None => P::get_id()
+        };
+        let result = self.send_frame(|buf| {
+            buf.put_var_i32(id);
+            packet.write(buf)
+        });
+        if let Err(e) = result {
+            println!("could not write packet {:#04x} to {:?}: {}", P::get_id(), self.username, e);
+            self.should_close = true;
+        }
+    }
+
+    /// Sends a packet that is already encoded, its id followed by its data.
+    pub fn send_raw_packet(&mut self, packet: &[u8]) {
+        // nothing to encode, it can't fail
+        let _ = self.send_frame(|buf| {
+            buf.put_slice(packet);
+            Ok(())
+        });
+    }
+
+    /// Nothing is sent if `write_packet` fails.
+    fn send_frame<F: FnOnce(&mut IndexedVec<u8>) -> Result<(), EncodeError>>(&mut self, write_packet: F) -> Result<(), EncodeError> {
         let compression_threshold = self.compression_threshold;
         let mut buf = IndexedVec::new();
         // total len
@@ -89,8 +166,7 @@ impl ConnectionContext {
             buf.put_u8(0);
             buf.advance_reader_index(1);
         }
-        buf.put_var_i32(P::get_id());
-        packet.write(&mut buf);
+        write_packet(&mut buf)?;
 
         if compression_threshold > 0 {
             let mut buffer = buf.as_slice();
@@ -111,7 +187,7 @@ impl ConnectionContext {
         if len_size > 3 {
             println!("illegal packet len");
             self.should_close = true;
-            return;
+            return Ok(());
         }
 
         let start = 3 - len_size;
@@ -124,12 +200,15 @@ impl ConnectionContext {
         buf.set_writer_index(end);
 
         write_socket(self, &mut buf);
+        Ok(())
     }
 }
 
 /// Context linked to a single networking thread.
 /// Stores all the connections and their tokens
 pub struct NetworkThreadContext {
+    /// Index of this thread in `threads`.
+    pub id: usize,
     pub connections: HashMap<Token, ConnectionContext>,
     pub threads: Arc<Vec<Arc<PaxyThread>>>,
     pub thread: Arc<PaxyThread>,
@@ -139,6 +218,17 @@ pub struct NetworkThreadContext {
 pub enum Message {
     Threads(Arc<Vec<Arc<PaxyThread>>>),
 
-    /// New connection should be processed in the receiving thread.
-    NewConnection(TcpStream, TcpStream),
+    /// New client connection should be processed in the receiving thread,
+    /// the backend is picked once its handshake arrives.
+    NewConnection(TcpStream),
+
+    /// The client with the given token should be moved to the named backend.
+    SwitchServer(Token, String),
+
+    /// The session server answered for the client with the given token,
+    /// with its profile or the reason it should be disconnected with.
+    LoginVerified(Token, Result<GameProfile, Box<Component>>),
+
+    /// The proxy is stopping, clients should be disconnected with the reason.
+    Shutdown(Box<Component>),
 }
\ No newline at end of file
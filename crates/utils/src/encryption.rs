@@ -0,0 +1,31 @@
+use aes::Aes128;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::cipher::inout::InOutBuf;
+
+/// AES-128-CFB8 state of a connection, the protocol uses the shared secret as both key and iv.
+/// Both directions keep their own state, bytes have to go through it in the order they are sent.
+pub struct Encryption {
+    encryptor: cfb8::Encryptor<Aes128>,
+    decryptor: cfb8::Decryptor<Aes128>,
+}
+
+impl Encryption {
+    /// Returns `None` if the shared secret isn't 16 bytes long.
+    pub fn new(shared_secret: &[u8]) -> Option<Encryption> {
+        Some(Encryption {
+            encryptor: cfb8::Encryptor::new_from_slices(shared_secret, shared_secret).ok()?,
+            decryptor: cfb8::Decryptor::new_from_slices(shared_secret, shared_secret).ok()?,
+        })
+    }
+
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        // cfb8 works on blocks of a single byte, so there is never a remainder
+        let (blocks, _) = InOutBuf::from(data).into_chunks();
+        self.encryptor.encrypt_blocks_inout_mut(blocks);
+    }
+
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        let (blocks, _) = InOutBuf::from(data).into_chunks();
+        self.decryptor.decrypt_blocks_inout_mut(blocks);
+    }
+}
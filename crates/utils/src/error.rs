@@ -0,0 +1,82 @@
+//! Errors of data read from the network, which can't be trusted to be well formed,
+//! and of values that can't be written back after being edited.
+
+use std::fmt;
+
+use bytes::TryGetError;
+
+/// Why a packet or a value in it couldn't be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The packet ended before the value did.
+    UnexpectedEnd,
+    /// A VarInt longer than 5 bytes, or a VarLong longer than 10.
+    VarIntTooBig,
+    /// A negative length, or one that is longer than the rest of the packet.
+    InvalidLength(i64),
+    /// A value its type can't have, like an unknown enum tag.
+    InvalidValue(String),
+    /// A compressed packet that couldn't be inflated to the size it claims.
+    Decompression,
+}
+
+impl DecodeError {
+    pub fn invalid_value(message: impl Into<String>) -> DecodeError {
+        DecodeError::InvalidValue(message.into())
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => f.write_str("the packet ended early"),
+            DecodeError::VarIntTooBig => f.write_str("VarInt is too big"),
+            DecodeError::InvalidLength(len) => write!(f, "invalid length {}", len),
+            DecodeError::InvalidValue(message) => f.write_str(message),
+            DecodeError::Decompression => f.write_str("the packet couldn't be decompressed"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<TryGetError> for DecodeError {
+    fn from(_: TryGetError) -> Self {
+        DecodeError::UnexpectedEnd
+    }
+}
+
+/// Why a value couldn't be encoded, which only happens to values that were edited into something
+/// their type can't send.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// A string or array longer than its length prefix allows.
+    TooLong(usize),
+    /// A value its type can't have, like an nbt list with tags of different types.
+    InvalidValue(String),
+}
+
+impl EncodeError {
+    pub fn invalid_value(message: impl Into<String>) -> EncodeError {
+        EncodeError::InvalidValue(message.into())
+    }
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::TooLong(len) => write!(f, "length {} is too long", len),
+            EncodeError::InvalidValue(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// A value that can't be written back is as unusable as one that couldn't be read,
+/// like when a packet is translated.
+impl From<EncodeError> for DecodeError {
+    fn from(e: EncodeError) -> Self {
+        DecodeError::InvalidValue(e.to_string())
+    }
+}
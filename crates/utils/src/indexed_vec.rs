@@ -1,9 +1,9 @@
 use crate::{add_vec_len, set_vec_len};
 use bytes::{Buf, BufMut};
 use bytes::buf::UninitSlice;
+use std::fmt;
 use std::io::{Write, Error, Read};
 
-#[derive(Debug)]
 pub struct IndexedVec<T> {
     pub vec: Vec<T>,
     writer_index: usize,
@@ -97,6 +97,13 @@ impl<T> IndexedVec<T> {
     }
 }
 
+// only the readable part, the rest of the vec may not be initialized
+impl<T: fmt::Debug> fmt::Debug for IndexedVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
 impl Buf for IndexedVec<u8> {
     fn remaining(&self) -> usize {
         self.vec.len() - self.get_reader_index()
@@ -108,7 +115,7 @@ impl Buf for IndexedVec<u8> {
 
     fn advance(&mut self, cnt: usize) {
         self.advance_reader_index(cnt);
-        debug_assert!(self.get_reader_index() > self.vec.len(), "no more space, reader_index: {} cnt: {}, len: {}", self.get_reader_index()-cnt, cnt, self.vec.len());
+        debug_assert!(self.get_reader_index() <= self.vec.len(), "no more space, reader_index: {} cnt: {}, len: {}", self.get_reader_index()-cnt, cnt, self.vec.len());
     }
 }
 
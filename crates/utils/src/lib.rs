@@ -2,11 +2,21 @@ use std::any::Any;
 
 use bytes::{Buf, BufMut};
 
+use crate::error::{DecodeError, EncodeError};
+
 pub mod buffers;
+pub mod error;
 pub mod indexed_vec;
 pub mod sendable;
+pub mod nbt;
+pub mod chat;
+pub mod slot;
+pub mod metadata;
 pub mod contexts;
 pub mod buffer_helpers;
+pub mod encryption;
+pub mod profile;
+pub mod protocol;
 
 pub fn add_vec_len<T>(vec: &mut Vec<T>, extra_len: usize) {
     vec.reserve(extra_len);
@@ -54,10 +64,11 @@ pub fn get_var_i32_size(num : i32) -> i32 {
 }
 
 pub trait Packet : Any {
-    fn read(buffer: &mut dyn Buf) -> Self
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError>
         where Self: Sized;
 
-    fn write(&self, buffer: &mut dyn BufMut);
+    /// Fails if a value of the packet was edited into something that can't be sent.
+    fn write(&self, buffer: &mut dyn BufMut) -> Result<(), EncodeError>;
 
     fn get_id() -> i32
         where Self: Sized;
@@ -0,0 +1,568 @@
+//! Entity metadata, the values an entity is shown with, like its flags, custom name and pose.
+
+use bytes::{Buf, BufMut};
+
+use crate::buffers::{Bools, BoolsMut, Strings, StringsMut, VarInts, VarIntsMut};
+use crate::error::{DecodeError, EncodeError};
+use crate::nbt::Nbt;
+use crate::sendable::{Position, Sendable, Uuid};
+use crate::slot::Slot;
+
+// the index that ends the entries
+const END: u8 = 0xFF;
+
+// indices every entity has
+/// A byte of the flags below.
+pub const FLAGS: u8 = 0;
+pub const AIR_TICKS: u8 = 1;
+pub const CUSTOM_NAME: u8 = 2;
+pub const CUSTOM_NAME_VISIBLE: u8 = 3;
+pub const SILENT: u8 = 4;
+pub const NO_GRAVITY: u8 = 5;
+pub const POSE: u8 = 6;
+
+// bits of the flags
+pub const ON_FIRE: i8 = 0x01;
+pub const CROUCHING: i8 = 0x02;
+pub const SPRINTING: i8 = 0x08;
+pub const SWIMMING: i8 = 0x10;
+pub const INVISIBLE: i8 = 0x20;
+pub const GLOWING: i8 = 0x40;
+pub const FLYING_WITH_ELYTRA: i8 = 0x80u8 as i8;
+
+/// The particles that carry data in a protocol version, the others are just their id.
+/// Particle ids change between versions, so they have to be read with the ids of the version that sent them.
+pub struct ParticleIds {
+    pub protocol_version: i32,
+    pub block: i32,
+    /// Added in 1.18.
+    pub block_marker: Option<i32>,
+    pub dust: i32,
+    /// Added in 1.17.
+    pub dust_color_transition: Option<i32>,
+    pub falling_dust: i32,
+    pub item: i32,
+    /// Added in 1.17.
+    pub vibration: Option<i32>,
+}
+
+/// The particle ids of every supported version, the packet structs are read with the first.
+pub const PARTICLE_IDS: &[ParticleIds] = &[
+    // 1.16.5
+    ParticleIds {
+        protocol_version: 754,
+        block: 3,
+        block_marker: None,
+        dust: 14,
+        dust_color_transition: None,
+        falling_dust: 23,
+        item: 34,
+        vibration: None,
+    },
+    // 1.17.1
+    ParticleIds {
+        protocol_version: 756,
+        block: 4,
+        block_marker: None,
+        dust: 15,
+        dust_color_transition: Some(16),
+        falling_dust: 25,
+        item: 36,
+        vibration: Some(37),
+    },
+    // 1.18.2
+    ParticleIds {
+        protocol_version: 758,
+        block: 2,
+        block_marker: Some(3),
+        dust: 14,
+        dust_color_transition: Some(15),
+        falling_dust: 24,
+        item: 35,
+        vibration: Some(36),
+    },
+];
+
+/// The metadata entries of an entity, by index, in the order they were sent.
+/// Packets only send the entries that changed, which entries an entity has depends on its type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EntityMetadata {
+    entries: Vec<(u8, MetadataValue)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataValue {
+    Byte(i8),
+    VarInt(i32),
+    Float(f32),
+    String(String),
+    /// The json of a chat component, kept as it was sent so it is written back the same.
+    /// [`crate::chat::Component::from_json`] parses it.
+    Chat(String),
+    OptChat(Option<String>),
+    Slot(Slot),
+    Bool(bool),
+    Rotation { x: f32, y: f32, z: f32 },
+    Position(Position),
+    OptPosition(Option<Position>),
+    /// 0: down, 1: up, 2: north, 3: south, 4: west, 5: east
+    Direction(i32),
+    OptUuid(Option<Uuid>),
+    /// A block state, `None` is sent like air.
+    OptBlockState(Option<i32>),
+    Nbt(Nbt),
+    Particle(Particle),
+    VillagerData { villager_type: i32, profession: i32, level: i32 },
+    OptVarInt(Option<i32>),
+    /// 0: standing, 1: flying with elytra, 2: sleeping, 3: swimming, 4: riptide, 5: crouching, 6: dying
+    Pose(i32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Particle {
+    /// Id of the particle in the particle registry.
+    pub id: i32,
+    pub data: ParticleData,
+}
+
+/// What a particle is drawn with, most only have their id.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParticleData {
+    None,
+    /// The block state of block, block marker and falling dust particles.
+    Block(i32),
+    Dust { red: f32, green: f32, blue: f32, scale: f32 },
+    DustColorTransition { from: (f32, f32, f32), scale: f32, to: (f32, f32, f32) },
+    Item(Slot),
+    /// A vibration moving from `origin` to `destination` in `ticks`.
+    Vibration { origin: (f64, f64, f64), destination: VibrationDestination, ticks: i32 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VibrationDestination {
+    Block(Position),
+    /// Id of the entity.
+    Entity(i32),
+}
+
+impl ParticleIds {
+    /// The ids of `protocol_version`, `None` if it isn't supported.
+    pub fn of(protocol_version: i32) -> Option<&'static ParticleIds> {
+        PARTICLE_IDS.iter().find(|ids| ids.protocol_version == protocol_version)
+    }
+}
+
+impl EntityMetadata {
+    pub fn new() -> EntityMetadata {
+        EntityMetadata::default()
+    }
+
+    pub fn get(&self, index: u8) -> Option<&MetadataValue> {
+        self.entries.iter().find(|(key, _)| *key == index).map(|(_, value)| value)
+    }
+
+    pub fn get_mut(&mut self, index: u8) -> Option<&mut MetadataValue> {
+        self.entries.iter_mut().find(|(key, _)| *key == index).map(|(_, value)| value)
+    }
+
+    pub fn contains(&self, index: u8) -> bool {
+        self.get(index).is_some()
+    }
+
+    /// Sets the entry at `index`, returns the value it replaced.
+    /// A replaced entry keeps its position, a new one is added at the end.
+    /// Fails if `index` is 0xFF, which ends the entries.
+    pub fn insert(&mut self, index: u8, value: impl Into<MetadataValue>) -> Result<Option<MetadataValue>, EncodeError> {
+        if index == END {
+            return Err(EncodeError::invalid_value(format!("metadata index {} ends the entries", END)));
+        }
+        let value = value.into();
+        Ok(match self.get_mut(index) {
+            Some(old) => Some(std::mem::replace(old, value)),
+            None => {
+                self.entries.push((index, value));
+                None
+            }
+        })
+    }
+
+    /// Reads metadata sent with the particle ids of `particle_ids`, the [`Sendable`] impl reads those of 1.16.5.
+    pub fn read_with(mut buffer: &mut dyn Buf, particle_ids: &ParticleIds) -> Result<EntityMetadata, DecodeError> {
+        let mut metadata = EntityMetadata::new();
+        loop {
+            let index = buffer.try_get_u8()?;
+            if index == END {
+                return Ok(metadata);
+            }
+            let (type_id, _type_id_bytes) = buffer.get_var_i32()?;
+            metadata.entries.push((index, MetadataValue::read(type_id, buffer, particle_ids)?));
+        }
+    }
+
+    pub fn remove(&mut self, index: u8) -> Option<MetadataValue> {
+        let position = self.entries.iter().position(|(key, _)| *key == index)?;
+        Some(self.entries.remove(position).1)
+    }
+
+    /// The flags of the entity, if they were sent.
+    pub fn flags(&self) -> Option<i8> {
+        match self.get(FLAGS)? {
+            MetadataValue::Byte(flags) => Some(*flags),
+            _ => None
+        }
+    }
+
+    /// Sets or clears `flag` in the flags of the entity, if they were sent.
+    /// Returns whether they were.
+    pub fn set_flag(&mut self, flag: i8, set: bool) -> bool {
+        match self.get_mut(FLAGS) {
+            Some(MetadataValue::Byte(flags)) => {
+                if set {
+                    *flags |= flag;
+                } else {
+                    *flags &= !flag;
+                }
+                true
+            }
+            _ => false
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &MetadataValue)> {
+        self.entries.iter().map(|(index, value)| (*index, value))
+    }
+
+    /// Values can be edited in place, the client expects the type an index has for the entity.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (u8, &mut MetadataValue)> {
+        self.entries.iter_mut().map(|(index, value)| (*index, value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl MetadataValue {
+    /// The type id the value is sent with.
+    pub fn type_id(&self) -> i32 {
+        match self {
+            MetadataValue::Byte(_) => 0,
+            MetadataValue::VarInt(_) => 1,
+            MetadataValue::Float(_) => 2,
+            MetadataValue::String(_) => 3,
+            MetadataValue::Chat(_) => 4,
+            MetadataValue::OptChat(_) => 5,
+            MetadataValue::Slot(_) => 6,
+            MetadataValue::Bool(_) => 7,
+            MetadataValue::Rotation { .. } => 8,
+            MetadataValue::Position(_) => 9,
+            MetadataValue::OptPosition(_) => 10,
+            MetadataValue::Direction(_) => 11,
+            MetadataValue::OptUuid(_) => 12,
+            MetadataValue::OptBlockState(_) => 13,
+            MetadataValue::Nbt(_) => 14,
+            MetadataValue::Particle(_) => 15,
+            MetadataValue::VillagerData { .. } => 16,
+            MetadataValue::OptVarInt(_) => 17,
+            MetadataValue::Pose(_) => 18,
+        }
+    }
+
+    fn read(type_id: i32, mut buffer: &mut dyn Buf, particle_ids: &ParticleIds) -> Result<MetadataValue, DecodeError> {
+        Ok(match type_id {
+            0 => MetadataValue::Byte(buffer.try_get_i8()?),
+            1 => MetadataValue::VarInt(buffer.get_var_i32()?.0),
+            2 => MetadataValue::Float(buffer.try_get_f32()?),
+            3 => MetadataValue::String(buffer.get_string()?),
+            4 => MetadataValue::Chat(buffer.get_string()?),
+            5 => MetadataValue::OptChat(if buffer.get_bool()? { Some(buffer.get_string()?) } else { None }),
+            6 => MetadataValue::Slot(Slot::read(buffer)?),
+            7 => MetadataValue::Bool(buffer.get_bool()?),
+            8 => MetadataValue::Rotation { x: buffer.try_get_f32()?, y: buffer.try_get_f32()?, z: buffer.try_get_f32()? },
+            9 => MetadataValue::Position(Position::read(buffer)?),
+            10 => MetadataValue::OptPosition(Option::read(buffer)?),
+            11 => MetadataValue::Direction(buffer.get_var_i32()?.0),
+            12 => MetadataValue::OptUuid(Option::read(buffer)?),
+            13 => MetadataValue::OptBlockState(match buffer.get_var_i32()?.0 {
+                0 => None,
+                state => Some(state)
+            }),
+            14 => MetadataValue::Nbt(Nbt::read(buffer)?),
+            15 => MetadataValue::Particle(Particle::read_with(buffer, particle_ids)?),
+            16 => MetadataValue::VillagerData {
+                villager_type: buffer.get_var_i32()?.0,
+                profession: buffer.get_var_i32()?.0,
+                level: buffer.get_var_i32()?.0,
+            },
+            17 => MetadataValue::OptVarInt(match buffer.get_var_i32()?.0 {
+                0 => None,
+                value => Some(value - 1)
+            }),
+            18 => MetadataValue::Pose(buffer.get_var_i32()?.0),
+            _ => return Err(DecodeError::InvalidValue(format!("unknown metadata type {}", type_id)))
+        })
+    }
+
+    fn write(&self, mut buffer: &mut dyn BufMut) -> Result<(), EncodeError> {
+        match self {
+            MetadataValue::Byte(value) => buffer.put_i8(*value),
+            MetadataValue::VarInt(value) | MetadataValue::Direction(value) | MetadataValue::Pose(value) => buffer.put_var_i32(*value),
+            MetadataValue::Float(value) => buffer.put_f32(*value),
+            MetadataValue::String(value) | MetadataValue::Chat(value) => buffer.put_string(value),
+            MetadataValue::OptChat(value) => {
+                buffer.put_bool(value.is_some());
+                if let Some(value) = value {
+                    buffer.put_string(value);
+                }
+            }
+            MetadataValue::Slot(slot) => Slot::write(buffer, slot)?,
+            MetadataValue::Bool(value) => buffer.put_bool(*value),
+            MetadataValue::Rotation { x, y, z } => {
+                buffer.put_f32(*x);
+                buffer.put_f32(*y);
+                buffer.put_f32(*z);
+            }
+            MetadataValue::Position(position) => Position::write(buffer, position)?,
+            MetadataValue::OptPosition(position) => Option::write(buffer, position)?,
+            MetadataValue::OptUuid(uuid) => Option::write(buffer, uuid)?,
+            MetadataValue::OptBlockState(state) => buffer.put_var_i32(state.unwrap_or(0)),
+            MetadataValue::Nbt(nbt) => Nbt::write(buffer, nbt)?,
+            MetadataValue::Particle(particle) => Particle::write(buffer, particle)?,
+            MetadataValue::VillagerData { villager_type, profession, level } => {
+                buffer.put_var_i32(*villager_type);
+                buffer.put_var_i32(*profession);
+                buffer.put_var_i32(*level);
+            }
+            MetadataValue::OptVarInt(value) => buffer.put_var_i32(value.map_or(0, |value| value + 1)),
+        }
+        Ok(())
+    }
+}
+
+macro_rules! value_from {
+    ($($ty:ty => $variant:ident),*) => {
+        $(impl From<$ty> for MetadataValue {
+            fn from(value: $ty) -> Self {
+                MetadataValue::$variant(value)
+            }
+        })*
+    };
+}
+
+value_from!(i8 => Byte, i32 => VarInt, f32 => Float, String => String, Slot => Slot, bool => Bool, Position => Position,
+    Nbt => Nbt, Particle => Particle);
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        MetadataValue::String(value.to_string())
+    }
+}
+
+impl Sendable for EntityMetadata {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        EntityMetadata::read_with(buffer, &PARTICLE_IDS[0])
+    }
+
+    fn write(mut buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        for (index, value) in data.entries.iter() {
+            buffer.put_u8(*index);
+            buffer.put_var_i32(value.type_id());
+            value.write(buffer)?;
+        }
+        buffer.put_u8(END);
+        Ok(())
+    }
+}
+
+impl Particle {
+    /// Reads a particle sent with the ids of `ids`, the [`Sendable`] impl reads those of 1.16.5.
+    pub fn read_with(mut buffer: &mut dyn Buf, ids: &ParticleIds) -> Result<Particle, DecodeError> {
+        let (id, _id_bytes) = buffer.get_var_i32()?;
+        let data = if id == ids.block || id == ids.falling_dust || Some(id) == ids.block_marker {
+            ParticleData::Block(buffer.get_var_i32()?.0)
+        } else if id == ids.dust {
+            ParticleData::Dust {
+                red: buffer.try_get_f32()?,
+                green: buffer.try_get_f32()?,
+                blue: buffer.try_get_f32()?,
+                scale: buffer.try_get_f32()?,
+            }
+        } else if Some(id) == ids.dust_color_transition {
+            ParticleData::DustColorTransition {
+                from: (buffer.try_get_f32()?, buffer.try_get_f32()?, buffer.try_get_f32()?),
+                scale: buffer.try_get_f32()?,
+                to: (buffer.try_get_f32()?, buffer.try_get_f32()?, buffer.try_get_f32()?),
+            }
+        } else if id == ids.item {
+            ParticleData::Item(Slot::read(buffer)?)
+        } else if Some(id) == ids.vibration {
+            let origin = (buffer.try_get_f64()?, buffer.try_get_f64()?, buffer.try_get_f64()?);
+            let destination = match buffer.get_string()?.as_str() {
+                "minecraft:block" => VibrationDestination::Block(Position::read(buffer)?),
+                "minecraft:entity" => VibrationDestination::Entity(buffer.get_var_i32()?.0),
+                other => return Err(DecodeError::invalid_value(format!("unknown vibration destination {}", other)))
+            };
+            ParticleData::Vibration { origin, destination, ticks: buffer.get_var_i32()?.0 }
+        } else {
+            ParticleData::None
+        };
+        Ok(Particle { id, data })
+    }
+}
+
+impl Sendable for Particle {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Particle::read_with(buffer, &PARTICLE_IDS[0])
+    }
+
+    fn write(mut buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_var_i32(data.id);
+        match &data.data {
+            ParticleData::None => {}
+            ParticleData::Block(state) => buffer.put_var_i32(*state),
+            ParticleData::Dust { red, green, blue, scale } => {
+                buffer.put_f32(*red);
+                buffer.put_f32(*green);
+                buffer.put_f32(*blue);
+                buffer.put_f32(*scale);
+            }
+            ParticleData::DustColorTransition { from, scale, to } => {
+                buffer.put_f32(from.0);
+                buffer.put_f32(from.1);
+                buffer.put_f32(from.2);
+                buffer.put_f32(*scale);
+                buffer.put_f32(to.0);
+                buffer.put_f32(to.1);
+                buffer.put_f32(to.2);
+            }
+            ParticleData::Item(slot) => Slot::write(buffer, slot)?,
+            ParticleData::Vibration { origin, destination, ticks } => {
+                buffer.put_f64(origin.0);
+                buffer.put_f64(origin.1);
+                buffer.put_f64(origin.2);
+                match destination {
+                    VibrationDestination::Block(position) => {
+                        buffer.put_string("minecraft:block");
+                        Position::write(buffer, position)?;
+                    }
+                    VibrationDestination::Entity(entity_id) => {
+                        buffer.put_string("minecraft:entity");
+                        buffer.put_var_i32(*entity_id);
+                    }
+                }
+                buffer.put_var_i32(*ticks);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nbt::Compound;
+    use crate::slot::ItemStack;
+
+    use super::*;
+
+    fn round_trip(metadata: &EntityMetadata, particle_ids: &ParticleIds) -> EntityMetadata {
+        let mut bytes = Vec::new();
+        EntityMetadata::write(&mut bytes, metadata).unwrap();
+        let mut slice = bytes.as_slice();
+        let read = EntityMetadata::read_with(&mut slice, particle_ids).unwrap();
+        assert!(slice.is_empty());
+        read
+    }
+
+    fn sword() -> Slot {
+        let mut item = ItemStack::new(603, 1);
+        item.tag_mut().insert("Damage", 10);
+        Slot::new(item)
+    }
+
+    #[test]
+    fn every_type() {
+        let mut root = Compound::new();
+        root.insert("Owner", "Steve");
+        let values = vec![
+            MetadataValue::Byte(CROUCHING | INVISIBLE),
+            MetadataValue::VarInt(300),
+            MetadataValue::Float(0.5),
+            MetadataValue::String("Steve".to_string()),
+            MetadataValue::Chat("{\"text\":\"Steve\"}".to_string()),
+            MetadataValue::OptChat(Some("{\"text\":\"Alex\"}".to_string())),
+            MetadataValue::OptChat(None),
+            MetadataValue::Slot(sword()),
+            MetadataValue::Slot(Slot::empty()),
+            MetadataValue::Bool(true),
+            MetadataValue::Rotation { x: 1.0, y: -2.0, z: 3.5 },
+            MetadataValue::Position(Position::new(-30_000_000, -64, 12)),
+            MetadataValue::OptPosition(Some(Position::new(1, 2, 3))),
+            MetadataValue::OptPosition(None),
+            MetadataValue::Direction(5),
+            MetadataValue::OptUuid(Some(Uuid(0x5627dd98_e6be_3c21_b8a8_e92344183641))),
+            MetadataValue::OptUuid(None),
+            MetadataValue::OptBlockState(Some(1)),
+            MetadataValue::OptBlockState(None),
+            MetadataValue::Nbt(Nbt::new("", root)),
+            MetadataValue::Nbt(Nbt::default()),
+            MetadataValue::Particle(Particle { id: 0, data: ParticleData::None }),
+            MetadataValue::VillagerData { villager_type: 2, profession: 5, level: 1 },
+            MetadataValue::OptVarInt(Some(0)),
+            MetadataValue::OptVarInt(None),
+            MetadataValue::Pose(3),
+        ];
+        let mut metadata = EntityMetadata::new();
+        for (index, value) in values.into_iter().enumerate() {
+            metadata.insert(index as u8, value).unwrap();
+        }
+        assert_eq!(round_trip(&metadata, &PARTICLE_IDS[0]), metadata);
+    }
+
+    #[test]
+    fn every_particle_with_data() {
+        for ids in PARTICLE_IDS {
+            let mut particles = vec![
+                Particle { id: ids.block, data: ParticleData::Block(9) },
+                Particle { id: ids.falling_dust, data: ParticleData::Block(10) },
+                Particle { id: ids.dust, data: ParticleData::Dust { red: 1.0, green: 0.5, blue: 0.0, scale: 2.0 } },
+                Particle { id: ids.item, data: ParticleData::Item(sword()) },
+            ];
+            if let Some(id) = ids.block_marker {
+                particles.push(Particle { id, data: ParticleData::Block(11) });
+            }
+            if let Some(id) = ids.dust_color_transition {
+                particles.push(Particle {
+                    id,
+                    data: ParticleData::DustColorTransition { from: (1.0, 0.0, 0.0), scale: 1.5, to: (0.0, 0.0, 1.0) },
+                });
+            }
+            if let Some(id) = ids.vibration {
+                particles.push(Particle {
+                    id,
+                    data: ParticleData::Vibration { origin: (0.5, 64.0, -0.5), destination: VibrationDestination::Block(Position::new(4, 60, 4)), ticks: 20 },
+                });
+                particles.push(Particle {
+                    id,
+                    data: ParticleData::Vibration { origin: (1.0, 2.0, 3.0), destination: VibrationDestination::Entity(42), ticks: 5 },
+                });
+            }
+
+            let mut metadata = EntityMetadata::new();
+            for (index, particle) in particles.into_iter().enumerate() {
+                metadata.insert(index as u8, particle).unwrap();
+            }
+            assert_eq!(round_trip(&metadata, ids), metadata, "protocol {}", ids.protocol_version);
+        }
+    }
+
+    #[test]
+    fn end_index_is_rejected() {
+        let mut metadata = EntityMetadata::new();
+        assert!(metadata.insert(END, 1).is_err());
+        assert!(metadata.is_empty());
+    }
+}
@@ -0,0 +1,741 @@
+//! Named binary tags, the format structured data like dimensions, items and block entities is sent in.
+
+use std::convert::TryInto;
+use std::fmt;
+
+use bytes::{Buf, BufMut};
+
+use crate::error::{DecodeError, EncodeError};
+use crate::sendable::Sendable;
+
+// nbt nesting deeper than this is rejected
+pub(crate) const MAX_NBT_DEPTH: usize = 512;
+// nbt larger than this is rejected, the same limit the client uses
+const MAX_NBT_SIZE: usize = 2 * 1024 * 1024;
+
+const END: u8 = 0;
+const BYTE: u8 = 1;
+const SHORT: u8 = 2;
+const INT: u8 = 3;
+const LONG: u8 = 4;
+const FLOAT: u8 = 5;
+const DOUBLE: u8 = 6;
+const BYTE_ARRAY: u8 = 7;
+const STRING: u8 = 8;
+const LIST: u8 = 9;
+const COMPOUND: u8 = 10;
+const INT_ARRAY: u8 = 11;
+const LONG_ARRAY: u8 = 12;
+
+/// A named root tag, as packets send it.
+/// `tag` is `None` when a TAG_End was sent in its place, which packets use for no nbt.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Nbt {
+    pub name: String,
+    pub tag: Option<Tag>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(List),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+/// Tags of the same type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct List {
+    // type of the elements, kept for empty lists so they are written back the same
+    element: u8,
+    tags: Vec<Tag>,
+}
+
+/// Named tags, in the order they were read or inserted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound {
+    entries: Vec<(String, Tag)>,
+}
+
+impl Nbt {
+    pub fn new(name: impl Into<String>, root: Compound) -> Nbt {
+        Nbt { name: name.into(), tag: Some(Tag::Compound(root)) }
+    }
+
+    /// The compound at the root, if there is one.
+    pub fn root(&self) -> Option<&Compound> {
+        self.tag.as_ref().and_then(Tag::as_compound)
+    }
+
+    pub fn root_mut(&mut self) -> Option<&mut Compound> {
+        self.tag.as_mut().and_then(Tag::as_compound_mut)
+    }
+}
+
+impl Tag {
+    /// The type id the tag is sent with.
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => BYTE,
+            Tag::Short(_) => SHORT,
+            Tag::Int(_) => INT,
+            Tag::Long(_) => LONG,
+            Tag::Float(_) => FLOAT,
+            Tag::Double(_) => DOUBLE,
+            Tag::ByteArray(_) => BYTE_ARRAY,
+            Tag::String(_) => STRING,
+            Tag::List(_) => LIST,
+            Tag::Compound(_) => COMPOUND,
+            Tag::IntArray(_) => INT_ARRAY,
+            Tag::LongArray(_) => LONG_ARRAY,
+        }
+    }
+
+    /// The value of any number tag, floating point ones are truncated.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value as i64),
+            Tag::Short(value) => Some(value as i64),
+            Tag::Int(value) => Some(value as i64),
+            Tag::Long(value) => Some(value),
+            Tag::Float(value) => Some(value as i64),
+            Tag::Double(value) => Some(value as i64),
+            _ => None
+        }
+    }
+
+    /// The value of any number tag.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Tag::Float(value) => Some(value as f64),
+            Tag::Double(value) => Some(value),
+            _ => self.as_i64().map(|value| value as f64)
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&List> {
+        match self {
+            Tag::List(list) => Some(list),
+            _ => None
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Option<&mut List> {
+        match self {
+            Tag::List(list) => Some(list),
+            _ => None
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Tag::Compound(compound) => Some(compound),
+            _ => None
+        }
+    }
+
+    pub fn as_compound_mut(&mut self) -> Option<&mut Compound> {
+        match self {
+            Tag::Compound(compound) => Some(compound),
+            _ => None
+        }
+    }
+}
+
+macro_rules! tag_from {
+    ($($ty:ty => $variant:ident),*) => {
+        $(impl From<$ty> for Tag {
+            fn from(value: $ty) -> Self {
+                Tag::$variant(value)
+            }
+        })*
+    };
+}
+
+tag_from!(i8 => Byte, i16 => Short, i32 => Int, i64 => Long, f32 => Float, f64 => Double, Vec<i8> => ByteArray,
+    String => String, List => List, Compound => Compound, Vec<i32> => IntArray, Vec<i64> => LongArray);
+
+impl From<bool> for Tag {
+    fn from(value: bool) -> Self {
+        Tag::Byte(value as i8)
+    }
+}
+
+impl From<&str> for Tag {
+    fn from(value: &str) -> Self {
+        Tag::String(value.to_string())
+    }
+}
+
+impl List {
+    pub fn new() -> List {
+        List::default()
+    }
+
+    /// Type id of the elements, TAG_End for lists that never had any.
+    pub fn element_id(&self) -> u8 {
+        self.tags.first().map_or(self.element, Tag::id)
+    }
+
+    /// Adds a tag at the end, fails if it has another type than the elements.
+    pub fn push(&mut self, tag: impl Into<Tag>) -> Result<(), EncodeError> {
+        let tag = tag.into();
+        if !self.tags.is_empty() && tag.id() != self.element_id() {
+            return Err(EncodeError::invalid_value(format!("a list of tag {} can't hold tag {}", self.element_id(), tag.id())));
+        }
+        self.element = tag.id();
+        self.tags.push(tag);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Tag {
+        self.tags.remove(index)
+    }
+
+    pub fn retain<F: FnMut(&Tag) -> bool>(&mut self, keep: F) {
+        self.tags.retain(keep)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Tag> {
+        self.tags.get(index)
+    }
+
+    /// Tags can be edited in place, a list holding tags of different types fails to be written.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Tag> {
+        self.tags.get_mut(index)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Tag> {
+        self.tags.iter()
+    }
+
+    /// Tags can be edited in place, a list holding tags of different types fails to be written.
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Tag> {
+        self.tags.iter_mut()
+    }
+
+    /// The compounds of a list of compounds.
+    pub fn compounds_mut(&mut self) -> impl Iterator<Item = &mut Compound> {
+        self.tags.iter_mut().filter_map(Tag::as_compound_mut)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+}
+
+impl Compound {
+    pub fn new() -> Compound {
+        Compound::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        self.entries.iter().find(|(key, _)| key == name).map(|(_, tag)| tag)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Tag> {
+        self.entries.iter_mut().find(|(key, _)| key == name).map(|(_, tag)| tag)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets the tag called `name`, returns the tag it replaced.
+    /// A replaced tag keeps its position, a new one is added at the end.
+    pub fn insert(&mut self, name: impl Into<String>, tag: impl Into<Tag>) -> Option<Tag> {
+        let name = name.into();
+        let tag = tag.into();
+        match self.get_mut(&name) {
+            Some(old) => Some(std::mem::replace(old, tag)),
+            None => {
+                self.entries.push((name, tag));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Tag> {
+        let index = self.entries.iter().position(|(key, _)| key == name)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    /// The value of the number tag called `name`, see [`Tag::as_i64`].
+    pub fn get_i64(&self, name: &str) -> Option<i64> {
+        self.get(name)?.as_i64()
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.get(name)?.as_str()
+    }
+
+    pub fn get_list(&self, name: &str) -> Option<&List> {
+        self.get(name)?.as_list()
+    }
+
+    pub fn get_list_mut(&mut self, name: &str) -> Option<&mut List> {
+        self.get_mut(name)?.as_list_mut()
+    }
+
+    pub fn get_compound(&self, name: &str) -> Option<&Compound> {
+        self.get(name)?.as_compound()
+    }
+
+    pub fn get_compound_mut(&mut self, name: &str) -> Option<&mut Compound> {
+        self.get_mut(name)?.as_compound_mut()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tag)> {
+        self.entries.iter().map(|(name, tag)| (name.as_str(), tag))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut Tag)> {
+        self.entries.iter_mut().map(|(name, tag)| (name.as_str(), tag))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Sendable for Nbt {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        let mut reader = NbtReader { buffer, budget: MAX_NBT_SIZE };
+        let tag = reader.get_u8()?;
+        if tag == END {
+            return Ok(Nbt::default());
+        }
+        let name = reader.get_string()?;
+        let tag = reader.get_payload(tag, 0)?;
+        Ok(Nbt { name, tag: Some(tag) })
+    }
+
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        match &data.tag {
+            Some(tag) => {
+                buffer.put_u8(tag.id());
+                put_nbt_string(buffer, &data.name)?;
+                put_payload(buffer, tag)?;
+            }
+            None => buffer.put_u8(END)
+        }
+        Ok(())
+    }
+}
+
+/// Reads nbt while keeping track of how much of it there is.
+struct NbtReader<'a> {
+    buffer: &'a mut dyn Buf,
+    // bytes that may still be read
+    budget: usize,
+}
+
+impl NbtReader<'_> {
+    /// Makes sure `len` more bytes can be read.
+    fn take(&mut self, len: usize) -> Result<(), DecodeError> {
+        if len > self.budget {
+            return Err(DecodeError::invalid_value("nbt is too large"));
+        }
+        if len > self.buffer.remaining() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        self.budget -= len;
+        Ok(())
+    }
+
+    /// Like `take` for `len` elements of `size` bytes.
+    fn take_elements(&mut self, len: i32, size: usize) -> Result<usize, DecodeError> {
+        let len = len.max(0) as usize;
+        self.take(len.saturating_mul(size))?;
+        Ok(len)
+    }
+
+    fn get_u8(&mut self) -> Result<u8, DecodeError> {
+        self.take(1)?;
+        Ok(self.buffer.get_u8())
+    }
+
+    fn get_i32(&mut self) -> Result<i32, DecodeError> {
+        self.take(4)?;
+        Ok(self.buffer.get_i32())
+    }
+
+    fn get_string(&mut self) -> Result<String, DecodeError> {
+        self.take(2)?;
+        let len = self.buffer.get_u16() as usize;
+        self.take(len)?;
+        let bytes = self.buffer.copy_to_bytes(len);
+        Ok(decode_mutf8(&bytes))
+    }
+
+    fn get_payload(&mut self, tag: u8, depth: usize) -> Result<Tag, DecodeError> {
+        if depth > MAX_NBT_DEPTH {
+            return Err(DecodeError::invalid_value("nbt is nested too deep"));
+        }
+        let tag = match tag {
+            BYTE => Tag::Byte(self.get_u8()? as i8),
+            SHORT => {
+                self.take(2)?;
+                Tag::Short(self.buffer.get_i16())
+            }
+            INT => Tag::Int(self.get_i32()?),
+            LONG => {
+                self.take(8)?;
+                Tag::Long(self.buffer.get_i64())
+            }
+            FLOAT => {
+                self.take(4)?;
+                Tag::Float(self.buffer.get_f32())
+            }
+            DOUBLE => {
+                self.take(8)?;
+                Tag::Double(self.buffer.get_f64())
+            }
+            BYTE_ARRAY => {
+                let len = self.get_i32()?;
+                let len = self.take_elements(len, 1)?;
+                let bytes = self.buffer.copy_to_bytes(len);
+                Tag::ByteArray(bytes.iter().map(|byte| *byte as i8).collect())
+            }
+            STRING => Tag::String(self.get_string()?),
+            LIST => {
+                let element = self.get_u8()?;
+                let len = self.get_i32()?;
+                if element == END && len > 0 {
+                    return Err(DecodeError::invalid_value("nbt list of end tags"));
+                }
+                // every element is at least a byte, which was checked against the budget
+                let len = len.max(0) as usize;
+                let mut tags = Vec::with_capacity(len.min(self.budget));
+                for _ in 0..len {
+                    tags.push(self.get_payload(element, depth + 1)?);
+                }
+                Tag::List(List { element, tags })
+            }
+            COMPOUND => {
+                let mut entries = Vec::new();
+                loop {
+                    let tag = self.get_u8()?;
+                    if tag == END {
+                        break;
+                    }
+                    let name = self.get_string()?;
+                    entries.push((name, self.get_payload(tag, depth + 1)?));
+                }
+                Tag::Compound(Compound { entries })
+            }
+            INT_ARRAY => {
+                let len = self.get_i32()?;
+                let len = self.take_elements(len, 4)?;
+                Tag::IntArray((0..len).map(|_| self.buffer.get_i32()).collect())
+            }
+            LONG_ARRAY => {
+                let len = self.get_i32()?;
+                let len = self.take_elements(len, 8)?;
+                Tag::LongArray((0..len).map(|_| self.buffer.get_i64()).collect())
+            }
+            _ => return Err(DecodeError::invalid_value(format!("unknown nbt tag {}", tag)))
+        };
+        Ok(tag)
+    }
+}
+
+fn put_payload(buffer: &mut dyn BufMut, tag: &Tag) -> Result<(), EncodeError> {
+    match tag {
+        Tag::Byte(value) => buffer.put_i8(*value),
+        Tag::Short(value) => buffer.put_i16(*value),
+        Tag::Int(value) => buffer.put_i32(*value),
+        Tag::Long(value) => buffer.put_i64(*value),
+        Tag::Float(value) => buffer.put_f32(*value),
+        Tag::Double(value) => buffer.put_f64(*value),
+        Tag::ByteArray(values) => {
+            buffer.put_i32(array_len(values.len())?);
+            for value in values {
+                buffer.put_i8(*value);
+            }
+        }
+        Tag::String(value) => put_nbt_string(buffer, value)?,
+        Tag::List(list) => {
+            let element = list.element_id();
+            buffer.put_u8(element);
+            buffer.put_i32(array_len(list.tags.len())?);
+            for tag in list.tags.iter() {
+                if tag.id() != element {
+                    return Err(EncodeError::invalid_value(format!("a list of tag {} holds tag {}", element, tag.id())));
+                }
+                put_payload(buffer, tag)?;
+            }
+        }
+        Tag::Compound(compound) => {
+            for (name, tag) in compound.entries.iter() {
+                buffer.put_u8(tag.id());
+                put_nbt_string(buffer, name)?;
+                put_payload(buffer, tag)?;
+            }
+            buffer.put_u8(END);
+        }
+        Tag::IntArray(values) => {
+            buffer.put_i32(array_len(values.len())?);
+            for value in values {
+                buffer.put_i32(*value);
+            }
+        }
+        Tag::LongArray(values) => {
+            buffer.put_i32(array_len(values.len())?);
+            for value in values {
+                buffer.put_i64(*value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn put_nbt_string(buffer: &mut dyn BufMut, string: &str) -> Result<(), EncodeError> {
+    let bytes = encode_mutf8(string);
+    let len: u16 = bytes.len().try_into().map_err(|_| EncodeError::TooLong(bytes.len()))?;
+    buffer.put_u16(len);
+    buffer.put_slice(&bytes);
+    Ok(())
+}
+
+fn array_len(len: usize) -> Result<i32, EncodeError> {
+    len.try_into().map_err(|_| EncodeError::TooLong(len))
+}
+
+/// Strings are sent in java's modified utf-8: nul is two bytes and characters outside the
+/// basic multilingual plane are two three byte surrogates.
+fn encode_mutf8(string: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(string.len());
+    for char in string.chars() {
+        match char as u32 {
+            0 => bytes.extend_from_slice(&[0xC0, 0x80]),
+            0x10000.. => {
+                let mut surrogates = [0; 2];
+                for surrogate in char.encode_utf16(&mut surrogates).iter() {
+                    put_mutf8_three_bytes(&mut bytes, *surrogate as u32);
+                }
+            }
+            _ => bytes.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    bytes
+}
+
+fn put_mutf8_three_bytes(bytes: &mut Vec<u8>, value: u32) {
+    bytes.push(0xE0 | (value >> 12) as u8);
+    bytes.push(0x80 | (value >> 6 & 0x3F) as u8);
+    bytes.push(0x80 | (value & 0x3F) as u8);
+}
+
+/// Invalid sequences become replacement characters, like other strings of the protocol.
+fn decode_mutf8(bytes: &[u8]) -> String {
+    // nearly always plain utf-8
+    if let Ok(string) = std::str::from_utf8(bytes) {
+        return string.to_string();
+    }
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let continuation = |offset: usize| bytes.get(index + offset).filter(|byte| *byte & 0xC0 == 0x80).map(|byte| (*byte & 0x3F) as u16);
+        let byte = bytes[index];
+        let (unit, len) = match byte {
+            0x00..=0x7F => (Some(byte as u16), 1),
+            0xC0..=0xDF => (continuation(1).map(|second| (byte as u16 & 0x1F) << 6 | second), 2),
+            0xE0..=0xEF => (continuation(1).zip(continuation(2)).map(|(second, third)| (byte as u16 & 0x0F) << 12 | second << 6 | third), 3),
+            _ => (None, 1)
+        };
+        match unit {
+            Some(unit) => {
+                units.push(unit);
+                index += len;
+            }
+            None => {
+                units.push(0xFFFD);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+/// Stringified nbt, like commands take it.
+impl fmt::Display for Nbt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.tag {
+            Some(tag) => tag.fmt(f),
+            None => f.write_str("{}")
+        }
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tag::Byte(value) => write!(f, "{}b", value),
+            Tag::Short(value) => write!(f, "{}s", value),
+            Tag::Int(value) => write!(f, "{}", value),
+            Tag::Long(value) => write!(f, "{}L", value),
+            Tag::Float(value) => write!(f, "{}f", value),
+            Tag::Double(value) => write!(f, "{}d", value),
+            Tag::ByteArray(values) => write_array(f, "B;", values.iter().map(|value| format!("{}b", value))),
+            Tag::String(value) => write_quoted(f, value),
+            Tag::List(list) => write_array(f, "", list.iter().map(Tag::to_string)),
+            Tag::Compound(compound) => {
+                f.write_str("{")?;
+                for (index, (name, tag)) in compound.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c)) {
+                        f.write_str(name)?;
+                    } else {
+                        write_quoted(f, name)?;
+                    }
+                    write!(f, ":{}", tag)?;
+                }
+                f.write_str("}")
+            }
+            Tag::IntArray(values) => write_array(f, "I;", values.iter().map(i32::to_string)),
+            Tag::LongArray(values) => write_array(f, "L;", values.iter().map(|value| format!("{}L", value))),
+        }
+    }
+}
+
+fn write_array(f: &mut fmt::Formatter<'_>, prefix: &str, values: impl Iterator<Item = String>) -> fmt::Result {
+    write!(f, "[{}", prefix)?;
+    for (index, value) in values.enumerate() {
+        if index > 0 {
+            f.write_str(",")?;
+        }
+        f.write_str(&value)?;
+    }
+    f.write_str("]")
+}
+
+fn write_quoted(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(nbt: &Nbt) -> Nbt {
+        let mut bytes = Vec::new();
+        Nbt::write(&mut bytes, nbt).unwrap();
+        let mut slice = bytes.as_slice();
+        let read = Nbt::read(&mut slice).unwrap();
+        assert!(slice.is_empty());
+        read
+    }
+
+    /// A list of lists nested `depth` times around a byte.
+    fn nested_lists(depth: usize) -> Vec<u8> {
+        let mut bytes = vec![LIST];
+        bytes.extend_from_slice(&0u16.to_be_bytes());
+        for _ in 0..depth {
+            bytes.push(LIST);
+            bytes.extend_from_slice(&1i32.to_be_bytes());
+        }
+        bytes.push(BYTE);
+        bytes.extend_from_slice(&1i32.to_be_bytes());
+        bytes.push(7);
+        bytes
+    }
+
+    #[test]
+    fn nested_round_trip() {
+        let mut enchantment = Compound::new();
+        enchantment.insert("id", "minecraft:sharpness");
+        enchantment.insert("lvl", 5i16);
+        let mut enchantments = List::new();
+        enchantments.push(enchantment).unwrap();
+
+        let mut lore = List::new();
+        lore.push("{\"text\":\"héllo\"}").unwrap();
+        let mut display = Compound::new();
+        display.insert("Lore", lore);
+        display.insert("color", 0x00ff00);
+
+        let mut root = Compound::new();
+        root.insert("Enchantments", enchantments);
+        root.insert("display", display);
+        root.insert("Unbreakable", true);
+        root.insert("Damage", 3i64);
+        root.insert("Scale", 1.5f32);
+        root.insert("Speed", 0.25f64);
+        root.insert("Bytes", Tag::ByteArray(vec![-1, 0, 1]));
+        root.insert("Ints", Tag::IntArray(vec![i32::MIN, 0, i32::MAX]));
+        root.insert("Longs", Tag::LongArray(vec![i64::MIN, i64::MAX]));
+        root.insert("Empty", List::new());
+        let nbt = Nbt::new("tag", root);
+
+        assert_eq!(round_trip(&nbt), nbt);
+        assert_eq!(round_trip(&Nbt::default()), Nbt::default());
+    }
+
+    #[test]
+    fn depth_limit() {
+        assert!(Nbt::read(&mut nested_lists(MAX_NBT_DEPTH - 1).as_slice()).is_ok());
+        let error = Nbt::read(&mut nested_lists(MAX_NBT_DEPTH).as_slice()).unwrap_err();
+        assert_eq!(error, DecodeError::invalid_value("nbt is nested too deep"));
+    }
+
+    #[test]
+    fn size_limit() {
+        let mut bytes = vec![BYTE_ARRAY, 0, 0];
+        bytes.extend_from_slice(&(MAX_NBT_SIZE as i32).to_be_bytes());
+        bytes.resize(bytes.len() + MAX_NBT_SIZE, 0);
+        let error = Nbt::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error, DecodeError::invalid_value("nbt is too large"));
+
+        // a length that is larger than what was sent
+        let mut bytes = vec![INT_ARRAY, 0, 0];
+        bytes.extend_from_slice(&100i32.to_be_bytes());
+        assert_eq!(Nbt::read(&mut bytes.as_slice()).unwrap_err(), DecodeError::UnexpectedEnd);
+    }
+
+    #[test]
+    fn unwritable_values() {
+        let mut list = List::new();
+        list.push(1).unwrap();
+        assert!(list.push("two").is_err());
+
+        // edited in place into a list of different tags
+        list.push(2).unwrap();
+        *list.get_mut(0).unwrap() = Tag::String("one".to_string());
+        let mut root = Compound::new();
+        root.insert("list", list);
+        let mut bytes = Vec::new();
+        assert!(matches!(Nbt::write(&mut bytes, &Nbt::new("", root)), Err(EncodeError::InvalidValue(_))));
+
+        let mut root = Compound::new();
+        root.insert("long", "a".repeat(u16::MAX as usize + 1));
+        let mut bytes = Vec::new();
+        assert_eq!(Nbt::write(&mut bytes, &Nbt::new("", root)), Err(EncodeError::TooLong(u16::MAX as usize + 1)));
+    }
+}
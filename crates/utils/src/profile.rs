@@ -0,0 +1,15 @@
+/// Account of a player, as returned by the session server.
+#[derive(Clone, Debug)]
+pub struct GameProfile {
+    pub id: u128,
+    pub name: String,
+    pub properties: Vec<ProfileProperty>,
+}
+
+/// Extra data attached to a profile, like the skin in the `textures` property.
+#[derive(Clone, Debug)]
+pub struct ProfileProperty {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
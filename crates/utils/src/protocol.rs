@@ -0,0 +1,57 @@
+use std::fmt::Debug;
+
+use bytes::Buf;
+
+use crate::error::DecodeError;
+use crate::Packet;
+
+/// The packet ids of a protocol version.
+/// Packet structs carry the ids of the version they were generated for, this maps them to the ids of another one.
+pub struct ProtocolIds {
+    pub protocol_version: i32,
+    pub minecraft_version: &'static str,
+    /// Every packet of the version that is encoded like its packet struct.
+    /// Packets that changed between the versions aren't listed.
+    pub packets: &'static [PacketId],
+}
+
+pub struct PacketId {
+    pub state: u8,
+    pub inbound: bool,
+    /// Id of the packet in this version.
+    pub id: i32,
+    /// Id of the packet struct, [`Packet::get_id`].
+    pub struct_id: i32,
+}
+
+impl ProtocolIds {
+    /// Id of `P` in this version, `None` if the version doesn't have it or encodes it differently.
+    pub fn id_of<P: Packet>(&self) -> Option<i32> {
+        self.packets.iter()
+            .find(|packet| packet.state == P::get_state() && packet.inbound == P::is_inbound() && packet.struct_id == P::get_id())
+            .map(|packet| packet.id)
+    }
+}
+
+/// A packet struct, see `packets::PACKETS` for all of them.
+pub struct PacketInfo {
+    /// Name of the struct, without its module.
+    pub name: &'static str,
+    pub state: u8,
+    pub inbound: bool,
+    /// Id of the packet struct, [`Packet::get_id`].
+    pub id: i32,
+    pub read: ReadPacket,
+}
+
+pub type ReadPacket = fn(&mut dyn Buf) -> Result<Box<dyn DebugPacket>, DecodeError>;
+
+/// A packet that can be printed, which all packet structs can.
+pub trait DebugPacket: Packet + Debug {}
+
+impl<P: Packet + Debug> DebugPacket for P {}
+
+/// Reads a `P`, it is what [`PacketInfo::read`] points to.
+pub fn read_packet<P: Packet + Debug>(buffer: &mut dyn Buf) -> Result<Box<dyn DebugPacket>, DecodeError> {
+    Ok(Box::new(P::read(buffer)?))
+}
@@ -1,17 +1,82 @@
 use bytes::{BufMut, Buf};
 use crate::buffers::{VarInts, VarIntsMut, Strings, StringsMut, Bools, BoolsMut};
 use crate::set_vec_len;
+use std::convert::TryInto;
+use std::fmt;
 use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+use crate::error::{DecodeError, EncodeError};
 use crate::indexed_vec::IndexedVec;
+use crate::nbt::MAX_NBT_DEPTH;
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Vari32 {
     pub val: i32
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Vari64 {
+    pub val: i64
+}
+
+/// An enum value sent as a single signed byte, like game modes where -1 means none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteEnum {
+    pub val: i32
+}
+
+/// A block position, packed into a long as 26 bits of x, 26 bits of z and 12 bits of y.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// A rotation in steps of 1/256 of a full turn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Angle {
+    pub val: u8
+}
+
+/// A uuid, displayed with hyphens like `069a79f4-44e9-4726-a5be-fca90e38aaf5`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Uuid(pub u128);
+
+/// A namespaced id like `minecraft:stone`, the namespace is `minecraft` when it is left out.
+/// It is kept as it was sent.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Identifier {
+    pub val: String
+}
+
+#[derive(Debug)]
 pub struct InferLenVec {
     pub inner: IndexedVec<u8>
 }
 
+/// A complete nbt tag (type, name and payload) kept as raw bytes.
+/// The tag is only walked to find where it ends, a TAG_End means there is no tag.
+/// Use [`crate::nbt::Nbt`] to look into it.
+#[derive(Debug)]
+pub struct RawNbt {
+    pub inner: IndexedVec<u8>
+}
+
+impl Deref for RawNbt {
+    type Target = IndexedVec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for RawNbt {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
 impl Deref for InferLenVec {
     type Target = IndexedVec<u8>;
 
@@ -26,140 +91,646 @@ impl DerefMut for InferLenVec {
     }
 }
 
-pub trait Sendable {
-    fn read(buffer: &mut dyn Buf) -> Self;
-    fn write(buffer: &mut dyn BufMut, data: &Self);
+impl From<i32> for Vari32 {
+    fn from(val: i32) -> Self {
+        Vari32 { val }
+    }
+}
+
+impl From<Vari32> for i32 {
+    fn from(varint: Vari32) -> Self {
+        varint.val
+    }
+}
+
+impl From<i64> for Vari64 {
+    fn from(val: i64) -> Self {
+        Vari64 { val }
+    }
+}
+
+impl From<Vari64> for i64 {
+    fn from(varlong: Vari64) -> Self {
+        varlong.val
+    }
+}
+
+impl From<i32> for ByteEnum {
+    fn from(val: i32) -> Self {
+        ByteEnum { val }
+    }
+}
+
+impl From<ByteEnum> for i32 {
+    fn from(byte_enum: ByteEnum) -> Self {
+        byte_enum.val
+    }
+}
+
+impl Position {
+    pub fn new(x: i32, y: i32, z: i32) -> Position {
+        Position { x, y, z }
+    }
+}
+
+impl From<(i32, i32, i32)> for Position {
+    fn from((x, y, z): (i32, i32, i32)) -> Self {
+        Position { x, y, z }
+    }
+}
+
+impl From<Position> for (i32, i32, i32) {
+    fn from(position: Position) -> Self {
+        (position.x, position.y, position.z)
+    }
+}
+
+impl Angle {
+    /// The closest angle to a rotation in degrees.
+    pub fn from_degrees(degrees: f32) -> Angle {
+        Angle { val: (degrees.rem_euclid(360.0) / 360.0 * 256.0).round() as i32 as u8 }
+    }
+
+    pub fn degrees(&self) -> f32 {
+        self.val as f32 * 360.0 / 256.0
+    }
+}
+
+impl From<Angle> for f32 {
+    fn from(angle: Angle) -> Self {
+        angle.degrees()
+    }
+}
+
+impl From<u128> for Uuid {
+    fn from(uuid: u128) -> Self {
+        Uuid(uuid)
+    }
+}
+
+impl From<Uuid> for u128 {
+    fn from(uuid: Uuid) -> Self {
+        uuid.0
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = format!("{:032x}", self.0);
+        write!(f, "{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+}
+
+/// Parses uuids with or without hyphens.
+impl FromStr for Uuid {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(uuid: &str) -> Result<Self, Self::Err> {
+        u128::from_str_radix(&uuid.replace('-', ""), 16).map(Uuid)
+    }
+}
+
+impl Identifier {
+    pub fn new(namespace: &str, path: &str) -> Identifier {
+        Identifier { val: format!("{}:{}", namespace, path) }
+    }
+
+    pub fn namespace(&self) -> &str {
+        match self.val.split_once(':') {
+            Some((namespace, _)) => namespace,
+            None => "minecraft"
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self.val.split_once(':') {
+            Some((_, path)) => path,
+            None => &self.val
+        }
+    }
+}
+
+impl From<&str> for Identifier {
+    fn from(val: &str) -> Self {
+        Identifier { val: val.to_string() }
+    }
+}
+
+impl From<String> for Identifier {
+    fn from(val: String) -> Self {
+        Identifier { val }
+    }
+}
+
+impl From<Identifier> for String {
+    fn from(identifier: Identifier) -> Self {
+        identifier.val
+    }
+}
+
+/// Compares the namespaced form, `stone` equals `minecraft:stone`.
+impl PartialEq<str> for Identifier {
+    fn eq(&self, other: &str) -> bool {
+        let other = match other.split_once(':') {
+            Some(split) => split,
+            None => ("minecraft", other)
+        };
+        (self.namespace(), self.path()) == other
+    }
+}
+
+impl PartialEq<&str> for Identifier {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.val)
+    }
+}
+
+pub trait Sendable: Sized {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError>;
+    /// Fails if the value was edited into something that can't be sent.
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError>;
+}
+
+/// How the length of an array is sent, picked with `#[packet(prefix = Short)]` on a packet field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Prefix {
+    VarInt,
+    /// An unsigned byte.
+    Byte,
+    Short,
+    Int,
+    /// The array takes the rest of the packet.
+    None,
+}
+
+/// Arrays that are sent with another length prefix than their [`Sendable`] impl uses.
+pub trait Prefixed: Sized {
+    fn read_prefixed(buffer: &mut dyn Buf, prefix: Prefix) -> Result<Self, DecodeError>;
+    fn write_prefixed(buffer: &mut dyn BufMut, data: &Self, prefix: Prefix) -> Result<(), EncodeError>;
+}
+
+impl Prefix {
+    /// `None` when the array takes the rest of the buffer.
+    fn read_len(self, mut buffer: &mut dyn Buf) -> Result<Option<usize>, DecodeError> {
+        let len = match self {
+            Prefix::VarInt => buffer.get_var_i32()?.0,
+            Prefix::Byte => buffer.try_get_u8()? as i32,
+            Prefix::Short => buffer.try_get_i16()? as i32,
+            Prefix::Int => buffer.try_get_i32()?,
+            Prefix::None => return Ok(None),
+        };
+        if len < 0 {
+            return Err(DecodeError::InvalidLength(len as i64));
+        }
+        Ok(Some(len as usize))
+    }
+
+    fn write_len(self, mut buffer: &mut dyn BufMut, len: usize) -> Result<(), EncodeError> {
+        let max = match self {
+            Prefix::Byte => u8::MAX as usize,
+            Prefix::Short => i16::MAX as usize,
+            Prefix::VarInt | Prefix::Int => i32::MAX as usize,
+            Prefix::None => usize::MAX,
+        };
+        if len > max {
+            return Err(EncodeError::TooLong(len));
+        }
+        match self {
+            Prefix::VarInt => buffer.put_var_i32(len as i32),
+            Prefix::Byte => buffer.put_u8(len as u8),
+            Prefix::Short => buffer.put_i16(len as i16),
+            Prefix::Int => buffer.put_i32(len as i32),
+            Prefix::None => {}
+        }
+        Ok(())
+    }
 }
 
 impl Sendable for Vari32 {
-    fn read(mut buffer: &mut dyn Buf) -> Self {
-        Vari32 { val: buffer.get_var_i32().0 }
+    fn read(mut buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(Vari32 { val: buffer.get_var_i32()?.0 })
+    }
+
+    fn write(mut buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_var_i32(data.val);
+        Ok(())
+    }
+}
+
+impl Sendable for Vari64 {
+    fn read(mut buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(Vari64 { val: buffer.get_var_i64()?.0 })
+    }
+
+    fn write(mut buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_var_i64(data.val);
+        Ok(())
+    }
+}
+
+impl Sendable for ByteEnum {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(ByteEnum { val: buffer.try_get_i8()? as i32 })
     }
 
-    fn write(mut buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_var_i32(data.val)
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_i8(data.val as i8);
+        Ok(())
+    }
+}
+
+impl Sendable for Position {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        let packed = buffer.try_get_i64()?;
+        // arithmetic shifts keep the sign
+        Ok(Position {
+            x: (packed >> 38) as i32,
+            y: (packed << 52 >> 52) as i32,
+            z: (packed << 26 >> 38) as i32,
+        })
+    }
+
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        let packed = (data.x as i64 & 0x3FFFFFF) << 38 | (data.z as i64 & 0x3FFFFFF) << 12 | (data.y as i64 & 0xFFF);
+        buffer.put_i64(packed);
+        Ok(())
+    }
+}
+
+impl Sendable for Angle {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(Angle { val: buffer.try_get_u8()? })
+    }
+
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_u8(data.val);
+        Ok(())
+    }
+}
+
+impl Sendable for Uuid {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(Uuid(buffer.try_get_u128()?))
+    }
+
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_u128(data.0);
+        Ok(())
+    }
+}
+
+impl Sendable for Identifier {
+    fn read(mut buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(Identifier { val: buffer.get_string()? })
+    }
+
+    fn write(mut buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_string(&data.val);
+        Ok(())
     }
 }
 
 impl Sendable for i32 {
-    fn read(buffer: &mut dyn Buf) -> Self {
-        buffer.get_i32()
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(buffer.try_get_i32()?)
     }
 
-    fn write(buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_i32(*data)
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_i32(*data);
+        Ok(())
+    }
+}
+
+impl Sendable for u8 {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(buffer.try_get_u8()?)
+    }
+
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_u8(*data);
+        Ok(())
+    }
+}
+
+impl Sendable for i8 {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(buffer.try_get_i8()?)
+    }
+
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_i8(*data);
+        Ok(())
     }
 }
 
 impl Sendable for u16 {
-    fn read(buffer: &mut dyn Buf) -> Self {
-        buffer.get_u16()
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(buffer.try_get_u16()?)
     }
 
-    fn write(buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_u16(*data)
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_u16(*data);
+        Ok(())
     }
 }
 
 impl Sendable for u128 {
-    fn read(buffer: &mut dyn Buf) -> Self {
-        buffer.get_u128()
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(buffer.try_get_u128()?)
     }
 
-    fn write(buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_u128(*data)
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_u128(*data);
+        Ok(())
     }
 }
 
 impl Sendable for i16 {
-    fn read(buffer: &mut dyn Buf) -> Self {
-        buffer.get_i16()
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(buffer.try_get_i16()?)
     }
 
-    fn write(buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_i16(*data)
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_i16(*data);
+        Ok(())
     }
 }
 
 impl Sendable for bool {
-    fn read(mut buffer: &mut dyn Buf) -> Self {
+    fn read(mut buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
         buffer.get_bool()
     }
 
-    fn write(mut buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_bool(*data)
+    fn write(mut buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_bool(*data);
+        Ok(())
+    }
+}
+
+impl Sendable for f32 {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(buffer.try_get_f32()?)
+    }
+
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_f32(*data);
+        Ok(())
     }
 }
 
 impl Sendable for f64 {
-    fn read(buffer: &mut dyn Buf) -> Self {
-        buffer.get_f64()
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(buffer.try_get_f64()?)
     }
 
-    fn write(buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_f64(*data)
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_f64(*data);
+        Ok(())
     }
 }
 
 impl Sendable for u64 {
-    fn read(buffer: &mut dyn Buf) -> Self {
-        buffer.get_u64()
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(buffer.try_get_u64()?)
     }
 
-    fn write(buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_u64(*data)
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_u64(*data);
+        Ok(())
     }
 }
 
 impl Sendable for i64 {
-    fn read(buffer: &mut dyn Buf) -> Self {
-        buffer.get_i64()
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Ok(buffer.try_get_i64()?)
     }
 
-    fn write(buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_i64(*data)
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_i64(*data);
+        Ok(())
     }
 }
 
 impl Sendable for String {
-    fn read(mut buffer: &mut dyn Buf) -> Self {
+    fn read(mut buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
         buffer.get_string()
     }
 
-    fn write(mut buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_string(data)
+    fn write(mut buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_string(data);
+        Ok(())
     }
 }
 
+/// VarInt prefixed bytes.
 impl Sendable for IndexedVec<u8> {
-    fn read(mut buffer: &mut dyn Buf) -> Self {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Prefixed::read_prefixed(buffer, Prefix::VarInt)
+    }
+
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        Prefixed::write_prefixed(buffer, data, Prefix::VarInt)
+    }
+}
+
+impl Sendable for InferLenVec {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
         let mut vec = Vec::new();
-        let len = buffer.get_var_i32().0 as usize;
+        let len = buffer.remaining();
         set_vec_len(&mut vec, len);
         buffer.copy_to_slice(&mut vec);
-        let mut buf = IndexedVec::from_vec(vec);
+        let mut buf = InferLenVec { inner: IndexedVec::from_vec(vec) };
         buf.set_writer_index(len);
-        buf
+        Ok(buf)
     }
 
-    fn write(mut buffer: &mut dyn BufMut, data: &Self) {
-        buffer.put_var_i32(data.remaining() as i32);
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
         buffer.put_slice(data.as_slice());
+        Ok(())
     }
 }
 
-impl Sendable for InferLenVec {
-    fn read(buffer: &mut dyn Buf) -> Self {
+/// VarInt prefixed array.
+impl<T: Sendable> Sendable for Vec<T> {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        Prefixed::read_prefixed(buffer, Prefix::VarInt)
+    }
+
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        Prefixed::write_prefixed(buffer, data, Prefix::VarInt)
+    }
+}
+
+impl<T: Sendable> Prefixed for Vec<T> {
+    fn read_prefixed(buffer: &mut dyn Buf, prefix: Prefix) -> Result<Self, DecodeError> {
+        match prefix.read_len(buffer)? {
+            Some(len) => {
+                // don't trust the length for the allocation, every element is at least a byte
+                let mut vec = Vec::with_capacity(len.min(buffer.remaining()));
+                for _ in 0..len {
+                    vec.push(T::read(buffer)?);
+                }
+                Ok(vec)
+            }
+            None => {
+                let mut vec = Vec::new();
+                while buffer.has_remaining() {
+                    vec.push(T::read(buffer)?);
+                }
+                Ok(vec)
+            }
+        }
+    }
+
+    fn write_prefixed(buffer: &mut dyn BufMut, data: &Self, prefix: Prefix) -> Result<(), EncodeError> {
+        prefix.write_len(buffer, data.len())?;
+        for element in data.iter() {
+            T::write(buffer, element)?;
+        }
+        Ok(())
+    }
+}
+
+impl Prefixed for IndexedVec<u8> {
+    fn read_prefixed(buffer: &mut dyn Buf, prefix: Prefix) -> Result<Self, DecodeError> {
+        let len = prefix.read_len(buffer)?.unwrap_or_else(|| buffer.remaining());
+        // checked before allocating
+        if len > buffer.remaining() {
+            return Err(DecodeError::InvalidLength(len as i64));
+        }
         let mut vec = Vec::new();
-        let len = buffer.remaining();
         set_vec_len(&mut vec, len);
         buffer.copy_to_slice(&mut vec);
-        let mut buf = InferLenVec { inner: IndexedVec::from_vec(vec) };
+        let mut buf = IndexedVec::from_vec(vec);
+        buf.set_writer_index(len);
+        Ok(buf)
+    }
+
+    fn write_prefixed(buffer: &mut dyn BufMut, data: &Self, prefix: Prefix) -> Result<(), EncodeError> {
+        prefix.write_len(buffer, data.readable_bytes())?;
+        buffer.put_slice(data.as_slice());
+        Ok(())
+    }
+}
+
+/// Bool prefixed optional value.
+impl<T: Sendable> Sendable for Option<T> {
+    fn read(mut buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        if buffer.get_bool()? {
+            Ok(Some(T::read(buffer)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn write(mut buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        buffer.put_bool(data.is_some());
+        if let Some(value) = data {
+            T::write(buffer, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fixed size array, the length isn't sent.
+impl<T: Sendable, const N: usize> Sendable for [T; N] {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        let mut elements = Vec::with_capacity(N);
+        for _ in 0..N {
+            elements.push(T::read(buffer)?);
+        }
+        // the vec has exactly N elements
+        Ok(elements.try_into().ok().unwrap())
+    }
+
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        for element in data.iter() {
+            T::write(buffer, element)?;
+        }
+        Ok(())
+    }
+}
+
+impl Sendable for RawNbt {
+    fn read(buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        let mut vec = Vec::new();
+        let tag = copy_nbt_bytes(buffer, &mut vec, 1)?[0];
+        if tag != 0 {
+            // name
+            let name_len = u16::from_be_bytes(copy_nbt_bytes(buffer, &mut vec, 2)?.try_into().unwrap());
+            copy_nbt_bytes(buffer, &mut vec, name_len as usize)?;
+            copy_nbt_payload(buffer, &mut vec, tag, 0)?;
+        }
+        let len = vec.len();
+        let mut buf = RawNbt { inner: IndexedVec::from_vec(vec) };
         buf.set_writer_index(len);
-        buf
+        Ok(buf)
     }
 
-    fn write(buffer: &mut dyn BufMut, data: &Self) {
+    fn write(buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
         buffer.put_slice(data.as_slice());
+        Ok(())
+    }
+}
+
+/// Moves `len` bytes from the buffer to the end of `out` and returns them.
+fn copy_nbt_bytes<'a>(buffer: &mut dyn Buf, out: &'a mut Vec<u8>, len: usize) -> Result<&'a [u8], DecodeError> {
+    // checked before allocating
+    if len > buffer.remaining() {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let start = out.len();
+    out.resize(start + len, 0);
+    buffer.copy_to_slice(&mut out[start..]);
+    Ok(&out[start..])
+}
+
+fn copy_nbt_payload(buffer: &mut dyn Buf, out: &mut Vec<u8>, tag: u8, depth: usize) -> Result<(), DecodeError> {
+    if depth > MAX_NBT_DEPTH {
+        return Err(DecodeError::invalid_value("nbt is nested too deep"));
+    }
+    match tag {
+        // byte, short, int, long, float, double
+        1 => { copy_nbt_bytes(buffer, out, 1)?; }
+        2 => { copy_nbt_bytes(buffer, out, 2)?; }
+        3 | 5 => { copy_nbt_bytes(buffer, out, 4)?; }
+        4 | 6 => { copy_nbt_bytes(buffer, out, 8)?; }
+        // byte, int and long arrays
+        7 | 11 | 12 => {
+            let len = i32::from_be_bytes(copy_nbt_bytes(buffer, out, 4)?.try_into().unwrap()).max(0) as usize;
+            let element_size = match tag { 7 => 1, 11 => 4, _ => 8 };
+            copy_nbt_bytes(buffer, out, len * element_size)?;
+        }
+        // string
+        8 => {
+            let len = u16::from_be_bytes(copy_nbt_bytes(buffer, out, 2)?.try_into().unwrap());
+            copy_nbt_bytes(buffer, out, len as usize)?;
+        }
+        // list
+        9 => {
+            let element_tag = copy_nbt_bytes(buffer, out, 1)?[0];
+            let len = i32::from_be_bytes(copy_nbt_bytes(buffer, out, 4)?.try_into().unwrap()).max(0);
+            for _ in 0..len {
+                copy_nbt_payload(buffer, out, element_tag, depth + 1)?;
+            }
+        }
+        // compound
+        10 => loop {
+            let element_tag = copy_nbt_bytes(buffer, out, 1)?[0];
+            if element_tag == 0 {
+                break;
+            }
+            let name_len = u16::from_be_bytes(copy_nbt_bytes(buffer, out, 2)?.try_into().unwrap());
+            copy_nbt_bytes(buffer, out, name_len as usize)?;
+            copy_nbt_payload(buffer, out, element_tag, depth + 1)?;
+        }
+        _ => return Err(DecodeError::invalid_value(format!("unknown nbt tag {}", tag)))
     }
-}
\ No newline at end of file
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! Item stacks, as inventories and windows send them.
+
+use bytes::{Buf, BufMut};
+
+use crate::buffers::{Bools, BoolsMut, VarInts, VarIntsMut};
+use crate::error::{DecodeError, EncodeError};
+use crate::nbt::{Compound, Nbt};
+use crate::sendable::Sendable;
+
+/// A slot of an inventory, `item` is `None` for empty slots.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Slot {
+    pub item: Option<ItemStack>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemStack {
+    /// Id of the item in the item registry.
+    pub id: i32,
+    pub count: i8,
+    /// Enchantments, names, damage and the like, without a tag for items that have none.
+    pub nbt: Nbt,
+}
+
+impl Slot {
+    pub fn empty() -> Slot {
+        Slot { item: None }
+    }
+
+    pub fn new(item: ItemStack) -> Slot {
+        Slot { item: Some(item) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.item.is_none()
+    }
+}
+
+impl ItemStack {
+    pub fn new(id: i32, count: i8) -> ItemStack {
+        ItemStack { id, count, nbt: Nbt::default() }
+    }
+
+    /// The compound holding the item's data.
+    pub fn tag(&self) -> Option<&Compound> {
+        self.nbt.root()
+    }
+
+    /// The compound holding the item's data, it is added if the item has none.
+    pub fn tag_mut(&mut self) -> &mut Compound {
+        if self.nbt.root().is_none() {
+            self.nbt = Nbt::new("", Compound::new());
+        }
+        self.nbt.root_mut().unwrap()
+    }
+}
+
+impl Sendable for Slot {
+    fn read(mut buffer: &mut dyn Buf) -> Result<Self, DecodeError> {
+        if !buffer.get_bool()? {
+            return Ok(Slot::empty());
+        }
+        let id = buffer.get_var_i32()?.0;
+        let count = buffer.try_get_i8()?;
+        let nbt = Nbt::read(buffer)?;
+        Ok(Slot::new(ItemStack { id, count, nbt }))
+    }
+
+    fn write(mut buffer: &mut dyn BufMut, data: &Self) -> Result<(), EncodeError> {
+        match &data.item {
+            Some(item) => {
+                buffer.put_bool(true);
+                buffer.put_var_i32(item.id);
+                buffer.put_i8(item.count);
+                Nbt::write(buffer, &item.nbt)?;
+            }
+            None => buffer.put_bool(false)
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nbt::List;
+
+    use super::*;
+
+    fn round_trip(slot: &Slot) -> Slot {
+        let mut bytes = Vec::new();
+        Slot::write(&mut bytes, slot).unwrap();
+        let mut slice = bytes.as_slice();
+        let read = Slot::read(&mut slice).unwrap();
+        assert!(slice.is_empty());
+        read
+    }
+
+    #[test]
+    fn empty_slot() {
+        let mut bytes = Vec::new();
+        Slot::write(&mut bytes, &Slot::empty()).unwrap();
+        assert_eq!(bytes, [0]);
+        assert_eq!(round_trip(&Slot::empty()), Slot::empty());
+    }
+
+    #[test]
+    fn item_without_nbt() {
+        let slot = Slot::new(ItemStack::new(1, 64));
+        assert_eq!(round_trip(&slot), slot);
+        assert!(round_trip(&slot).item.unwrap().tag().is_none());
+    }
+
+    #[test]
+    fn item_with_nbt() {
+        let mut item = ItemStack::new(603, 1);
+        let mut lore = List::new();
+        lore.push("{\"text\":\"Sharp\"}").unwrap();
+        let mut display = Compound::new();
+        display.insert("Lore", lore);
+        item.tag_mut().insert("display", display);
+        item.tag_mut().insert("Damage", 10);
+        let slot = Slot::new(item);
+
+        let read = round_trip(&slot);
+        assert_eq!(read, slot);
+        assert_eq!(read.item.unwrap().tag().unwrap().get_i64("Damage"), Some(10));
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use proxy::forwarding::Forwarding;
+
+/// Contents of `paxy.toml`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub forwarding: ForwardingConfig,
+    #[serde(default)]
+    pub status: StatusConfig,
+    #[serde(default)]
+    pub messages: MessagesConfig,
+    #[serde(default)]
+    pub features: FeaturesConfig,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Addresses players connect to.
+    pub listen: Vec<SocketAddr>,
+    /// Amount of network threads, defaults to twice the amount of cpus.
+    pub threads: Option<usize>,
+    /// Capacity of the message queue of every network thread.
+    pub channel_size: usize,
+    /// libdeflate level used when recompressing modified packets.
+    pub compression_level: i32,
+    /// How long a single attempt at connecting to the backend may take, in milliseconds.
+    pub connect_timeout_ms: u64,
+    /// How many times connecting to the backend is tried before the player is disconnected.
+    pub connect_attempts: u32,
+    /// Minecraft version the backends run, newer clients are translated to it.
+    pub backend_version: Option<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> NetworkConfig {
+        NetworkConfig {
+            listen: vec!["127.0.0.1:25566".parse().unwrap()],
+            threads: None,
+            channel_size: 1000,
+            compression_level: 1,
+            connect_timeout_ms: 5000,
+            connect_attempts: 3,
+            backend_version: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoutingConfig {
+    /// Backend used when the hostname has no forced host.
+    pub default: String,
+    /// Minecraft servers by name.
+    pub backends: BTreeMap<String, SocketAddr>,
+    /// Hostnames and the name of the backend they lead to.
+    pub forced_hosts: BTreeMap<String, String>,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> RoutingConfig {
+        let mut backends = BTreeMap::new();
+        backends.insert("lobby".to_string(), "127.0.0.1:25565".parse().unwrap());
+        RoutingConfig {
+            default: "lobby".to_string(),
+            backends,
+            forced_hosts: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Authenticate players on the proxy, the backends should run in offline mode then.
+    pub online_mode: bool,
+    /// Session server players are verified with.
+    pub session_server: String,
+}
+
+impl Default for AuthConfig {
+    fn default() -> AuthConfig {
+        AuthConfig {
+            online_mode: false,
+            session_server: "https://sessionserver.mojang.com".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForwardingConfig {
+    /// How the backends learn the address and profile of players.
+    pub mode: ForwardingMode,
+    /// Secret shared with the backends, required by modern forwarding.
+    pub secret: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ForwardingMode {
+    #[default]
+    None,
+    /// BungeeCord's format, for Spigot and Paper with `bungeecord: true`.
+    Legacy,
+    /// Velocity's format, for Paper with `velocity-support` enabled.
+    Modern,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusConfig {
+    /// Forward server list pings to the backend instead of answering them on the proxy.
+    pub passthrough: bool,
+    /// Description shown in the server list, a plain string or a chat component.
+    pub motd: serde_json::Value,
+    pub max_players: u32,
+    /// 64x64 png shown in the server list.
+    pub favicon: Option<PathBuf>,
+}
+
+impl Default for StatusConfig {
+    fn default() -> StatusConfig {
+        StatusConfig {
+            passthrough: false,
+            motd: serde_json::Value::String("A Paxy proxy".to_string()),
+            max_players: 100,
+            favicon: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessagesConfig {
+    /// Sent to players when the backend can't be reached.
+    pub backend_unavailable: String,
+    /// Sent to every player when the proxy shuts down.
+    pub shutdown: String,
+}
+
+impl Default for MessagesConfig {
+    fn default() -> MessagesConfig {
+        MessagesConfig {
+            backend_unavailable: "Could not connect to the server".to_string(),
+            shutdown: "Proxy is shutting down".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    pub brand_rewrite: bool,
+    pub server_command: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> FeaturesConfig {
+        let features = proxy::Features::default();
+        FeaturesConfig {
+            brand_rewrite: features.brand_rewrite,
+            server_command: features.server_command,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// A field has a value that parsed fine but can't be used.
+    Invalid { field: &'static str, reason: String },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "could not parse {}: {}", path.display(), e),
+            ConfigError::Invalid { field, reason } => write!(f, "invalid value for `{}`: {}", field, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Loads the config at `path`, falling back to the defaults if `required` is false and the file doesn't exist.
+    pub fn load(path: &Path, required: bool) -> Result<Config, ConfigError> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                println!("{} not found, using the default config", path.display());
+                String::new()
+            }
+            Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+        };
+        let config: Config = toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let network = &self.network;
+        if network.listen.is_empty() {
+            return Err(invalid("network.listen", "at least one address is required"));
+        }
+        if network.threads == Some(0) {
+            return Err(invalid("network.threads", "must be at least 1"));
+        }
+        if network.channel_size == 0 {
+            return Err(invalid("network.channel_size", "must be at least 1"));
+        }
+        if !(0..=12).contains(&network.compression_level) {
+            return Err(invalid("network.compression_level", format!("{} is not between 0 and 12", network.compression_level)));
+        }
+        if network.connect_timeout_ms == 0 {
+            return Err(invalid("network.connect_timeout_ms", "must be at least 1"));
+        }
+        if network.connect_attempts == 0 {
+            return Err(invalid("network.connect_attempts", "must be at least 1"));
+        }
+        if let Some(backend_version) = &network.backend_version {
+            if !cfg!(feature = "translation") {
+                return Err(invalid("network.backend_version", "paxy has to be built with the translation feature"));
+            }
+            if !proxy::backend_versions().any(|version| version == backend_version) {
+                let versions: Vec<&str> = proxy::backend_versions().collect();
+                return Err(invalid("network.backend_version", format!("{} is not supported, expected one of {}", backend_version, versions.join(", "))));
+            }
+        }
+
+        let routing = &self.routing;
+        if !routing.backends.contains_key(&routing.default) {
+            return Err(invalid("routing.default", format!("there is no backend called `{}`", routing.default)));
+        }
+        for (hostname, backend) in routing.forced_hosts.iter() {
+            if !routing.backends.contains_key(backend) {
+                return Err(invalid("routing.forced_hosts", format!("`{}` leads to `{}`, but there is no backend called that", hostname, backend)));
+            }
+        }
+
+        if let ForwardingMode::Modern = self.forwarding.mode {
+            if self.forwarding.secret.is_empty() {
+                return Err(invalid("forwarding.secret", "modern forwarding needs a secret"));
+            }
+        }
+
+        let status = &self.status;
+        if !is_component(&status.motd) {
+            return Err(invalid("status.motd", "must be a string or a chat component"));
+        }
+        if let Some(favicon) = &status.favicon {
+            if let Err(e) = proxy::load_favicon(favicon) {
+                return Err(invalid("status.favicon", e.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn builder(&self) -> proxy::ProxyBuilder {
+        let network = &self.network;
+        let mut builder = proxy::ProxyBuilder::new(network.listen[0]);
+        for address in network.listen[1..].iter() {
+            builder = builder.listen(*address);
+        }
+
+        let routing = &self.routing;
+        for (name, address) in routing.backends.iter() {
+            builder = builder.backend(name.as_str(), *address);
+        }
+        builder = builder.default_backend(routing.default.as_str());
+        for (hostname, backend) in routing.forced_hosts.iter() {
+            builder = builder.forced_host(hostname, backend.as_str());
+        }
+        if let Some(threads) = network.threads {
+            builder = builder.threads(threads);
+        }
+        if let Some(backend_version) = &network.backend_version {
+            builder = builder.backend_version(backend_version.as_str());
+        }
+        if let Some(favicon) = &self.status.favicon {
+            builder = builder.favicon(favicon);
+        }
+        builder
+            .channel_size(network.channel_size)
+            .compression_level(network.compression_level)
+            .connect_timeout(Duration::from_millis(network.connect_timeout_ms))
+            .connect_attempts(network.connect_attempts)
+            .online_mode(self.auth.online_mode)
+            .session_verifier(proxy::auth::MojangSessionVerifier::new(self.auth.session_server.as_str()))
+            .forwarding(match self.forwarding.mode {
+                ForwardingMode::None => Forwarding::None,
+                ForwardingMode::Legacy => Forwarding::Legacy,
+                ForwardingMode::Modern => Forwarding::Modern { secret: self.forwarding.secret.clone().into_bytes() },
+            })
+            .status_passthrough(self.status.passthrough)
+            .motd(self.status.motd.to_string())
+            .max_players(self.status.max_players)
+            .backend_unavailable_message(self.messages.backend_unavailable.as_str())
+            .shutdown_message(self.messages.shutdown.as_str())
+            .features(proxy::Features {
+                brand_rewrite: self.features.brand_rewrite,
+                server_command: self.features.server_command,
+            })
+    }
+}
+
+/// Strings and tables are components, arrays are lists of them.
+fn is_component(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(_) | serde_json::Value::Object(_) => true,
+        serde_json::Value::Array(components) => !components.is_empty() && components.iter().all(is_component),
+        _ => false
+    }
+}
+
+fn invalid(field: &'static str, reason: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid { field, reason: reason.into() }
+}
@@ -1,7 +1,24 @@
-use std::net::{SocketAddr, Ipv4Addr, IpAddr};
+use std::path::PathBuf;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let proxy_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 25566);
-    let server_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 25565);
-    proxy::start(proxy_address, server_address)
-}
\ No newline at end of file
+use crate::config::Config;
+
+mod config;
+
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // the config path can be passed as the first argument
+    let (path, required) = match std::env::args_os().nth(1) {
+        Some(path) => (PathBuf::from(path), true),
+        None => (PathBuf::from("paxy.toml"), false),
+    };
+    let config = match Config::load(&path, required) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let handle = config.builder().start()?;
+    handle.shutdown_on_signals()?;
+    handle.join()
+}